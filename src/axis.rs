@@ -0,0 +1,172 @@
+use serde::Deserialize;
+
+/// Shaping applied to a raw SDL axis value before it becomes a drone command.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AxisCurve {
+    /// fraction of the travel around center that reads as zero, 0.0..1.0
+    pub dead_zone: f32,
+    /// 0.0 is linear, 1.0 is fully cubic
+    pub expo: f32,
+    /// max output change per second, `None` for unlimited
+    pub rate: Option<f32>,
+}
+
+impl Default for AxisCurve {
+    fn default() -> Self {
+        Self {
+            dead_zone: 0.05,
+            expo: 0.0,
+            rate: None,
+        }
+    }
+}
+
+impl AxisCurve {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..1.0).contains(&self.dead_zone) {
+            return Err(format!("dead_zone {} not in 0.0..1.0", self.dead_zone));
+        }
+        if !(0.0..=1.0).contains(&self.expo) {
+            return Err(format!("expo {} not in 0.0..=1.0", self.expo));
+        }
+        if let Some(rate) = self.rate {
+            if rate <= 0.0 || !rate.is_finite() {
+                return Err(format!("rate {rate} must be positive"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps a raw SDL axis value to -1.0..=1.0.
+    /// The output starts at zero on the dead zone edge so there's no jump.
+    pub fn shape(&self, raw: i16) -> f32 {
+        let x = (raw as f32 / 32767.0).clamp(-1.0, 1.0);
+        let magnitude = x.abs();
+        if magnitude <= self.dead_zone {
+            return 0.0;
+        }
+        let n = (magnitude - self.dead_zone) / (1.0 - self.dead_zone);
+        let y = (1.0 - self.expo) * n + self.expo * n * n * n;
+        y.clamp(0.0, 1.0).copysign(x)
+    }
+}
+
+/// Moves an output towards its target by at most `rate` per second.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RateLimiter {
+    pub current: f32,
+    pub target: f32,
+}
+
+impl RateLimiter {
+    pub fn settled(&self) -> bool {
+        self.current == self.target
+    }
+
+    pub fn step(&mut self, rate: Option<f32>, dt: f32) -> f32 {
+        self.current = match rate {
+            None => self.target,
+            Some(rate) => {
+                let max_step = rate * dt;
+                self.current + (self.target - self.current).clamp(-max_step, max_step)
+            }
+        };
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(dead_zone: f32, expo: f32) -> AxisCurve {
+        AxisCurve {
+            dead_zone,
+            expo,
+            rate: None,
+        }
+    }
+
+    #[test]
+    fn dead_zone_edges() {
+        let c = curve(0.1, 0.0);
+        let edge = (0.1 * 32767.0) as i16;
+        assert_eq!(c.shape(0), 0.0);
+        assert_eq!(c.shape(edge), 0.0);
+        assert_eq!(c.shape(-edge), 0.0);
+        let just_out = c.shape(edge + 40);
+        assert!(just_out > 0.0 && just_out < 0.01, "{just_out}");
+    }
+
+    #[test]
+    fn sign_symmetry() {
+        for c in [curve(0.0, 0.0), curve(0.1, 0.5), curve(0.2, 1.0)] {
+            for raw in [1, 100, 5000, 16384, 30000, 32767] {
+                assert_eq!(c.shape(raw), -c.shape(-raw), "{c:?} {raw}");
+            }
+        }
+    }
+
+    #[test]
+    fn saturation() {
+        for c in [curve(0.0, 0.0), curve(0.1, 0.5), curve(0.3, 1.0)] {
+            assert_eq!(c.shape(i16::MAX), 1.0);
+            assert_eq!(c.shape(i16::MIN), -1.0);
+            assert_eq!(c.shape(-i16::MAX), -1.0);
+        }
+    }
+
+    #[test]
+    fn linear_without_dead_zone_and_expo() {
+        let c = curve(0.0, 0.0);
+        assert!((c.shape(16384) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn expo_softens_center() {
+        let linear = curve(0.0, 0.0);
+        let expo = curve(0.0, 0.6);
+        assert!(expo.shape(8000) < linear.shape(8000));
+        assert_eq!(expo.shape(i16::MAX), linear.shape(i16::MAX));
+    }
+
+    #[test]
+    fn monotonic() {
+        let c = curve(0.1, 0.7);
+        let mut last = c.shape(i16::MIN);
+        for raw in (i16::MIN..=i16::MAX).step_by(97) {
+            let y = c.shape(raw);
+            assert!(y >= last, "{raw}: {y} < {last}");
+            last = y;
+        }
+    }
+
+    #[test]
+    fn validation() {
+        assert!(AxisCurve::default().validate().is_ok());
+        assert!(curve(1.0, 0.0).validate().is_err());
+        assert!(curve(-0.1, 0.0).validate().is_err());
+        assert!(curve(0.1, 1.5).validate().is_err());
+        let c = AxisCurve {
+            rate: Some(0.0),
+            ..Default::default()
+        };
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn rate_limit() {
+        let mut r = RateLimiter {
+            target: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(r.step(Some(2.0), 0.25), 0.5);
+        assert!(!r.settled());
+        assert_eq!(r.step(Some(2.0), 0.25), 1.0);
+        assert!(r.settled());
+        r.target = -1.0;
+        assert_eq!(r.step(Some(2.0), 0.5), 0.0);
+        assert_eq!(r.step(None, 0.0), -1.0);
+    }
+}
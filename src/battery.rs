@@ -0,0 +1,265 @@
+use std::{collections::HashSet, fmt};
+
+use sdl2::controller::Button;
+use serde::Deserialize;
+
+/// Levels rise with falling charge and only go back once the battery is this much above warning.
+const RESET_MARGIN: i32 = 5;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatteryConfig {
+    /// percent, flashes the battery gauge and beeps
+    pub warning: i32,
+    /// percent, lands, the sticks can't climb then
+    pub critical: i32,
+    /// buttons held together to cancel the forced landing, SDL names
+    pub cancel: Vec<String>,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            warning: 30,
+            critical: 15,
+            cancel: vec!["leftstick".to_owned(), "rightstick".to_owned()],
+        }
+    }
+}
+
+impl BatteryConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0..=100).contains(&self.critical) || !(self.critical..=100).contains(&self.warning) {
+            return Err(format!(
+                "battery: need 0 <= critical ({}) <= warning ({}) <= 100",
+                self.critical, self.warning
+            ));
+        }
+        if self.cancel.is_empty() {
+            return Err("battery: cancel needs at least one button".to_owned());
+        }
+        for name in &self.cancel {
+            if Button::from_string(name).is_none() {
+                return Err(format!("battery: unknown button `{name}` in cancel"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BatteryLevel {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for BatteryLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatteryLevel::Normal => write!(f, "normal"),
+            BatteryLevel::Warning => write!(f, "low"),
+            BatteryLevel::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+/// Tracks the battery level and the forced landing it triggers.
+#[derive(Debug)]
+pub struct BatteryGuard {
+    config: BatteryConfig,
+    cancel: Vec<Button>,
+    level: BatteryLevel,
+    forced_landing: bool,
+}
+
+impl BatteryGuard {
+    pub fn new(config: BatteryConfig) -> Self {
+        let cancel = config
+            .cancel
+            .iter()
+            .filter_map(|name| Button::from_string(name))
+            .collect();
+        Self {
+            config,
+            cancel,
+            level: BatteryLevel::Normal,
+            forced_landing: false,
+        }
+    }
+
+    pub fn level(&self) -> BatteryLevel {
+        self.level
+    }
+
+    /// True while the sticks are overridden and the drone is brought down.
+    pub fn forced_landing(&self) -> bool {
+        self.forced_landing
+    }
+
+    /// Feeds a battery reading, returns the new level when it changed.
+    pub fn update(&mut self, percentage: i32) -> Option<BatteryLevel> {
+        let level = if percentage <= self.config.critical {
+            BatteryLevel::Critical
+        } else if percentage <= self.config.warning {
+            BatteryLevel::Warning
+        } else {
+            BatteryLevel::Normal
+        };
+        // readings jitter around the thresholds, only a fresh battery resets the level
+        let level = if level < self.level && percentage < self.config.warning + RESET_MARGIN {
+            self.level
+        } else {
+            level
+        };
+        if level == self.level {
+            return None;
+        }
+        match level {
+            BatteryLevel::Critical => {
+                tracing::warn!("battery critical at {percentage}%, forced landing");
+                self.forced_landing = true;
+            }
+            BatteryLevel::Warning => tracing::warn!("battery low at {percentage}%"),
+            BatteryLevel::Normal => {
+                tracing::info!("battery back to {percentage}%");
+                self.forced_landing = false;
+            }
+        }
+        self.level = level;
+        Some(level)
+    }
+
+    /// Gives the sticks back to the pilot once all cancel buttons are held, true when it did.
+    pub fn cancel(&mut self, held: &HashSet<Button>) -> bool {
+        if self.forced_landing && self.cancel.iter().all(|b| held.contains(b)) {
+            tracing::warn!("forced landing cancelled by the pilot");
+            self.forced_landing = false;
+            return true;
+        }
+        false
+    }
+
+    /// HUD line for the current level, empty when the battery is fine.
+    pub fn status(&self) -> String {
+        match self.level {
+            BatteryLevel::Normal => String::new(),
+            BatteryLevel::Warning => "BATTERY LOW".to_owned(),
+            BatteryLevel::Critical if self.forced_landing => {
+                format!("FORCED LANDING, {} cancels", self.config.cancel.join("+"))
+            }
+            BatteryLevel::Critical => "BATTERY CRITICAL".to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard() -> BatteryGuard {
+        BatteryGuard::new(BatteryConfig::default())
+    }
+
+    // the level changes after feeding `readings` in order
+    fn changes(guard: &mut BatteryGuard, readings: &[i32]) -> Vec<Option<BatteryLevel>> {
+        readings.iter().map(|&p| guard.update(p)).collect()
+    }
+
+    #[test]
+    fn levels_follow_the_thresholds() {
+        use BatteryLevel::*;
+        let mut guard = guard();
+        assert_eq!(
+            changes(&mut guard, &[100, 31, 30, 16, 15, 0]),
+            vec![None, None, Some(Warning), None, Some(Critical), None]
+        );
+        // straight to critical
+        let mut guard = self::guard();
+        assert_eq!(changes(&mut guard, &[10]), vec![Some(Critical)]);
+        assert!(guard.forced_landing());
+    }
+
+    #[test]
+    fn jitter_doesnt_reset_the_level() {
+        use BatteryLevel::*;
+        let mut guard = guard();
+        // up to warning + RESET_MARGIN the level stays
+        assert_eq!(
+            changes(&mut guard, &[30, 31, 34, 29, 15, 16, 25, 34]),
+            vec![
+                Some(Warning),
+                None,
+                None,
+                None,
+                Some(Critical),
+                None,
+                None,
+                None
+            ]
+        );
+        assert_eq!(guard.level(), Critical);
+        assert!(guard.forced_landing());
+        // a fresh battery
+        assert_eq!(guard.update(35), Some(Normal));
+        assert!(!guard.forced_landing());
+        assert_eq!(guard.status(), "");
+    }
+
+    #[test]
+    fn critical_fires_once() {
+        let mut guard = guard();
+        let critical = changes(&mut guard, &[15, 14, 15, 3, 12])
+            .into_iter()
+            .flatten()
+            .count();
+        assert_eq!(critical, 1);
+    }
+
+    #[test]
+    fn cancel_needs_every_button() {
+        let mut guard = guard();
+        let mut held = HashSet::from([Button::LeftStick]);
+        // nothing to cancel yet
+        assert!(!guard.cancel(&HashSet::from([Button::LeftStick, Button::RightStick])));
+
+        guard.update(10);
+        assert!(guard.status().starts_with("FORCED LANDING"));
+        assert!(!guard.cancel(&held));
+        assert!(guard.forced_landing());
+        held.insert(Button::RightStick);
+        assert!(guard.cancel(&held));
+        assert!(!guard.forced_landing());
+        assert_eq!(guard.status(), "BATTERY CRITICAL");
+        // cancelled for good, more critical readings don't take the sticks again
+        assert!(!guard.cancel(&held));
+        assert_eq!(guard.update(5), None);
+        assert!(!guard.forced_landing());
+    }
+
+    #[test]
+    fn new_critical_after_a_fresh_battery_lands_again() {
+        let mut guard = guard();
+        guard.update(10);
+        guard.cancel(&HashSet::from([Button::LeftStick, Button::RightStick]));
+        guard.update(90);
+        assert_eq!(guard.update(12), Some(BatteryLevel::Critical));
+        assert!(guard.forced_landing());
+    }
+
+    #[test]
+    fn validation() {
+        assert!(BatteryConfig::default().validate().is_ok());
+        let config = |warning, critical, cancel: &[&str]| BatteryConfig {
+            warning,
+            critical,
+            cancel: cancel.iter().map(|s| s.to_string()).collect(),
+        };
+        assert!(config(10, 20, &["a"]).validate().is_err());
+        assert!(config(101, 20, &["a"]).validate().is_err());
+        assert!(config(30, -1, &["a"]).validate().is_err());
+        assert!(config(30, 15, &[]).validate().is_err());
+        assert!(config(30, 15, &["nope"]).validate().is_err());
+        assert!(config(15, 15, &["a", "b"]).validate().is_ok());
+    }
+}
@@ -0,0 +1,98 @@
+use std::{fs, io, path::Path, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    battery::BatteryConfig,
+    failsafe::FailsafeConfig,
+    flip::FlipConfig,
+    geofence::GeofenceConfig,
+    hold::{AltitudeHoldConfig, HeadingHoldConfig},
+    script::ScriptConfig,
+    sdk::CommandConfig,
+    survey::SurveyConfig,
+    video_recorder::VideoConfig,
+};
+
+/// Settings read from the config file, command line options take precedence.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    pub pictures_dir: PathBuf,
+    pub input: PathBuf,
+    pub profile: Option<String>,
+    pub record: bool,
+    pub record_dir: PathBuf,
+    pub record_format: String,
+    pub battery: BatteryConfig,
+    pub failsafe: FailsafeConfig,
+    /// no fence without a `[geofence]` section
+    pub geofence: Option<GeofenceConfig>,
+    pub video: VideoConfig,
+    pub altitude_hold: AltitudeHoldConfig,
+    pub heading_hold: HeadingHoldConfig,
+    /// mission file, see doc/mission.toml
+    pub mission: Option<PathBuf>,
+    /// no survey without a `[survey]` section
+    pub survey: Option<SurveyConfig>,
+    pub script: ScriptConfig,
+    pub flip: FlipConfig,
+    pub commands: CommandConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            width: 3440,
+            height: 1440,
+            fullscreen: false,
+            pictures_dir: PathBuf::from("./save_pics"),
+            input: PathBuf::from("input.toml"),
+            profile: None,
+            record: true,
+            record_dir: PathBuf::from("./flight_logs"),
+            record_format: "jsonl".to_owned(),
+            battery: BatteryConfig::default(),
+            failsafe: FailsafeConfig::default(),
+            geofence: None,
+            video: VideoConfig::default(),
+            altitude_hold: AltitudeHoldConfig::default(),
+            heading_hold: HeadingHoldConfig::default(),
+            mission: None,
+            survey: None,
+            script: ScriptConfig::default(),
+            flip: FlipConfig::default(),
+            commands: CommandConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config from `path`, a missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                let config: Self =
+                    toml::from_str(&content).map_err(|e| format!("{}: {e}", path.display()))?;
+                config
+                    .battery
+                    .validate()
+                    .and_then(|_| config.failsafe.validate())
+                    .and_then(|_| config.altitude_hold.validate())
+                    .and_then(|_| config.heading_hold.validate())
+                    .and_then(|_| config.geofence.as_ref().map_or(Ok(()), |g| g.validate()))
+                    .and_then(|_| config.survey.as_ref().map_or(Ok(()), |s| s.validate()))
+                    .and_then(|_| config.script.validate())
+                    .and_then(|_| config.commands.validate())
+                    .and_then(|_| config.flip.validate())
+                    .map_err(|e| format!("{}: {e}", path.display()))?;
+                Ok(config)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {e}", path.display())),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use rust_tello::TelloController;
+
+/// Direction of a flip, as seen from the drone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flip {
+    Forward,
+    Back,
+    Left,
+    Right,
+}
+
+impl Flip {
+    pub fn label(self) -> &'static str {
+        match self {
+            Flip::Forward => "forward",
+            Flip::Back => "back",
+            Flip::Left => "left",
+            Flip::Right => "right",
+        }
+    }
+}
+
+/// Drone calls the UI makes, implemented by the real `TelloController` and by the simulator.
+pub trait Drone {
+    fn takeoff(&mut self);
+    fn land(&mut self);
+    /// motors on, the drone takes off when thrown
+    fn throw_takeoff(&mut self);
+    /// lands on the palm held under the drone
+    fn palm_land(&mut self);
+    fn hover(&mut self);
+    fn flying(&mut self) -> bool;
+    fn take_picture(&mut self);
+    fn toggle_video(&mut self);
+    fn forward(&mut self, speed: f32);
+    fn right(&mut self, speed: f32);
+    fn up(&mut self, speed: f32);
+    fn turn_clockwise(&mut self, speed: f32);
+    fn flip(&mut self, flip: Flip);
+}
+
+/// Stands in for the drone in replays, nothing is sent anywhere.
+pub struct NoDrone;
+
+impl Drone for NoDrone {
+    fn takeoff(&mut self) {}
+    fn land(&mut self) {}
+    fn throw_takeoff(&mut self) {}
+    fn palm_land(&mut self) {}
+    fn hover(&mut self) {}
+
+    fn flying(&mut self) -> bool {
+        false
+    }
+
+    fn take_picture(&mut self) {}
+    fn toggle_video(&mut self) {}
+    fn forward(&mut self, _speed: f32) {}
+    fn right(&mut self, _speed: f32) {}
+    fn up(&mut self, _speed: f32) {}
+    fn turn_clockwise(&mut self, _speed: f32) {}
+    fn flip(&mut self, _flip: Flip) {}
+}
+
+impl Drone for TelloController {
+    fn takeoff(&mut self) {
+        TelloController::takeoff(self)
+    }
+
+    fn land(&mut self) {
+        TelloController::land(self)
+    }
+
+    fn throw_takeoff(&mut self) {
+        TelloController::throw_takeoff(self)
+    }
+
+    fn palm_land(&mut self) {
+        TelloController::palm_land(self)
+    }
+
+    fn hover(&mut self) {
+        TelloController::hover(self)
+    }
+
+    fn flying(&mut self) -> bool {
+        TelloController::flying(self)
+    }
+
+    fn take_picture(&mut self) {
+        TelloController::take_picture(self)
+    }
+
+    fn toggle_video(&mut self) {
+        TelloController::toggle_video(self)
+    }
+
+    fn forward(&mut self, speed: f32) {
+        TelloController::forward(self, speed)
+    }
+
+    fn right(&mut self, speed: f32) {
+        TelloController::right(self, speed)
+    }
+
+    fn up(&mut self, speed: f32) {
+        TelloController::up(self, speed)
+    }
+
+    fn turn_clockwise(&mut self, speed: f32) {
+        TelloController::turn_clockwise(self, speed)
+    }
+
+    fn flip(&mut self, flip: Flip) {
+        match flip {
+            Flip::Forward => TelloController::front_flip(self),
+            Flip::Back => TelloController::back_flip(self),
+            Flip::Left => TelloController::left_flip(self),
+            Flip::Right => TelloController::right_flip(self),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FailsafeConfig {
+    /// seconds without a controller before landing, hover only when unset
+    pub controller_land_after: Option<f32>,
+    /// seconds without telemetry before the link counts as lost
+    pub link_timeout: f32,
+}
+
+impl Default for FailsafeConfig {
+    fn default() -> Self {
+        Self {
+            controller_land_after: None,
+            link_timeout: 1.5,
+        }
+    }
+}
+
+impl FailsafeConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(secs) = self.controller_land_after {
+            if secs < 0.0 || !secs.is_finite() {
+                return Err(format!(
+                    "failsafe: controller_land_after {secs} must not be negative"
+                ));
+            }
+        }
+        if self.link_timeout <= 0.0 || !self.link_timeout.is_finite() {
+            return Err(format!(
+                "failsafe: link_timeout {} must be positive",
+                self.link_timeout
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Takes the sticks away while the gamepad is gone, until it's back with the sticks centered.
+#[derive(Debug)]
+pub struct ControllerFailsafe {
+    land_after: Option<Duration>,
+    lost_at: Option<Instant>,
+    centering: bool,
+    landing: bool,
+}
+
+impl ControllerFailsafe {
+    pub fn new(config: &FailsafeConfig) -> Self {
+        Self {
+            land_after: config.controller_land_after.map(Duration::from_secs_f32),
+            lost_at: None,
+            centering: false,
+            landing: false,
+        }
+    }
+
+    /// True while stick input is ignored.
+    pub fn active(&self) -> bool {
+        self.lost_at.is_some() || self.centering
+    }
+
+    /// The last controller went away at `now`.
+    pub fn lost(&mut self, now: Instant) {
+        tracing::warn!("controller disconnected, hovering");
+        self.lost_at = Some(now);
+        self.centering = false;
+        self.landing = false;
+    }
+
+    pub fn reconnected(&mut self) {
+        if !self.active() {
+            return;
+        }
+        tracing::info!("controller reconnected, waiting for centered sticks");
+        self.lost_at = None;
+        self.centering = true;
+    }
+
+    pub fn centered(&mut self) {
+        if self.centering {
+            tracing::info!("sticks centered, control resumed");
+            self.centering = false;
+        }
+    }
+
+    pub fn centering(&self) -> bool {
+        self.centering
+    }
+
+    /// True once, when the controller has been gone for `controller_land_after` at `now`.
+    pub fn should_land(&mut self, now: Instant) -> bool {
+        let (Some(lost_at), Some(land_after)) = (self.lost_at, self.land_after) else {
+            return false;
+        };
+        if self.landing || now.saturating_duration_since(lost_at) < land_after {
+            return false;
+        }
+        tracing::warn!("controller still disconnected after {land_after:?}, landing");
+        self.landing = true;
+        true
+    }
+
+    /// HUD line, empty while the controller is in charge.
+    pub fn status(&self) -> String {
+        match (self.lost_at, self.land_after) {
+            _ if self.centering => "CENTER THE STICKS".to_owned(),
+            (None, _) => String::new(),
+            (Some(_), _) if self.landing => "CONTROLLER LOST, LANDING".to_owned(),
+            (Some(lost_at), Some(land_after)) => format!(
+                "CONTROLLER LOST, landing in {} s",
+                land_after.saturating_sub(lost_at.elapsed()).as_secs() + 1
+            ),
+            (Some(_), None) => "CONTROLLER LOST, HOVERING".to_owned(),
+        }
+    }
+}
+
+/// Watches the age of the last telemetry, the link is lost when it's older than the timeout.
+#[derive(Debug)]
+pub struct LinkWatch {
+    timeout: Duration,
+    lost: bool,
+    age: Option<Duration>,
+}
+
+impl LinkWatch {
+    pub fn new(config: &FailsafeConfig) -> Self {
+        Self {
+            timeout: Duration::from_secs_f32(config.link_timeout),
+            lost: false,
+            age: None,
+        }
+    }
+
+    pub fn lost(&self) -> bool {
+        self.lost
+    }
+
+    /// Feeds the arrival time of the last telemetry, `None` before the first one.
+    /// Returns `Some(true)` when the link was just lost and `Some(false)` when it recovered.
+    pub fn update(&mut self, last_update: Option<Instant>, now: Instant) -> Option<bool> {
+        self.age = last_update.map(|t| now.saturating_duration_since(t));
+        let lost = self.age.is_some_and(|age| age > self.timeout);
+        if lost == self.lost {
+            return None;
+        }
+        self.lost = lost;
+        if lost {
+            tracing::warn!("link lost, no telemetry for {:?}, hovering", self.timeout);
+        } else {
+            tracing::info!("link recovered");
+        }
+        Some(lost)
+    }
+
+    /// HUD line, empty while telemetry is fresh.
+    pub fn status(&self) -> String {
+        match self.age {
+            Some(age) if self.lost => {
+                format!("LINK LOST, telemetry {:.1} s old", age.as_secs_f32())
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(land_after: Option<f32>) -> FailsafeConfig {
+        FailsafeConfig {
+            controller_land_after: land_after,
+            ..Default::default()
+        }
+    }
+
+    fn secs(secs: f32) -> Duration {
+        Duration::from_secs_f32(secs)
+    }
+
+    #[test]
+    fn should_land_fires_exactly_once() {
+        let mut failsafe = ControllerFailsafe::new(&config(Some(2.0)));
+        let start = Instant::now();
+        assert!(!failsafe.should_land(start + secs(5.0)), "controller there");
+        failsafe.lost(start);
+        let fired: Vec<bool> = [0.0, 1.0, 1.9, 2.1, 2.2, 3.0, 10.0]
+            .iter()
+            .map(|&t| failsafe.should_land(start + secs(t)))
+            .collect();
+        assert_eq!(fired, [false, false, false, true, false, false, false]);
+        assert_eq!(failsafe.status(), "CONTROLLER LOST, LANDING");
+        // lost again, counts anew
+        let again = start + secs(20.0);
+        failsafe.lost(again);
+        assert!(!failsafe.should_land(again + secs(1.0)));
+        assert!(failsafe.should_land(again + secs(2.1)));
+    }
+
+    #[test]
+    fn hover_only_without_land_after() {
+        let mut failsafe = ControllerFailsafe::new(&config(None));
+        let start = Instant::now();
+        failsafe.lost(start);
+        assert!(!failsafe.should_land(start + secs(3600.0)));
+        assert_eq!(failsafe.status(), "CONTROLLER LOST, HOVERING");
+    }
+
+    #[test]
+    fn reconnect_waits_for_centered_sticks() {
+        let mut failsafe = ControllerFailsafe::new(&config(Some(2.0)));
+        // a controller showing up at start isn't a reconnect
+        failsafe.reconnected();
+        assert!(!failsafe.active());
+        failsafe.centered();
+        assert!(!failsafe.active());
+
+        let start = Instant::now();
+        failsafe.lost(start);
+        assert!(failsafe.active());
+        assert!(!failsafe.centering());
+        failsafe.reconnected();
+        assert!(failsafe.active());
+        assert!(failsafe.centering());
+        assert_eq!(failsafe.status(), "CENTER THE STICKS");
+        // no landing once it's back
+        assert!(!failsafe.should_land(start + secs(10.0)));
+        failsafe.centered();
+        assert!(!failsafe.active());
+        assert_eq!(failsafe.status(), "");
+    }
+
+    #[test]
+    fn lost_again_while_centering() {
+        let mut failsafe = ControllerFailsafe::new(&config(Some(2.0)));
+        let start = Instant::now();
+        failsafe.lost(start);
+        failsafe.reconnected();
+        failsafe.lost(start + secs(1.0));
+        assert!(!failsafe.centering());
+        failsafe.centered();
+        assert!(failsafe.active());
+    }
+
+    #[test]
+    fn link_loss_timeout() {
+        let mut link = LinkWatch::new(&FailsafeConfig::default());
+        let start = Instant::now();
+        // (seconds since the last telemetry, transition, lost)
+        let table = [
+            (None, None, false),
+            (Some(0.0), None, false),
+            (Some(1.5), None, false),
+            (Some(1.6), Some(true), true),
+            (Some(5.0), None, true),
+            (Some(0.1), Some(false), false),
+            (Some(0.2), None, false),
+        ];
+        for (age, transition, lost) in table {
+            let now = start + secs(10.0);
+            let last_update = age.map(|age| now - secs(age));
+            assert_eq!(link.update(last_update, now), transition, "{age:?}");
+            assert_eq!(link.lost(), lost, "{age:?}");
+            assert_eq!(link.status().is_empty(), !lost, "{age:?}");
+        }
+    }
+
+    #[test]
+    fn validation() {
+        assert!(FailsafeConfig::default().validate().is_ok());
+        assert!(config(Some(0.0)).validate().is_ok());
+        assert!(config(Some(-1.0)).validate().is_err());
+        assert!(config(Some(f32::NAN)).validate().is_err());
+        let no_timeout = FailsafeConfig {
+            link_timeout: 0.0,
+            ..Default::default()
+        };
+        assert!(no_timeout.validate().is_err());
+    }
+}
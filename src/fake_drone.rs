@@ -0,0 +1,405 @@
+//! Stand-in Tello speaking the low level UDP protocol, for integration tests.
+//!
+//! It answers `conn_req:` with `conn_ack:`, acknowledges every command packet, sends flight,
+//! wifi, light and log (MVO + IMU) status packets while a client is connected, streams an
+//! optional H.264 clip to the client's video port and records everything it received.
+//! Packet layouts follow the ones TelloPy documents for the Tello firmware.
+
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+pub const CONN_REQ: &[u8] = b"conn_req:";
+pub const CONN_ACK: &[u8] = b"conn_ack:";
+
+pub const WIFI_MSG: u16 = 0x001a;
+pub const VIDEO_START_CMD: u16 = 0x0025;
+pub const TAKE_PICTURE_CMD: u16 = 0x0030;
+pub const LIGHT_MSG: u16 = 0x0035;
+pub const STICK_CMD: u16 = 0x0050;
+pub const TAKEOFF_CMD: u16 = 0x0054;
+pub const LAND_CMD: u16 = 0x0055;
+pub const FLIGHT_MSG: u16 = 0x0056;
+pub const THROW_TAKEOFF_CMD: u16 = 0x005d;
+pub const PALM_LAND_CMD: u16 = 0x005e;
+pub const LOG_DATA_MSG: u16 = 0x1051;
+
+const LOG_MVO_ID: u16 = 0x001d;
+const LOG_IMU_ID: u16 = 0x0800;
+
+const STATUS_PERIOD: Duration = Duration::from_millis(100);
+const VIDEO_PERIOD: Duration = Duration::from_millis(33);
+const VIDEO_CHUNK: usize = 1460;
+
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0x77u8;
+    for &b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8c
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0x3692u16;
+    for &b in data {
+        crc ^= b as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// Builds a protocol packet: header, crc8, type, command, sequence, payload, crc16.
+pub fn packet(cmd: u16, pkt_type: u8, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let size = 11 + payload.len();
+    let mut buf = Vec::with_capacity(size);
+    buf.push(0xcc);
+    buf.extend(((size as u16) << 3).to_le_bytes());
+    buf.push(crc8(&buf));
+    buf.push(pkt_type);
+    buf.extend(cmd.to_le_bytes());
+    buf.extend(seq.to_le_bytes());
+    buf.extend(payload);
+    buf.extend(crc16(&buf).to_le_bytes());
+    buf
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub cmd: u16,
+    pub pkt_type: u8,
+    pub seq: u16,
+    pub payload: Vec<u8>,
+}
+
+/// Parses and checks a protocol packet, `None` when it isn't one or the checksums don't match.
+pub fn parse(buf: &[u8]) -> Option<Packet> {
+    if buf.len() < 11 || buf[0] != 0xcc || crc8(&buf[..3]) != buf[3] {
+        return None;
+    }
+    let size = (u16::from_le_bytes([buf[1], buf[2]]) >> 3) as usize;
+    if size < 11 || size > buf.len() {
+        return None;
+    }
+    let crc = u16::from_le_bytes([buf[size - 2], buf[size - 1]]);
+    if crc16(&buf[..size - 2]) != crc {
+        return None;
+    }
+    Some(Packet {
+        cmd: u16::from_le_bytes([buf[5], buf[6]]),
+        pkt_type: buf[4],
+        seq: u16::from_le_bytes([buf[7], buf[8]]),
+        payload: buf[9..size - 2].to_vec(),
+    })
+}
+
+/// Stick command normalized to -1.0..1.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sticks {
+    pub roll: f32,
+    pub pitch: f32,
+    pub throttle: f32,
+    pub yaw: f32,
+}
+
+/// Decodes the 11 bit axes packed in a stick command payload.
+pub fn decode_sticks(payload: &[u8]) -> Option<Sticks> {
+    if payload.len() < 6 {
+        return None;
+    }
+    let mut packed = [0u8; 8];
+    packed[..6].copy_from_slice(&payload[..6]);
+    let packed = u64::from_le_bytes(packed);
+    let axis = |shift: u32| (((packed >> shift) & 0x7ff) as f32 - 1024.0) / 660.0;
+    Some(Sticks {
+        roll: axis(0),
+        pitch: axis(11),
+        throttle: axis(22),
+        yaw: axis(33),
+    })
+}
+
+/// Status the fake drone reports, in protocol units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FakeStatus {
+    /// decimeters
+    pub height: i16,
+    pub fly_time: i16,
+    pub battery_percentage: u8,
+    pub battery_milli_volts: i16,
+    pub wifi_strength: u8,
+    pub light_strength: u8,
+    /// mm/s
+    pub velocity: (i16, i16, i16),
+    /// degrees
+    pub yaw: f32,
+    pub temperature: f32,
+}
+
+impl Default for FakeStatus {
+    fn default() -> Self {
+        Self {
+            height: 0,
+            fly_time: 0,
+            battery_percentage: 87,
+            battery_milli_volts: 4012,
+            wifi_strength: 90,
+            light_strength: 1,
+            velocity: (0, 0, 0),
+            yaw: 0.0,
+            temperature: 45.0,
+        }
+    }
+}
+
+impl FakeStatus {
+    fn flight_payload(&self, flying: bool) -> Vec<u8> {
+        let mut p = vec![0u8; 24];
+        p[0..2].copy_from_slice(&self.height.to_le_bytes());
+        p[8..10].copy_from_slice(&self.fly_time.to_le_bytes());
+        p[12] = self.battery_percentage;
+        p[13..15].copy_from_slice(&self.battery_milli_volts.to_le_bytes());
+        // em_sky
+        p[17] = flying as u8;
+        p
+    }
+
+    fn log_payload(&self) -> Vec<u8> {
+        let mut mvo = vec![0u8; 80];
+        mvo[2..4].copy_from_slice(&self.velocity.0.to_le_bytes());
+        mvo[4..6].copy_from_slice(&self.velocity.1.to_le_bytes());
+        mvo[6..8].copy_from_slice(&self.velocity.2.to_le_bytes());
+
+        // attitude as a quaternion, rotation about z only
+        let half = self.yaw.to_radians() / 2.0;
+        let mut imu = vec![0u8; 120];
+        imu[48..52].copy_from_slice(&half.cos().to_le_bytes());
+        imu[60..64].copy_from_slice(&half.sin().to_le_bytes());
+        imu[106..108].copy_from_slice(&((self.temperature * 100.0) as i16).to_le_bytes());
+
+        // first byte is skipped by the receiver
+        let mut payload = vec![0u8];
+        payload.extend(log_record(LOG_MVO_ID, &mvo));
+        payload.extend(log_record(LOG_IMU_ID, &imu));
+        payload
+    }
+}
+
+fn log_record(id: u16, data: &[u8]) -> Vec<u8> {
+    const XOR: u8 = 0x5a;
+    let length = (data.len() + 12) as u16;
+    let mut rec = vec![0x55];
+    rec.extend(length.to_le_bytes());
+    rec.push(crc8(&rec));
+    rec.extend(id.to_le_bytes());
+    rec.push(XOR);
+    rec.extend([0u8; 3]);
+    rec.extend(data.iter().map(|b| b ^ XOR));
+    rec.extend([0u8; 2]);
+    rec
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Received {
+    ConnReq { video_port: u16 },
+    Command(Packet),
+    Garbage(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FakeDroneConfig {
+    pub status: FakeStatus,
+    /// H.264 elementary stream sent to the video port in a loop
+    pub video: Option<Vec<u8>>,
+}
+
+struct Shared {
+    received: Vec<Received>,
+    status: FakeStatus,
+    client: Option<(SocketAddr, u16)>,
+    flying: bool,
+}
+
+pub struct FakeDrone {
+    addr: SocketAddr,
+    shared: Arc<Mutex<Shared>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FakeDrone {
+    /// Listens on `bind`, e.g. `127.0.0.1:0` or `192.168.10.1:8889`.
+    pub fn start(bind: &str, config: FakeDroneConfig) -> io::Result<Self> {
+        let socket = UdpSocket::bind(bind)?;
+        socket.set_read_timeout(Some(Duration::from_millis(10)))?;
+        let addr = socket.local_addr()?;
+        let shared = Arc::new(Mutex::new(Shared {
+            received: Vec::new(),
+            status: config.status,
+            client: None,
+            flying: false,
+        }));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let shared = shared.clone();
+            let running = running.clone();
+            thread::spawn(move || serve(socket, shared, running, config.video))
+        };
+        Ok(Self {
+            addr,
+            shared,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn set_status(&self, status: FakeStatus) {
+        self.shared.lock().unwrap().status = status;
+    }
+
+    pub fn connected(&self) -> bool {
+        self.shared.lock().unwrap().client.is_some()
+    }
+
+    pub fn flying(&self) -> bool {
+        self.shared.lock().unwrap().flying
+    }
+
+    pub fn received(&self) -> Vec<Received> {
+        self.shared.lock().unwrap().received.clone()
+    }
+
+    /// Commands received so far, in order.
+    pub fn commands(&self) -> Vec<Packet> {
+        self.received()
+            .into_iter()
+            .filter_map(|r| match r {
+                Received::Command(p) => Some(p),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Waits until a command `cmd` shows up, returns it.
+    pub fn wait_for(&self, cmd: u16, timeout: Duration) -> Option<Packet> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(p) = self.commands().into_iter().find(|p| p.cmd == cmd) {
+                return Some(p);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        None
+    }
+}
+
+impl Drop for FakeDrone {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(
+    socket: UdpSocket,
+    shared: Arc<Mutex<Shared>>,
+    running: Arc<AtomicBool>,
+    video: Option<Vec<u8>>,
+) {
+    let mut buf = [0u8; 2048];
+    let mut seq = 0u16;
+    let mut last_status = Instant::now();
+    let mut last_video = Instant::now();
+    let mut video_pos = 0;
+    let mut frame = 0u8;
+    let mut ticks = 0u32;
+    while running.load(Ordering::SeqCst) {
+        if let Ok((n, from)) = socket.recv_from(&mut buf) {
+            let data = &buf[..n];
+            let mut sh = shared.lock().unwrap();
+            if data.starts_with(CONN_REQ) && n >= CONN_REQ.len() + 2 {
+                let port = [data[CONN_REQ.len()], data[CONN_REQ.len() + 1]];
+                let video_port = u16::from_le_bytes(port);
+                sh.received.push(Received::ConnReq { video_port });
+                sh.client = Some((from, video_port));
+                let mut ack = CONN_ACK.to_vec();
+                ack.extend(port);
+                let _ = socket.send_to(&ack, from);
+            } else if let Some(p) = parse(data) {
+                match p.cmd {
+                    // thrown right away
+                    TAKEOFF_CMD | THROW_TAKEOFF_CMD => sh.flying = true,
+                    LAND_CMD | PALM_LAND_CMD => sh.flying = false,
+                    _ => {}
+                }
+                // stick updates are not acknowledged by the drone
+                if p.cmd != STICK_CMD {
+                    let _ = socket.send_to(&packet(p.cmd, 0x90, p.seq, &[0]), from);
+                }
+                sh.received.push(Received::Command(p));
+            } else {
+                sh.received.push(Received::Garbage(data.to_vec()));
+            }
+        }
+
+        let sh = shared.lock().unwrap();
+        let Some((client, video_port)) = sh.client else {
+            continue;
+        };
+        if last_status.elapsed() >= STATUS_PERIOD {
+            last_status = Instant::now();
+            ticks += 1;
+            let mut out = vec![
+                (FLIGHT_MSG, sh.status.flight_payload(sh.flying)),
+                (LOG_DATA_MSG, sh.status.log_payload()),
+            ];
+            if ticks.is_multiple_of(10) {
+                out.push((WIFI_MSG, vec![sh.status.wifi_strength, 0]));
+                out.push((LIGHT_MSG, vec![sh.status.light_strength]));
+            }
+            for (cmd, payload) in out {
+                seq = seq.wrapping_add(1);
+                let _ = socket.send_to(&packet(cmd, 0x88, seq, &payload), client);
+            }
+        }
+        drop(sh);
+
+        if let Some(ref clip) = video {
+            if !clip.is_empty() && last_video.elapsed() >= VIDEO_PERIOD {
+                last_video = Instant::now();
+                let end = (video_pos + VIDEO_CHUNK).min(clip.len());
+                // two byte header: frame and chunk counters
+                let mut chunk = vec![frame, 0];
+                chunk.extend(&clip[video_pos..end]);
+                let _ = socket.send_to(&chunk, SocketAddr::new(client.ip(), video_port));
+                video_pos = if end == clip.len() { 0 } else { end };
+                frame = frame.wrapping_add(1);
+            }
+        }
+    }
+}
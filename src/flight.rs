@@ -0,0 +1,403 @@
+//! The drone's flight state, followed from the telemetry and the commands sent.
+//!
+//! The state decides what may be sent: takeoff only when ready or landed, sticks in the air and
+//! during an emergency landing to steer clear of obstacles, flips only in the air, landing
+//! whenever the drone is up.
+
+use std::time::{Duration, Instant};
+
+/// Takeoff is done once the drone flies for this long and has stopped climbing.
+const TAKEOFF_SETTLE: Duration = Duration::from_secs(1);
+/// m/s
+const SETTLED_CLIMB: f32 = 0.2;
+/// Back on the ground when the drone doesn't report flying by then, e.g. a throw never came.
+const TAKEOFF_TIMEOUT: Duration = Duration::from_secs(10);
+/// Landed is shown this long before the drone counts as ready again.
+const LANDED_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlightState {
+    /// no telemetry from the drone
+    Disconnected,
+    /// telemetry, but no flight data yet or a battery too low to take off
+    Connected,
+    /// on the ground, takeoff allowed
+    Ready,
+    TakingOff,
+    /// in the air with stick input
+    Flying,
+    /// in the air with the sticks centered
+    Hovering,
+    Landing,
+    /// just landed, takeoff allowed
+    Landed,
+    /// in the air with the link lost or the battery critical
+    Emergency,
+}
+
+impl FlightState {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Disconnected => "DISCONNECTED",
+            Self::Connected => "CONNECTED",
+            Self::Ready => "READY",
+            Self::TakingOff => "TAKING OFF",
+            Self::Flying => "FLYING",
+            Self::Hovering => "HOVERING",
+            Self::Landing => "LANDING",
+            Self::Landed => "LANDED",
+            Self::Emergency => "EMERGENCY",
+        }
+    }
+
+    /// Up or on the way up or down, the take off button lands then.
+    pub fn airborne(self) -> bool {
+        matches!(
+            self,
+            Self::TakingOff | Self::Flying | Self::Hovering | Self::Landing | Self::Emergency
+        )
+    }
+}
+
+/// Commands gated by the state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    TakeOff,
+    Land,
+    Hover,
+    Sticks,
+    Flip,
+}
+
+/// What the drone and the failsafes report this frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Observation {
+    /// telemetry is coming in
+    pub link: bool,
+    /// flight data received and the battery allows a takeoff
+    pub ready: bool,
+    pub flying: bool,
+    /// m/s
+    pub climb: f32,
+    /// no stick command is sent
+    pub sticks_idle: bool,
+    /// the battery is critical, the drone is landed whatever the pilot does
+    pub emergency: bool,
+}
+
+#[derive(Debug)]
+pub struct StateMachine {
+    state: FlightState,
+    since: Instant,
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+impl StateMachine {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            state: FlightState::Disconnected,
+            since: now,
+        }
+    }
+
+    pub fn state(&self) -> FlightState {
+        self.state
+    }
+
+    fn enter(&mut self, state: FlightState, now: Instant) {
+        if state != self.state {
+            tracing::info!("flight state {} -> {}", self.state.label(), state.label());
+            self.state = state;
+            self.since = now;
+        }
+    }
+
+    pub fn allows(&self, request: Request) -> bool {
+        use FlightState::*;
+        match request {
+            Request::TakeOff => matches!(self.state, Ready | Landed),
+            Request::Land => self.state.airborne(),
+            Request::Hover => matches!(self.state, TakingOff | Flying | Hovering | Emergency),
+            Request::Sticks => matches!(self.state, Flying | Hovering | Emergency),
+            Request::Flip => matches!(self.state, Flying | Hovering),
+        }
+    }
+
+    /// Moves on for a command about to be sent, false when the state doesn't allow it.
+    pub fn request(&mut self, request: Request, now: Instant) -> bool {
+        use FlightState::*;
+        if !self.allows(request) {
+            return false;
+        }
+        let next = match (request, self.state) {
+            (Request::TakeOff, _) => TakingOff,
+            // an emergency stays one until the drone is down
+            (Request::Land, Emergency) => Emergency,
+            (Request::Land, _) => Landing,
+            (Request::Hover, Flying) => Hovering,
+            (_, state) => state,
+        };
+        self.enter(next, now);
+        true
+    }
+
+    /// Follows the drone.
+    pub fn update(&mut self, obs: &Observation, now: Instant) {
+        use FlightState::*;
+        let elapsed = now.duration_since(self.since);
+        let ground = if obs.ready { Ready } else { Connected };
+        let air = if obs.sticks_idle { Hovering } else { Flying };
+        let next = if !obs.link {
+            if self.state.airborne() {
+                Emergency
+            } else {
+                Disconnected
+            }
+        } else {
+            match self.state {
+                Disconnected => Connected,
+                Connected | Ready | Landed if obs.flying => air,
+                Connected | Ready => ground,
+                Landed if elapsed >= LANDED_TIME => ground,
+                TakingOff if obs.flying => {
+                    if elapsed >= TAKEOFF_SETTLE && obs.climb.abs() < SETTLED_CLIMB {
+                        air
+                    } else {
+                        TakingOff
+                    }
+                }
+                TakingOff if elapsed >= TAKEOFF_TIMEOUT => ground,
+                Flying | Hovering | Landing | Emergency if !obs.flying => Landed,
+                Flying | Hovering | Landing if obs.emergency => Emergency,
+                Flying | Hovering => air,
+                Emergency if !obs.emergency => air,
+                state => state,
+            }
+        };
+        self.enter(next, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use FlightState::*;
+
+    const ALL: [FlightState; 9] = [
+        Disconnected,
+        Connected,
+        Ready,
+        TakingOff,
+        Flying,
+        Hovering,
+        Landing,
+        Landed,
+        Emergency,
+    ];
+
+    fn machine(state: FlightState) -> (StateMachine, Instant) {
+        let now = Instant::now();
+        (StateMachine { state, since: now }, now)
+    }
+
+    // on the ground with the link up and the battery fine
+    fn ground() -> Observation {
+        Observation {
+            link: true,
+            ready: true,
+            sticks_idle: true,
+            ..Default::default()
+        }
+    }
+
+    fn air(sticks_idle: bool) -> Observation {
+        Observation {
+            flying: true,
+            sticks_idle,
+            ..ground()
+        }
+    }
+
+    fn after(state: FlightState, obs: Observation, secs: f32) -> FlightState {
+        let (mut machine, now) = machine(state);
+        machine.update(&obs, now + Duration::from_secs_f32(secs));
+        machine.state()
+    }
+
+    #[test]
+    fn starts_disconnected() {
+        assert_eq!(StateMachine::default().state(), Disconnected);
+    }
+
+    #[test]
+    fn connects_with_telemetry() {
+        assert_eq!(after(Disconnected, ground(), 0.0), Connected);
+        let offline = Observation {
+            link: false,
+            ..ground()
+        };
+        assert_eq!(after(Disconnected, offline, 5.0), Disconnected);
+    }
+
+    #[test]
+    fn ready_with_flight_data_and_battery() {
+        assert_eq!(after(Connected, ground(), 0.0), Ready);
+        let not_ready = Observation {
+            ready: false,
+            ..ground()
+        };
+        assert_eq!(after(Connected, not_ready, 0.0), Connected);
+        assert_eq!(after(Ready, not_ready, 0.0), Connected);
+        assert_eq!(after(Ready, ground(), 0.0), Ready);
+    }
+
+    #[test]
+    fn flying_when_found_in_the_air() {
+        assert_eq!(after(Connected, air(false), 0.0), Flying);
+        assert_eq!(after(Ready, air(false), 0.0), Flying);
+        assert_eq!(after(Landed, air(false), 0.0), Flying);
+    }
+
+    #[test]
+    fn takeoff_from_ready_or_landed_only() {
+        for state in ALL {
+            let (mut machine, now) = machine(state);
+            let allowed = machine.request(Request::TakeOff, now);
+            assert_eq!(allowed, matches!(state, Ready | Landed), "{state:?}");
+            let expected = if allowed { TakingOff } else { state };
+            assert_eq!(machine.state(), expected, "{state:?}");
+        }
+    }
+
+    #[test]
+    fn takeoff_settles_into_the_air() {
+        // still climbing or too early
+        assert_eq!(after(TakingOff, air(true), 0.5), TakingOff);
+        let climbing = Observation {
+            climb: 0.5,
+            ..air(true)
+        };
+        assert_eq!(after(TakingOff, climbing, 2.0), TakingOff);
+        assert_eq!(after(TakingOff, air(true), 1.0), Hovering);
+        assert_eq!(after(TakingOff, air(false), 1.0), Flying);
+    }
+
+    #[test]
+    fn takeoff_gives_up_on_the_ground() {
+        assert_eq!(after(TakingOff, ground(), 5.0), TakingOff);
+        assert_eq!(after(TakingOff, ground(), 10.0), Ready);
+        let not_ready = Observation {
+            ready: false,
+            ..ground()
+        };
+        assert_eq!(after(TakingOff, not_ready, 10.0), Connected);
+    }
+
+    #[test]
+    fn sticks_switch_flying_and_hovering() {
+        assert_eq!(after(Flying, air(true), 0.0), Hovering);
+        assert_eq!(after(Hovering, air(false), 0.0), Flying);
+        assert_eq!(after(Flying, air(false), 0.0), Flying);
+        assert_eq!(after(Hovering, air(true), 0.0), Hovering);
+    }
+
+    #[test]
+    fn land_whenever_airborne() {
+        for state in ALL {
+            let (mut machine, now) = machine(state);
+            let allowed = machine.request(Request::Land, now);
+            assert_eq!(allowed, state.airborne(), "{state:?}");
+            let expected = match state {
+                TakingOff | Flying | Hovering | Landing => Landing,
+                state => state,
+            };
+            assert_eq!(machine.state(), expected, "{state:?}");
+        }
+    }
+
+    #[test]
+    fn landed_when_no_longer_flying() {
+        for state in [Flying, Hovering, Landing, Emergency] {
+            assert_eq!(after(state, ground(), 0.0), Landed, "{state:?}");
+        }
+        assert_eq!(after(Landing, air(true), 0.0), Landing);
+    }
+
+    #[test]
+    fn landed_becomes_ready() {
+        assert_eq!(after(Landed, ground(), 1.0), Landed);
+        assert_eq!(after(Landed, ground(), 2.0), Ready);
+        let not_ready = Observation {
+            ready: false,
+            ..ground()
+        };
+        assert_eq!(after(Landed, not_ready, 2.0), Connected);
+    }
+
+    #[test]
+    fn critical_battery_is_an_emergency() {
+        let critical = Observation {
+            emergency: true,
+            ..air(true)
+        };
+        for state in [Flying, Hovering, Landing] {
+            assert_eq!(after(state, critical, 0.0), Emergency, "{state:?}");
+        }
+        assert_eq!(after(Emergency, critical, 0.0), Emergency);
+    }
+
+    #[test]
+    fn link_loss_in_the_air_is_an_emergency() {
+        let lost = Observation {
+            link: false,
+            ..air(true)
+        };
+        for state in [TakingOff, Flying, Hovering, Landing, Emergency] {
+            assert_eq!(after(state, lost, 0.0), Emergency, "{state:?}");
+        }
+        for state in [Disconnected, Connected, Ready, Landed] {
+            assert_eq!(after(state, lost, 0.0), Disconnected, "{state:?}");
+        }
+    }
+
+    #[test]
+    fn emergency_recovers_in_the_air() {
+        assert_eq!(after(Emergency, air(true), 0.0), Hovering);
+        assert_eq!(after(Emergency, air(false), 0.0), Flying);
+    }
+
+    #[test]
+    fn hover_request() {
+        for state in ALL {
+            let (mut machine, now) = machine(state);
+            let allowed = machine.request(Request::Hover, now);
+            assert_eq!(
+                allowed,
+                matches!(state, TakingOff | Flying | Hovering | Emergency),
+                "{state:?}"
+            );
+            let expected = if state == Flying { Hovering } else { state };
+            assert_eq!(machine.state(), expected, "{state:?}");
+        }
+    }
+
+    #[test]
+    fn sticks_and_flips_in_the_air_only() {
+        for state in ALL {
+            let (mut machine, now) = machine(state);
+            let in_air = matches!(state, Flying | Hovering);
+            assert_eq!(
+                machine.allows(Request::Sticks),
+                in_air || state == Emergency,
+                "{state:?}"
+            );
+            assert_eq!(machine.request(Request::Flip, now), in_air, "{state:?}");
+            assert_eq!(machine.state(), state);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Flips, refused when the drone is too low or the battery too weak to recover from one.
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlipConfig {
+    /// percent, the Tello itself won't flip below 50
+    pub min_battery: i32,
+    /// meters above the takeoff point
+    pub min_height: f32,
+}
+
+impl Default for FlipConfig {
+    fn default() -> Self {
+        Self {
+            min_battery: 50,
+            min_height: 1.0,
+        }
+    }
+}
+
+impl FlipConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0..=100).contains(&self.min_battery) {
+            return Err(format!(
+                "flip: min_battery {} not in 0..=100",
+                self.min_battery
+            ));
+        }
+        if self.min_height < 0.0 || !self.min_height.is_finite() {
+            return Err("flip: min_height must not be negative".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Why a flip can't be flown now, `None` when it can. `flight` is the battery percentage
+    /// and height in meters as last reported.
+    pub fn refusal(&self, flying: bool, flight: Option<(i32, f32)>) -> Option<String> {
+        if !flying {
+            return Some("not flying".to_owned());
+        }
+        let Some((battery, height)) = flight else {
+            return Some("no flight data".to_owned());
+        };
+        if battery < self.min_battery {
+            Some(format!("battery {battery}% < {}%", self.min_battery))
+        } else if height < self.min_height {
+            Some(format!("height {height:.1} m < {:.1} m", self.min_height))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refused_on_the_ground_or_blind() {
+        let config = FlipConfig::default();
+        assert_eq!(
+            config.refusal(false, Some((100, 3.0))).as_deref(),
+            Some("not flying")
+        );
+        assert_eq!(
+            config.refusal(true, None).as_deref(),
+            Some("no flight data")
+        );
+    }
+
+    #[test]
+    fn battery_threshold() {
+        let config = FlipConfig::default();
+        assert_eq!(config.refusal(true, Some((50, 2.0))), None);
+        assert_eq!(
+            config.refusal(true, Some((49, 2.0))).as_deref(),
+            Some("battery 49% < 50%")
+        );
+    }
+
+    #[test]
+    fn height_threshold() {
+        let config = FlipConfig::default();
+        assert_eq!(config.refusal(true, Some((80, 1.0))), None);
+        assert_eq!(
+            config.refusal(true, Some((80, 0.9))).as_deref(),
+            Some("height 0.9 m < 1.0 m")
+        );
+    }
+
+    #[test]
+    fn battery_checked_first() {
+        let config = FlipConfig {
+            min_battery: 60,
+            min_height: 2.5,
+        };
+        assert_eq!(
+            config.refusal(true, Some((30, 0.5))).as_deref(),
+            Some("battery 30% < 60%")
+        );
+        assert_eq!(
+            config.refusal(true, Some((60, 2.4))).as_deref(),
+            Some("height 2.4 m < 2.5 m")
+        );
+        assert_eq!(config.refusal(true, Some((60, 2.5))), None);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(FlipConfig::default().validate().is_ok());
+        for config in [
+            FlipConfig {
+                min_battery: 101,
+                ..Default::default()
+            },
+            FlipConfig {
+                min_battery: -1,
+                ..Default::default()
+            },
+            FlipConfig {
+                min_height: -0.5,
+                ..Default::default()
+            },
+            FlipConfig {
+                min_height: f32::INFINITY,
+                ..Default::default()
+            },
+        ] {
+            assert!(config.validate().is_err());
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! Height and distance limits around the takeoff point.
+//!
+//! The position comes from `nav::Navigation`. Stick commands heading out of the fence are scaled
+//! down within `margin` of a limit and reversed once the ceiling or the radius is crossed.
+
+use sdl2::{pixels::Color, rect::Point, rect::Rect, render::Canvas, video::Window};
+use serde::Deserialize;
+
+use crate::{
+    nav::{self, Navigation},
+    telemetry::StickSample,
+};
+
+/// Stick command pushing the drone back inside after a limit was crossed.
+const RETURN_SPEED: f32 = 0.3;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeofenceConfig {
+    /// max height in meters
+    pub ceiling: Option<f32>,
+    /// min height in meters while flying
+    pub floor: Option<f32>,
+    /// max horizontal distance from the takeoff point in meters
+    pub radius: Option<f32>,
+    /// meters before a limit where the commands start to slow down
+    pub margin: f32,
+}
+
+impl Default for GeofenceConfig {
+    fn default() -> Self {
+        Self {
+            ceiling: None,
+            floor: None,
+            radius: None,
+            margin: 1.0,
+        }
+    }
+}
+
+impl GeofenceConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.margin <= 0.0 || !self.margin.is_finite() {
+            return Err(format!("geofence: margin {} must be positive", self.margin));
+        }
+        for (name, limit) in [
+            ("ceiling", self.ceiling),
+            ("floor", self.floor),
+            ("radius", self.radius),
+        ] {
+            if limit.is_some_and(|l| l < 0.0 || !l.is_finite()) {
+                return Err(format!("geofence: {name} must not be negative"));
+            }
+        }
+        if let (Some(floor), Some(ceiling)) = (self.floor, self.ceiling) {
+            if floor >= ceiling {
+                return Err(format!(
+                    "geofence: floor {floor} must be below ceiling {ceiling}"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Limit {
+    Ceiling,
+    Floor,
+    Radius,
+}
+
+#[derive(Debug)]
+pub struct Geofence {
+    config: GeofenceConfig,
+    active: Vec<Limit>,
+}
+
+impl Geofence {
+    pub fn new(config: GeofenceConfig) -> Self {
+        Self {
+            config,
+            active: Vec::new(),
+        }
+    }
+
+    // 1.0 far from the limit, 0.0 on it, negative beyond
+    fn room(&self, left: f32) -> f32 {
+        (left / self.config.margin).min(1.0)
+    }
+
+    /// Clamps or reverses the parts of `sticks` that lead out of the fence.
+    pub fn limit(&mut self, nav: &Navigation, sticks: &mut StickSample) {
+        let mut active = Vec::new();
+        if !nav.flying {
+            self.active.clear();
+            return;
+        }
+
+        if let Some(ceiling) = self.config.ceiling {
+            let room = self.room(ceiling - nav.height);
+            if room < 1.0 && sticks.up > 0.0 {
+                sticks.up *= room.max(0.0);
+                active.push(Limit::Ceiling);
+            }
+            // the pilot may fly back faster
+            if room < 0.0 {
+                sticks.up = sticks.up.min(-RETURN_SPEED);
+                active.push(Limit::Ceiling);
+            }
+        }
+        // only stops the descent, pushing up near the ground would fight takeoff and landing
+        if let Some(floor) = self.config.floor {
+            let room = self.room(nav.height - floor);
+            if room < 1.0 && sticks.up < 0.0 {
+                sticks.up *= room.max(0.0);
+                active.push(Limit::Floor);
+            }
+        }
+        if let Some(radius) = self.config.radius {
+            let distance = nav.distance();
+            let room = self.room(radius - distance);
+            if room < 1.0 && distance > 0.0 {
+                // split the command into outward and tangential parts in the world frame
+                let (north, east) = nav::to_world(nav.yaw, (sticks.forward, sticks.right));
+                let (un, ue) = (nav.position.0 / distance, nav.position.1 / distance);
+                let outward = north * un + east * ue;
+                let limited = if room < 0.0 {
+                    outward.min(-RETURN_SPEED)
+                } else if outward > 0.0 {
+                    outward * room
+                } else {
+                    outward
+                };
+                if limited != outward {
+                    let north = north + (limited - outward) * un;
+                    let east = east + (limited - outward) * ue;
+                    (sticks.forward, sticks.right) = nav::to_body(nav.yaw, (north, east));
+                    active.push(Limit::Radius);
+                }
+            }
+        }
+
+        // the ceiling may have pushed twice
+        active.dedup();
+        for limit in &active {
+            if !self.active.contains(limit) {
+                tracing::warn!(
+                    "geofence: {limit:?} limits the sticks at height {:.1} m, distance {:.1} m",
+                    nav.height,
+                    nav.distance()
+                );
+            }
+        }
+        for limit in &self.active {
+            if !active.contains(limit) {
+                tracing::info!("geofence: {limit:?} released");
+            }
+        }
+        self.active = active;
+    }
+
+    /// Top down map of the fence around `center`, `size` pixels wide, with a height bar on the right.
+    pub fn draw(
+        &self,
+        nav: &Navigation,
+        canvas: &mut Canvas<Window>,
+        center: (i32, i32),
+        size: i32,
+    ) -> Result<(), String> {
+        let half = size / 2;
+        let (cx, cy) = center;
+        let fence_color = |limit| {
+            if self.active.contains(&limit) {
+                Color::RGB(255, 60, 60)
+            } else {
+                Color::RGB(200, 200, 60)
+            }
+        };
+
+        // meters to pixels, the fence (or the drone when there's none) fits the map
+        let extent = self
+            .config
+            .radius
+            .unwrap_or(0.0)
+            .max(nav.distance())
+            .max(5.0);
+        let scale = 0.9 * half as f32 / extent;
+
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 120));
+        canvas.fill_rect(Rect::new(cx - half, cy - half, size as u32, size as u32))?;
+        if let Some(radius) = self.config.radius {
+            let r = radius * scale;
+            let circle: Vec<Point> = (0..=64)
+                .map(|i| {
+                    let a = i as f32 / 64.0 * std::f32::consts::TAU;
+                    Point::new(cx + (r * a.cos()) as i32, cy + (r * a.sin()) as i32)
+                })
+                .collect();
+            canvas.set_draw_color(fence_color(Limit::Radius));
+            canvas.draw_lines(circle.as_slice())?;
+        }
+        canvas.set_draw_color(Color::RGB(200, 200, 200));
+        canvas.draw_line((cx - 4, cy), (cx + 4, cy))?;
+        canvas.draw_line((cx, cy - 4), (cx, cy + 4))?;
+
+        // north is up
+        let x = cx + (nav.position.1 * scale) as i32;
+        let y = cy - (nav.position.0 * scale) as i32;
+        let (sin, cos) = nav.yaw.to_radians().sin_cos();
+        canvas.set_draw_color(Color::RGB(60, 255, 60));
+        canvas.fill_rect(Rect::new(x - 3, y - 3, 6, 6))?;
+        canvas.draw_line((x, y), (x + (sin * 12.0) as i32, y - (cos * 12.0) as i32))?;
+
+        // height bar, 0 at the bottom
+        let top = self.config.ceiling.unwrap_or(0.0).max(nav.height).max(3.0) * 1.1;
+        let bar_x = cx + half + 8;
+        let level = |h: f32| cy + half - (h / top * size as f32) as i32;
+        canvas.set_draw_color(Color::RGB(200, 200, 200));
+        canvas.draw_line((bar_x, cy - half), (bar_x, cy + half))?;
+        if let Some(ceiling) = self.config.ceiling {
+            canvas.set_draw_color(fence_color(Limit::Ceiling));
+            canvas.draw_line((bar_x - 6, level(ceiling)), (bar_x + 6, level(ceiling)))?;
+        }
+        if let Some(floor) = self.config.floor {
+            canvas.set_draw_color(fence_color(Limit::Floor));
+            canvas.draw_line((bar_x - 6, level(floor)), (bar_x + 6, level(floor)))?;
+        }
+        canvas.set_draw_color(Color::RGB(60, 255, 60));
+        canvas.fill_rect(Rect::new(bar_x - 4, level(nav.height) - 2, 9, 4))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fence(ceiling: Option<f32>, floor: Option<f32>, radius: Option<f32>) -> Geofence {
+        Geofence::new(GeofenceConfig {
+            ceiling,
+            floor,
+            radius,
+            margin: 1.0,
+        })
+    }
+
+    fn nav(height: f32, position: (f32, f32), yaw: f32) -> Navigation {
+        let mut nav = Navigation::default();
+        nav.flying = true;
+        nav.height = height;
+        nav.position = position;
+        nav.yaw = yaw;
+        nav
+    }
+
+    fn sticks(forward: f32, right: f32, up: f32) -> StickSample {
+        StickSample {
+            forward,
+            right,
+            up,
+            turn_clockwise: 0.0,
+        }
+    }
+
+    fn limited(fence: &mut Geofence, nav: &Navigation, mut sample: StickSample) -> StickSample {
+        fence.limit(nav, &mut sample);
+        sample
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn ceiling() {
+        let mut fence = fence(Some(10.0), None, None);
+        // far below, in the margin, beyond
+        let up = |fence: &mut Geofence, height, up| {
+            limited(fence, &nav(height, (0.0, 0.0), 0.0), sticks(0.0, 0.0, up)).up
+        };
+        assert_close(up(&mut fence, 5.0, 1.0), 1.0);
+        assert!(fence.active.is_empty());
+        assert_close(up(&mut fence, 9.5, 1.0), 0.5);
+        assert_eq!(fence.active, [Limit::Ceiling]);
+        assert_close(up(&mut fence, 10.0, 1.0), 0.0);
+        assert_close(up(&mut fence, 10.5, 1.0), -RETURN_SPEED);
+        assert_close(up(&mut fence, 10.5, -1.0), -1.0);
+        // scaled and reversed, still one limit
+        assert_eq!(fence.active, [Limit::Ceiling]);
+        // flying back down is left alone
+        assert_close(up(&mut fence, 9.5, -1.0), -1.0);
+        assert!(fence.active.is_empty());
+    }
+
+    #[test]
+    fn floor() {
+        let mut fence = fence(None, Some(1.0), None);
+        let up = |fence: &mut Geofence, height, up| {
+            limited(fence, &nav(height, (0.0, 0.0), 0.0), sticks(0.0, 0.0, up)).up
+        };
+        assert_close(up(&mut fence, 3.0, -1.0), -1.0);
+        assert_close(up(&mut fence, 1.5, -1.0), -0.5);
+        assert_eq!(fence.active, [Limit::Floor]);
+        // below the floor it just stops, no push up
+        assert_close(up(&mut fence, 0.5, -1.0), 0.0);
+        assert_close(up(&mut fence, 0.5, 0.0), 0.0);
+        assert_close(up(&mut fence, 0.5, 1.0), 1.0);
+        assert!(fence.active.is_empty());
+    }
+
+    #[test]
+    fn radius() {
+        let mut fence = fence(None, None, Some(10.0));
+        // north of the takeoff point facing north, in the margin
+        let near = nav(2.0, (9.5, 0.0), 0.0);
+        let out = limited(&mut fence, &near, sticks(1.0, 0.0, 0.0));
+        assert_close(out.forward, 0.5);
+        assert_eq!(fence.active, [Limit::Radius]);
+        // sideways and back inside are left alone
+        let side = limited(&mut fence, &near, sticks(0.0, 1.0, 0.0));
+        assert_close(side.forward, 0.0);
+        assert_close(side.right, 1.0);
+        let back = limited(&mut fence, &near, sticks(-1.0, 0.0, 0.0));
+        assert_close(back.forward, -1.0);
+        assert!(fence.active.is_empty());
+
+        // facing east the outward part is the left stick
+        let east = nav(2.0, (9.5, 0.0), 90.0);
+        let out = limited(&mut fence, &east, sticks(0.5, -1.0, 0.0));
+        assert_close(out.forward, 0.5);
+        assert_close(out.right, -0.5);
+
+        // beyond the radius the drone is pushed back in
+        let beyond = nav(2.0, (0.0, -11.0), 0.0);
+        let out = limited(&mut fence, &beyond, sticks(0.0, 0.0, 0.0));
+        assert_close(out.forward, 0.0);
+        assert_close(out.right, RETURN_SPEED);
+        // flying back in faster than that is fine
+        let out = limited(&mut fence, &beyond, sticks(0.0, 1.0, 0.0));
+        assert_close(out.right, 1.0);
+        assert!(fence.active.is_empty());
+    }
+
+    #[test]
+    fn every_limit_at_once() {
+        let mut fence = fence(Some(5.0), Some(1.0), Some(10.0));
+        let out = limited(
+            &mut fence,
+            &nav(6.0, (10.5, 0.0), 0.0),
+            sticks(1.0, 0.0, 1.0),
+        );
+        assert_close(out.up, -RETURN_SPEED);
+        assert_close(out.forward, -RETURN_SPEED);
+        assert_eq!(fence.active, [Limit::Ceiling, Limit::Radius]);
+    }
+
+    #[test]
+    fn nothing_on_the_ground() {
+        let mut fence = fence(Some(1.0), None, Some(1.0));
+        let mut ground = nav(5.0, (5.0, 0.0), 0.0);
+        ground.flying = false;
+        let out = limited(&mut fence, &ground, sticks(1.0, 0.0, 1.0));
+        assert_eq!(out, sticks(1.0, 0.0, 1.0));
+        assert!(fence.active.is_empty());
+    }
+
+    #[test]
+    fn validation() {
+        assert!(GeofenceConfig::default().validate().is_ok());
+        assert!(fence(Some(10.0), Some(1.0), Some(5.0))
+            .config
+            .validate()
+            .is_ok());
+        assert!(fence(Some(1.0), Some(1.0), None).config.validate().is_err());
+        assert!(fence(None, None, Some(-1.0)).config.validate().is_err());
+        let no_margin = GeofenceConfig {
+            margin: 0.0,
+            ..Default::default()
+        };
+        assert!(no_margin.validate().is_err());
+    }
+}
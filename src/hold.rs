@@ -0,0 +1,469 @@
+//! Assisted manual flight: holding the height with the throttle moving the target height,
+//! holding the heading while the yaw stick is centered and headless forward/slide.
+//!
+//! The controllers run on `nav::Navigation`, the height and yaw as reported by the drone and
+//! the climb rate from the MVO velocities.
+
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use crate::nav;
+
+/// Integration steps longer than this (seconds) are not taken, same as dead reckoning.
+const MAX_DT: f32 = 0.5;
+/// m/s, the target is captured once the drone has stopped climbing, e.g. after takeoff.
+const CAPTURE_CLIMB: f32 = 0.2;
+
+fn wrap_degrees(degrees: f32) -> f32 {
+    (degrees + 180.0).rem_euclid(360.0) - 180.0
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AltitudeHoldConfig {
+    /// hold from the start, the `altitude_hold` action toggles it either way
+    pub enabled: bool,
+    /// stick per meter of height error
+    pub kp: f32,
+    /// stick per meter second
+    pub ki: f32,
+    /// stick per m/s of climb
+    pub kd: f32,
+    /// m/s the target height moves at full throttle
+    pub rate: f32,
+    /// max up/down command, 0.0..1.0
+    pub max_command: f32,
+}
+
+impl Default for AltitudeHoldConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            kp: 0.8,
+            ki: 0.1,
+            kd: 0.3,
+            rate: 0.5,
+            max_command: 0.6,
+        }
+    }
+}
+
+impl AltitudeHoldConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, gain) in [("kp", self.kp), ("ki", self.ki), ("kd", self.kd)] {
+            if gain < 0.0 || !gain.is_finite() {
+                return Err(format!("altitude_hold: {name} must not be negative"));
+            }
+        }
+        if self.rate <= 0.0 || !self.rate.is_finite() {
+            return Err(format!(
+                "altitude_hold: rate {} must be positive",
+                self.rate
+            ));
+        }
+        if !(self.max_command > 0.0 && self.max_command <= 1.0) {
+            return Err(format!(
+                "altitude_hold: max_command {} not in 0.0..=1.0",
+                self.max_command
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// PID on the height, the throttle moves the target.
+#[derive(Debug)]
+pub struct AltitudeHold {
+    config: AltitudeHoldConfig,
+    on: bool,
+    /// meters, captured when the hold engages
+    target: Option<f32>,
+    integral: f32,
+    last: Option<Instant>,
+}
+
+impl AltitudeHold {
+    pub fn new(config: AltitudeHoldConfig) -> Self {
+        Self {
+            on: config.enabled,
+            config,
+            target: None,
+            integral: 0.0,
+            last: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.on = !self.on;
+        self.release();
+        tracing::info!("altitude hold {}", if self.on { "on" } else { "off" });
+    }
+
+    pub fn on(&self) -> bool {
+        self.on
+    }
+
+    /// Target height while holding.
+    pub fn target(&self) -> Option<f32> {
+        self.target
+    }
+
+    // the next engagement captures the height anew
+    fn release(&mut self) {
+        self.target = None;
+        self.integral = 0.0;
+        self.last = None;
+    }
+
+    /// The up command holding `height`, `None` when the hold is off, not `engaged` or still
+    /// waiting for the climb to stop.
+    /// `throttle` is the pilot's up command, it moves the target at up to `rate` m/s.
+    pub fn update(
+        &mut self,
+        height: f32,
+        climb: f32,
+        throttle: f32,
+        engaged: bool,
+        now: Instant,
+    ) -> Option<f32> {
+        if !self.on || !engaged {
+            self.release();
+            return None;
+        }
+        if self.target.is_none() && climb.abs() > CAPTURE_CLIMB {
+            return None;
+        }
+        let dt = self.last.map_or(0.0, |last| {
+            now.saturating_duration_since(last).as_secs_f32()
+        });
+        self.last = Some(now);
+        let dt = if dt < MAX_DT { dt } else { 0.0 };
+        let target = self.target.get_or_insert_with(|| {
+            tracing::info!("altitude hold at {height:.1} m");
+            height
+        });
+        *target = (*target + throttle.clamp(-1.0, 1.0) * self.config.rate * dt).max(0.0);
+        let error = *target - height;
+
+        let max = self.config.max_command;
+        if self.config.ki > 0.0 {
+            // no more integral than the command can use
+            let limit = max / self.config.ki;
+            self.integral = (self.integral + error * dt).clamp(-limit, limit);
+        }
+        let command =
+            self.config.kp * error + self.config.ki * self.integral - self.config.kd * climb;
+        Some(command.clamp(-max, max))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeadingHoldConfig {
+    /// hold from the start, the `heading_hold` action toggles it either way
+    pub enabled: bool,
+    /// stick per degree of heading error
+    pub kp: f32,
+    /// max turn command, 0.0..1.0
+    pub max_command: f32,
+}
+
+impl Default for HeadingHoldConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            kp: 0.02,
+            max_command: 0.5,
+        }
+    }
+}
+
+impl HeadingHoldConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.kp < 0.0 || !self.kp.is_finite() {
+            return Err("heading_hold: kp must not be negative".to_owned());
+        }
+        if !(self.max_command > 0.0 && self.max_command <= 1.0) {
+            return Err(format!(
+                "heading_hold: max_command {} not in 0.0..=1.0",
+                self.max_command
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Locks the yaw when the pilot stops turning and turns back to it.
+#[derive(Debug)]
+pub struct HeadingHold {
+    config: HeadingHoldConfig,
+    on: bool,
+    /// degrees, captured when the turn command goes back to zero
+    locked: Option<f32>,
+}
+
+impl HeadingHold {
+    pub fn new(config: HeadingHoldConfig) -> Self {
+        Self {
+            on: config.enabled,
+            config,
+            locked: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.on = !self.on;
+        self.locked = None;
+        tracing::info!("heading hold {}", if self.on { "on" } else { "off" });
+    }
+
+    pub fn on(&self) -> bool {
+        self.on
+    }
+
+    /// Locked heading while holding.
+    pub fn locked(&self) -> Option<f32> {
+        self.locked
+    }
+
+    /// The turn command holding the heading, `None` while the pilot turns, when the hold is
+    /// off or not `engaged`.
+    pub fn update(&mut self, yaw: f32, turn: f32, engaged: bool) -> Option<f32> {
+        if !self.on || !engaged || turn != 0.0 {
+            self.locked = None;
+            return None;
+        }
+        let locked = *self.locked.get_or_insert_with(|| {
+            tracing::info!("heading locked at {yaw:.0} deg");
+            yaw
+        });
+        let max = self.config.max_command;
+        Some((self.config.kp * wrap_degrees(locked - yaw)).clamp(-max, max))
+    }
+}
+
+/// Forward/slide relative to the heading the pilot faced when headless mode was switched on.
+#[derive(Debug, Default)]
+pub struct Headless {
+    /// degrees, `None` while off
+    reference: Option<f32>,
+}
+
+impl Headless {
+    /// Switches on facing `yaw`, or off.
+    pub fn toggle(&mut self, yaw: f32) {
+        self.reference = match self.reference {
+            Some(_) => {
+                tracing::info!("headless off");
+                None
+            }
+            None => {
+                tracing::info!("headless on, forward is {yaw:.0} deg");
+                Some(yaw)
+            }
+        };
+    }
+
+    pub fn reference(&self) -> Option<f32> {
+        self.reference
+    }
+
+    /// Pilot (forward, right) to the drone's body frame at `yaw`, unchanged while off.
+    pub fn apply(&self, yaw: f32, sticks: (f32, f32)) -> (f32, f32) {
+        match self.reference {
+            Some(reference) => nav::to_body(yaw, nav::to_world(reference, sticks)),
+            None => sticks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(100);
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5
+    }
+
+    fn holding() -> AltitudeHold {
+        AltitudeHold::new(AltitudeHoldConfig {
+            enabled: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn altitude_captured_once_the_climb_stops() {
+        let mut hold = holding();
+        let now = Instant::now();
+        assert_eq!(hold.update(1.0, 0.5, 0.0, true, now), None);
+        assert_eq!(hold.target(), None);
+        assert_eq!(hold.update(1.2, 0.0, 0.0, true, now + TICK), Some(0.0));
+        assert_eq!(hold.target(), Some(1.2));
+        // sagging pushes up, climbing past it pulls down
+        assert!(hold.update(1.0, 0.0, 0.0, true, now + 2 * TICK).unwrap() > 0.0);
+        assert!(hold.update(1.4, 0.5, 0.0, true, now + 3 * TICK).unwrap() < 0.0);
+    }
+
+    #[test]
+    fn throttle_moves_the_target() {
+        let mut hold = holding();
+        let now = Instant::now();
+        hold.update(1.0, 0.0, 0.0, true, now);
+        hold.update(1.0, 0.0, 1.0, true, now + Duration::from_secs_f32(0.4));
+        assert!((hold.target().unwrap() - 1.2).abs() < 1e-5);
+        // not below the ground
+        for i in 2..20 {
+            hold.update(1.0, 0.0, -1.0, true, now + i * Duration::from_secs_f32(0.4));
+        }
+        assert_eq!(hold.target(), Some(0.0));
+    }
+
+    #[test]
+    fn integral_stays_clamped() {
+        let config = AltitudeHoldConfig::default();
+        let mut hold = holding();
+        let mut now = Instant::now();
+        hold.update(2.0, 0.0, 0.0, true, now);
+        // stuck a meter low for a long time
+        for _ in 0..1000 {
+            now += TICK;
+            let up = hold.update(1.0, 0.0, 0.0, true, now).unwrap();
+            assert!(up <= config.max_command);
+        }
+        assert!((hold.integral - config.max_command / config.ki).abs() < 1e-3);
+        // back at the target the wound up integral alone can't push past the limit and
+        // unwinds as soon as the error turns
+        now += TICK;
+        assert_eq!(
+            hold.update(2.0, 0.0, 0.0, true, now),
+            Some(config.max_command)
+        );
+        now += TICK;
+        hold.update(2.5, 0.0, 0.0, true, now);
+        assert!(hold.integral < config.max_command / config.ki);
+    }
+
+    #[test]
+    fn long_steps_are_not_integrated() {
+        let mut hold = holding();
+        let now = Instant::now();
+        hold.update(2.0, 0.0, 0.0, true, now);
+        hold.update(1.0, 0.0, 1.0, true, now + Duration::from_secs(2));
+        assert_eq!(hold.integral, 0.0);
+        assert_eq!(hold.target(), Some(2.0));
+    }
+
+    #[test]
+    fn altitude_released_when_not_engaged() {
+        let mut hold = holding();
+        let now = Instant::now();
+        hold.update(1.0, 0.0, 0.0, true, now);
+        hold.update(0.5, 0.0, 0.0, true, now + TICK);
+        assert_eq!(hold.update(0.5, 0.0, 0.0, false, now + 2 * TICK), None);
+        assert_eq!(hold.target(), None);
+        assert_eq!(hold.integral, 0.0);
+        hold.update(0.5, 0.0, 0.0, true, now + 3 * TICK);
+        assert_eq!(hold.target(), Some(0.5));
+        hold.toggle();
+        assert_eq!(hold.update(0.5, 0.0, 0.0, true, now + 4 * TICK), None);
+    }
+
+    #[test]
+    fn heading_locks_when_the_stick_centres() {
+        let mut hold = HeadingHold::new(HeadingHoldConfig {
+            enabled: true,
+            ..Default::default()
+        });
+        assert_eq!(hold.update(10.0, 0.5, true), None);
+        assert_eq!(hold.locked(), None);
+        assert_eq!(hold.update(30.0, 0.0, true), Some(0.0));
+        assert_eq!(hold.locked(), Some(30.0));
+        // drifted clockwise, turns back counterclockwise
+        assert!((hold.update(40.0, 0.0, true).unwrap() + 0.2).abs() < 1e-5);
+        assert!((hold.update(25.0, 0.0, true).unwrap() - 0.1).abs() < 1e-5);
+        // capped
+        assert_eq!(hold.update(90.0, 0.0, true), Some(-0.5));
+        // turning again unlocks, centring locks the new heading
+        assert_eq!(hold.update(90.0, -0.3, true), None);
+        hold.update(120.0, 0.0, true);
+        assert_eq!(hold.locked(), Some(120.0));
+        assert_eq!(hold.update(120.0, 0.0, false), None);
+        assert_eq!(hold.locked(), None);
+    }
+
+    #[test]
+    fn heading_turns_back_the_short_way_across_north() {
+        let mut hold = HeadingHold::new(HeadingHoldConfig {
+            enabled: true,
+            ..Default::default()
+        });
+        hold.update(175.0, 0.0, true);
+        // 10 degrees past south, counterclockwise back
+        assert!((hold.update(-175.0, 0.0, true).unwrap() + 0.2).abs() < 1e-5);
+        hold.update(-175.0, 0.5, true);
+        hold.update(-175.0, 0.0, true);
+        assert!((hold.update(175.0, 0.0, true).unwrap() - 0.2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn headless_off_leaves_the_sticks() {
+        let headless = Headless::default();
+        assert_eq!(headless.apply(90.0, (0.5, -0.25)), (0.5, -0.25));
+    }
+
+    #[test]
+    fn headless_turned_a_quarter() {
+        let mut headless = Headless::default();
+        headless.toggle(0.0);
+        // the pilot's forward is north, the drone faces east: north is its left
+        assert!(close(headless.apply(90.0, (1.0, 0.0)), (0.0, -1.0)));
+        assert!(close(headless.apply(90.0, (0.0, 1.0)), (1.0, 0.0)));
+        assert!(close(headless.apply(-90.0, (1.0, 0.0)), (0.0, 1.0)));
+    }
+
+    #[test]
+    fn headless_turned_around() {
+        let mut headless = Headless::default();
+        headless.toggle(0.0);
+        assert!(close(headless.apply(180.0, (1.0, 0.5)), (-1.0, -0.5)));
+        // the reference is where the pilot faced, not north
+        headless.toggle(0.0);
+        headless.toggle(90.0);
+        assert!(close(headless.apply(270.0, (1.0, 0.0)), (-1.0, 0.0)));
+        assert!(close(headless.apply(90.0, (0.3, 0.4)), (0.3, 0.4)));
+    }
+
+    #[test]
+    fn validation() {
+        assert!(AltitudeHoldConfig::default().validate().is_ok());
+        assert!(HeadingHoldConfig::default().validate().is_ok());
+        for config in [
+            AltitudeHoldConfig {
+                ki: -0.1,
+                ..Default::default()
+            },
+            AltitudeHoldConfig {
+                rate: 0.0,
+                ..Default::default()
+            },
+            AltitudeHoldConfig {
+                max_command: 1.5,
+                ..Default::default()
+            },
+        ] {
+            assert!(config.validate().is_err());
+        }
+        assert!(HeadingHoldConfig {
+            kp: f32::NAN,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,813 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::Path,
+};
+
+use sdl2::{
+    controller::{Axis, Button},
+    keyboard::Keycode,
+};
+use serde::Deserialize;
+
+use crate::{axis::AxisCurve, drone::Flip};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// takes off on the ground, lands in the air
+    TakeOff,
+    Land,
+    /// cycles standard, throw-and-go and palm landing for the take off button
+    LaunchMode,
+    /// starts the loaded mission, aborts it when running
+    Mission,
+    /// flies the configured survey grid, aborts it when running
+    Survey,
+    /// runs the loaded script, aborts it when running
+    Script,
+    /// opens the Tello SDK command console
+    Console,
+    /// holds the height, the throttle moves the target
+    AltitudeHold,
+    /// keeps the heading while the yaw stick is centered
+    HeadingHold,
+    /// forward/slide relative to the pilot instead of the nose
+    Headless,
+    Hover,
+    /// flips in the given direction
+    Flip(Flip),
+    TakePicture,
+    ToggleVideo,
+    /// start/stop saving the video stream
+    ToggleRecording,
+    SensitivityUp,
+    SensitivityDown,
+    /// set sensitivity directly, in percent
+    Sensitivity(u8),
+    CarouselLeft,
+    CarouselRight,
+    CarouselZoom,
+    StickMode,
+    Quit,
+}
+
+impl Action {
+    fn parse(name: &str) -> Option<Self> {
+        let action = match name {
+            "take_off" => Self::TakeOff,
+            "land" => Self::Land,
+            "launch_mode" => Self::LaunchMode,
+            "mission" => Self::Mission,
+            "survey" => Self::Survey,
+            "script" => Self::Script,
+            "console" => Self::Console,
+            "altitude_hold" => Self::AltitudeHold,
+            "heading_hold" => Self::HeadingHold,
+            "headless" => Self::Headless,
+            "hover" => Self::Hover,
+            "flip_forward" => Self::Flip(Flip::Forward),
+            "flip_back" => Self::Flip(Flip::Back),
+            "flip_left" => Self::Flip(Flip::Left),
+            "flip_right" => Self::Flip(Flip::Right),
+            "take_picture" => Self::TakePicture,
+            "toggle_video" => Self::ToggleVideo,
+            "toggle_recording" => Self::ToggleRecording,
+            "sensitivity_up" => Self::SensitivityUp,
+            "sensitivity_down" => Self::SensitivityDown,
+            "carousel_left" => Self::CarouselLeft,
+            "carousel_right" => Self::CarouselRight,
+            "carousel_zoom" => Self::CarouselZoom,
+            "stick_mode" => Self::StickMode,
+            "quit" => Self::Quit,
+            _ => {
+                let percent = name.strip_prefix("sensitivity_")?.parse::<u8>().ok()?;
+                if percent > 100 {
+                    return None;
+                }
+                Self::Sensitivity(percent)
+            }
+        };
+        Some(action)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    Forward,
+    SlideRight,
+    TurnClockwise,
+    Throttle,
+    Up,
+    Down,
+}
+
+impl Motion {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "forward" => Some(Self::Forward),
+            "slide_right" => Some(Self::SlideRight),
+            "turn_clockwise" => Some(Self::TurnClockwise),
+            "throttle" => Some(Self::Throttle),
+            "up" => Some(Self::Up),
+            "down" => Some(Self::Down),
+            _ => None,
+        }
+    }
+}
+
+/// Motion bound to an axis or a key, `-forward` in the profile inverts the direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionBinding {
+    pub motion: Motion,
+    pub invert: bool,
+}
+
+impl MotionBinding {
+    fn new(motion: Motion, invert: bool) -> Self {
+        Self { motion, invert }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name.strip_prefix('-') {
+            Some(name) => Some(Self::new(Motion::parse(name)?, true)),
+            None => Some(Self::new(Motion::parse(name)?, false)),
+        }
+    }
+
+    pub fn apply(&self, value: f32) -> f32 {
+        if self.invert {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
+/// RC stick layout, `Gamepad` keeps the stick axes from the profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StickMode {
+    Gamepad,
+    /// left: pitch + yaw, right: throttle + roll
+    Mode1,
+    /// left: throttle + yaw, right: pitch + roll
+    Mode2,
+    /// left: pitch + roll, right: throttle + yaw
+    Mode3,
+    /// left: throttle + roll, right: pitch + yaw
+    Mode4,
+}
+
+impl StickMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "gamepad" => Some(Self::Gamepad),
+            "mode1" => Some(Self::Mode1),
+            "mode2" => Some(Self::Mode2),
+            "mode3" => Some(Self::Mode3),
+            "mode4" => Some(Self::Mode4),
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Gamepad => Self::Mode1,
+            Self::Mode1 => Self::Mode2,
+            Self::Mode2 => Self::Mode3,
+            Self::Mode3 => Self::Mode4,
+            Self::Mode4 => Self::Gamepad,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Gamepad => "GAMEPAD",
+            Self::Mode1 => "MODE 1",
+            Self::Mode2 => "MODE 2",
+            Self::Mode3 => "MODE 3",
+            Self::Mode4 => "MODE 4",
+        }
+    }
+
+    /// Motions on (left x, left y, right x, right y), `None` for the gamepad layout.
+    pub fn layout(self) -> Option<[Motion; 4]> {
+        use Motion::*;
+        match self {
+            Self::Gamepad => None,
+            Self::Mode1 => Some([TurnClockwise, Forward, SlideRight, Throttle]),
+            Self::Mode2 => Some([TurnClockwise, Throttle, SlideRight, Forward]),
+            Self::Mode3 => Some([SlideRight, Forward, TurnClockwise, Throttle]),
+            Self::Mode4 => Some([SlideRight, Throttle, TurnClockwise, Forward]),
+        }
+    }
+
+    fn axis(self, axis: Axis) -> Option<MotionBinding> {
+        let [lx, ly, rx, ry] = self.layout()?;
+        // stick y axes report positive values when pulled down
+        match axis {
+            Axis::LeftX => Some(MotionBinding::new(lx, false)),
+            Axis::LeftY => Some(MotionBinding::new(ly, true)),
+            Axis::RightX => Some(MotionBinding::new(rx, false)),
+            Axis::RightY => Some(MotionBinding::new(ry, true)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyBinding {
+    Action(Action),
+    Motion(MotionBinding),
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Io(String, io::Error),
+    Parse(String, toml::de::Error),
+    UnknownProfile(String),
+    UnknownStickMode {
+        profile: String,
+        mode: String,
+    },
+    UnknownInput {
+        profile: String,
+        kind: &'static str,
+        name: String,
+    },
+    UnknownAction {
+        profile: String,
+        input: String,
+        action: String,
+    },
+    /// the same input under two spellings, e.g. `W` and `w`
+    DuplicateInput {
+        profile: String,
+        kind: &'static str,
+        name: String,
+    },
+    InvalidCurve {
+        profile: String,
+        axis: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "can't read input profiles from {path}: {e}"),
+            Self::Parse(path, e) => write!(f, "can't parse input profiles in {path}: {e}"),
+            Self::UnknownProfile(name) => write!(f, "input profile `{name}` not found"),
+            Self::UnknownStickMode { profile, mode } => {
+                write!(f, "profile `{profile}`: unknown stick mode `{mode}`")
+            }
+            Self::UnknownInput {
+                profile,
+                kind,
+                name,
+            } => write!(f, "profile `{profile}`: unknown {kind} `{name}`"),
+            Self::UnknownAction {
+                profile,
+                input,
+                action,
+            } => write!(
+                f,
+                "profile `{profile}`: unknown action `{action}` bound to `{input}`"
+            ),
+            Self::DuplicateInput {
+                profile,
+                kind,
+                name,
+            } => write!(f, "profile `{profile}`: {kind} `{name}` bound twice"),
+            Self::InvalidCurve {
+                profile,
+                axis,
+                reason,
+            } => write!(f, "profile `{profile}`: curve for `{axis}`: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    default: Option<String>,
+    #[serde(default)]
+    profiles: HashMap<String, RawProfile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawProfile {
+    mode: Option<String>,
+    #[serde(default)]
+    buttons: HashMap<String, String>,
+    #[serde(default)]
+    axes: HashMap<String, String>,
+    #[serde(default)]
+    keys: HashMap<String, String>,
+    /// `"<held>+<pressed>" = "<action>"`
+    #[serde(default)]
+    chords: HashMap<String, String>,
+    #[serde(default)]
+    curves: HashMap<String, AxisCurve>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputProfile {
+    pub name: String,
+    pub mode: StickMode,
+    buttons: HashMap<Button, Action>,
+    axes: HashMap<Axis, MotionBinding>,
+    keys: HashMap<Keycode, KeyBinding>,
+    /// (held, pressed) buttons
+    chords: HashMap<(Button, Button), Action>,
+    curves: HashMap<Axis, AxisCurve>,
+}
+
+impl InputProfile {
+    /// Loads profile `name` (or the file's `default`) from `path`.
+    /// A missing file yields the built-in bindings.
+    pub fn load(path: &Path, name: Option<&str>) -> Result<Self, ProfileError> {
+        let source = path.display().to_string();
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return match name {
+                    None | Some(DEFAULT_PROFILE) => {
+                        tracing::info!("{} not found, using default input bindings", source);
+                        Ok(Self::default())
+                    }
+                    Some(name) => Err(ProfileError::UnknownProfile(name.to_owned())),
+                };
+            }
+            Err(e) => return Err(ProfileError::Io(source, e)),
+        };
+        Self::parse(&content, name).map_err(|e| match e {
+            ProfileError::Parse(_, e) => ProfileError::Parse(source, e),
+            e => e,
+        })
+    }
+
+    fn parse(content: &str, name: Option<&str>) -> Result<Self, ProfileError> {
+        let mut file: ProfileFile =
+            toml::from_str(content).map_err(|e| ProfileError::Parse(String::new(), e))?;
+        let name = name
+            .map(str::to_owned)
+            .or(file.default.take())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_owned());
+        match file.profiles.remove(&name) {
+            Some(raw) => Self::compile(name, raw),
+            None if name == DEFAULT_PROFILE => Ok(Self::default()),
+            None => Err(ProfileError::UnknownProfile(name)),
+        }
+    }
+
+    fn compile(name: String, raw: RawProfile) -> Result<Self, ProfileError> {
+        let unknown_input = |kind, input: &str| ProfileError::UnknownInput {
+            profile: name.clone(),
+            kind,
+            name: input.to_owned(),
+        };
+        let unknown_action = |input: &str, action: &str| ProfileError::UnknownAction {
+            profile: name.clone(),
+            input: input.to_owned(),
+            action: action.to_owned(),
+        };
+        let duplicate = |kind, input: &str| ProfileError::DuplicateInput {
+            profile: name.clone(),
+            kind,
+            name: input.to_owned(),
+        };
+
+        let mode = match &raw.mode {
+            Some(mode) => StickMode::parse(mode).ok_or_else(|| ProfileError::UnknownStickMode {
+                profile: name.clone(),
+                mode: mode.clone(),
+            })?,
+            None => StickMode::Gamepad,
+        };
+
+        let mut buttons = HashMap::new();
+        for (input, action) in &raw.buttons {
+            let button =
+                Button::from_string(input).ok_or_else(|| unknown_input("button", input))?;
+            let action = Action::parse(action).ok_or_else(|| unknown_action(input, action))?;
+            if buttons.insert(button, action).is_some() {
+                return Err(duplicate("button", input));
+            }
+        }
+
+        let mut axes = HashMap::new();
+        for (input, motion) in &raw.axes {
+            let axis = Axis::from_string(input).ok_or_else(|| unknown_input("axis", input))?;
+            let motion =
+                MotionBinding::parse(motion).ok_or_else(|| unknown_action(input, motion))?;
+            if axes.insert(axis, motion).is_some() {
+                return Err(duplicate("axis", input));
+            }
+        }
+
+        let mut keys = HashMap::new();
+        for (input, binding) in &raw.keys {
+            let key = Keycode::from_name(input).ok_or_else(|| unknown_input("key", input))?;
+            let binding = match Action::parse(binding) {
+                Some(action) => KeyBinding::Action(action),
+                None => KeyBinding::Motion(
+                    MotionBinding::parse(binding).ok_or_else(|| unknown_action(input, binding))?,
+                ),
+            };
+            if keys.insert(key, binding).is_some() {
+                return Err(duplicate("key", input));
+            }
+        }
+
+        let mut chords = HashMap::new();
+        for (input, action) in &raw.chords {
+            let (held, pressed) = input
+                .split_once('+')
+                .and_then(|(held, pressed)| {
+                    Some((Button::from_string(held)?, Button::from_string(pressed)?))
+                })
+                .filter(|(held, pressed)| held != pressed)
+                .ok_or_else(|| unknown_input("chord", input))?;
+            let action = Action::parse(action).ok_or_else(|| unknown_action(input, action))?;
+            if chords.insert((held, pressed), action).is_some() {
+                return Err(duplicate("chord", input));
+            }
+        }
+
+        let mut curves = HashMap::new();
+        for (input, curve) in &raw.curves {
+            let axis = Axis::from_string(input).ok_or_else(|| unknown_input("axis", input))?;
+            curve
+                .validate()
+                .map_err(|reason| ProfileError::InvalidCurve {
+                    profile: name.clone(),
+                    axis: input.to_owned(),
+                    reason,
+                })?;
+            if curves.insert(axis, *curve).is_some() {
+                return Err(duplicate("curve axis", input));
+            }
+        }
+
+        Ok(Self {
+            name,
+            mode,
+            buttons,
+            axes,
+            keys,
+            chords,
+            curves,
+        })
+    }
+
+    pub fn button(&self, button: Button) -> Option<Action> {
+        self.buttons.get(&button).copied()
+    }
+
+    /// The action of `pressed` together with one of the `held` buttons.
+    pub fn chord(&self, held: &HashSet<Button>, pressed: Button) -> Option<Action> {
+        held.iter()
+            .find_map(|held| self.chords.get(&(*held, pressed)).copied())
+    }
+
+    pub fn axis(&self, axis: Axis) -> Option<MotionBinding> {
+        self.mode
+            .axis(axis)
+            .or_else(|| self.axes.get(&axis).copied())
+    }
+
+    pub fn key(&self, key: Keycode) -> Option<KeyBinding> {
+        self.keys.get(&key).copied()
+    }
+
+    pub fn curve(&self, axis: Axis) -> AxisCurve {
+        self.curves.get(&axis).copied().unwrap_or_default()
+    }
+}
+
+impl Default for InputProfile {
+    fn default() -> Self {
+        let buttons = HashMap::from([
+            (Button::A, Action::TakePicture),
+            (Button::B, Action::ToggleVideo),
+            (Button::X, Action::CarouselZoom),
+            (Button::Guide, Action::Hover),
+            (Button::Start, Action::TakeOff),
+            (Button::LeftShoulder, Action::SensitivityDown),
+            (Button::RightShoulder, Action::SensitivityUp),
+            (Button::DPadLeft, Action::CarouselLeft),
+            (Button::DPadRight, Action::CarouselRight),
+            (Button::Back, Action::StickMode),
+            (Button::DPadUp, Action::ToggleRecording),
+            (Button::DPadDown, Action::Mission),
+            (Button::RightStick, Action::HeadingHold),
+            (Button::LeftStick, Action::Headless),
+        ]);
+        let axes = HashMap::from([
+            (Axis::LeftX, MotionBinding::new(Motion::SlideRight, false)),
+            (Axis::LeftY, MotionBinding::new(Motion::Forward, true)),
+            (
+                Axis::RightX,
+                MotionBinding::new(Motion::TurnClockwise, false),
+            ),
+            (Axis::TriggerRight, MotionBinding::new(Motion::Up, false)),
+            (Axis::TriggerLeft, MotionBinding::new(Motion::Down, false)),
+        ]);
+        let motion = |motion, invert| KeyBinding::Motion(MotionBinding::new(motion, invert));
+        let keys = HashMap::from([
+            (Keycode::Escape, KeyBinding::Action(Action::Quit)),
+            (Keycode::NUM_1, KeyBinding::Action(Action::Sensitivity(0))),
+            (Keycode::NUM_2, KeyBinding::Action(Action::Sensitivity(20))),
+            (Keycode::NUM_3, KeyBinding::Action(Action::Sensitivity(40))),
+            (Keycode::NUM_4, KeyBinding::Action(Action::Sensitivity(60))),
+            (Keycode::NUM_5, KeyBinding::Action(Action::Sensitivity(80))),
+            (Keycode::NUM_6, KeyBinding::Action(Action::Sensitivity(100))),
+            (Keycode::MINUS, KeyBinding::Action(Action::SensitivityDown)),
+            (Keycode::EQUALS, KeyBinding::Action(Action::SensitivityUp)),
+            (Keycode::W, motion(Motion::Forward, false)),
+            (Keycode::S, motion(Motion::Forward, true)),
+            (Keycode::A, motion(Motion::SlideRight, true)),
+            (Keycode::D, motion(Motion::SlideRight, false)),
+            (Keycode::Up, motion(Motion::Throttle, false)),
+            (Keycode::Down, motion(Motion::Throttle, true)),
+            (Keycode::Left, motion(Motion::TurnClockwise, true)),
+            (Keycode::Right, motion(Motion::TurnClockwise, false)),
+            (Keycode::SPACE, KeyBinding::Action(Action::TakeOff)),
+            (Keycode::L, KeyBinding::Action(Action::Land)),
+            (Keycode::J, KeyBinding::Action(Action::LaunchMode)),
+            (Keycode::H, KeyBinding::Action(Action::Hover)),
+            (Keycode::G, KeyBinding::Action(Action::Mission)),
+            (Keycode::K, KeyBinding::Action(Action::Survey)),
+            (Keycode::X, KeyBinding::Action(Action::Script)),
+            (Keycode::BACKQUOTE, KeyBinding::Action(Action::Console)),
+            (Keycode::T, KeyBinding::Action(Action::AltitudeHold)),
+            (Keycode::Y, KeyBinding::Action(Action::HeadingHold)),
+            (Keycode::U, KeyBinding::Action(Action::Headless)),
+            (Keycode::V, KeyBinding::Action(Action::ToggleVideo)),
+            (Keycode::R, KeyBinding::Action(Action::ToggleRecording)),
+            (Keycode::P, KeyBinding::Action(Action::TakePicture)),
+            (Keycode::C, KeyBinding::Action(Action::CarouselZoom)),
+            (Keycode::COMMA, KeyBinding::Action(Action::CarouselLeft)),
+            (Keycode::PERIOD, KeyBinding::Action(Action::CarouselRight)),
+            (Keycode::M, KeyBinding::Action(Action::StickMode)),
+        ]);
+        // the D-pad flips while Y is held, start picks the launch mode
+        let chords = HashMap::from([
+            ((Button::Y, Button::DPadUp), Action::Flip(Flip::Forward)),
+            ((Button::Y, Button::DPadDown), Action::Flip(Flip::Back)),
+            ((Button::Y, Button::DPadLeft), Action::Flip(Flip::Left)),
+            ((Button::Y, Button::DPadRight), Action::Flip(Flip::Right)),
+            ((Button::Y, Button::Start), Action::LaunchMode),
+        ]);
+        Self {
+            name: DEFAULT_PROFILE.to_owned(),
+            mode: StickMode::Gamepad,
+            buttons,
+            axes,
+            keys,
+            chords,
+            curves: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(content: &str) -> String {
+        InputProfile::parse(content, None).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn actions() {
+        assert_eq!(Action::parse("take_off"), Some(Action::TakeOff));
+        assert_eq!(Action::parse("flip_left"), Some(Action::Flip(Flip::Left)));
+        assert_eq!(Action::parse("sensitivity_0"), Some(Action::Sensitivity(0)));
+        assert_eq!(
+            Action::parse("sensitivity_100"),
+            Some(Action::Sensitivity(100))
+        );
+        assert_eq!(Action::parse("sensitivity_101"), None);
+        assert_eq!(Action::parse("sensitivity_-5"), None);
+        assert_eq!(Action::parse("sensitivity_"), None);
+        assert_eq!(Action::parse("Take_Off"), None);
+        assert_eq!(Action::parse("fly_away"), None);
+    }
+
+    #[test]
+    fn motions_invert_with_a_minus() {
+        let forward = MotionBinding::parse("forward").unwrap();
+        assert_eq!(forward, MotionBinding::new(Motion::Forward, false));
+        assert_eq!(forward.apply(0.5), 0.5);
+        let back = MotionBinding::parse("-forward").unwrap();
+        assert_eq!(back, MotionBinding::new(Motion::Forward, true));
+        assert_eq!(back.apply(0.5), -0.5);
+        assert_eq!(MotionBinding::parse("--forward"), None);
+        assert_eq!(MotionBinding::parse("-"), None);
+        assert_eq!(MotionBinding::parse("backward"), None);
+    }
+
+    #[test]
+    fn profile() {
+        let content = r#"
+            default = "mine"
+
+            [profiles.mine]
+            mode = "mode2"
+
+            [profiles.mine.buttons]
+            a = "take_off"
+            dpdown = "sensitivity_40"
+
+            [profiles.mine.axes]
+            lefttrigger = "-up"
+
+            [profiles.mine.keys]
+            Q = "quit"
+            Up = "-throttle"
+
+            [profiles.mine.chords]
+            "leftshoulder+a" = "flip_back"
+
+            [profiles.mine.curves.leftx]
+            expo = 0.5
+            rate = 2.0
+
+            [profiles.other.buttons]
+            b = "hover"
+        "#;
+        let profile = InputProfile::parse(content, None).unwrap();
+        assert_eq!(profile.name, "mine");
+        assert_eq!(profile.mode, StickMode::Mode2);
+        assert_eq!(profile.button(Button::A), Some(Action::TakeOff));
+        assert_eq!(
+            profile.button(Button::DPadDown),
+            Some(Action::Sensitivity(40))
+        );
+        assert_eq!(profile.button(Button::B), None);
+        assert_eq!(
+            profile.axis(Axis::TriggerLeft),
+            Some(MotionBinding::new(Motion::Up, true))
+        );
+        // the stick axes follow the mode
+        assert_eq!(
+            profile.axis(Axis::LeftY),
+            Some(MotionBinding::new(Motion::Throttle, true))
+        );
+        assert_eq!(
+            profile.key(Keycode::Q),
+            Some(KeyBinding::Action(Action::Quit))
+        );
+        assert_eq!(
+            profile.key(Keycode::Up),
+            Some(KeyBinding::Motion(MotionBinding::new(
+                Motion::Throttle,
+                true
+            )))
+        );
+        let held = HashSet::from([Button::LeftShoulder]);
+        assert_eq!(
+            profile.chord(&held, Button::A),
+            Some(Action::Flip(Flip::Back))
+        );
+        assert_eq!(profile.chord(&HashSet::new(), Button::A), None);
+        // the other way round isn't the chord
+        assert_eq!(
+            profile.chord(&HashSet::from([Button::A]), Button::LeftShoulder),
+            None
+        );
+        let curve = profile.curve(Axis::LeftX);
+        assert_eq!(
+            (curve.dead_zone, curve.expo, curve.rate),
+            (0.05, 0.5, Some(2.0))
+        );
+        assert_eq!(profile.curve(Axis::RightX).expo, 0.0);
+
+        let other = InputProfile::parse(content, Some("other")).unwrap();
+        assert_eq!(other.mode, StickMode::Gamepad);
+        assert_eq!(other.button(Button::B), Some(Action::Hover));
+    }
+
+    #[test]
+    fn builtin_default() {
+        let profile = InputProfile::parse("", None).unwrap();
+        assert_eq!(profile.name, DEFAULT_PROFILE);
+        assert_eq!(profile.button(Button::Start), Some(Action::TakeOff));
+        assert!(matches!(
+            InputProfile::parse("", Some("mine")),
+            Err(ProfileError::UnknownProfile(name)) if name == "mine"
+        ));
+    }
+
+    #[test]
+    fn shipped_profiles() {
+        let content = include_str!("../doc/input.toml");
+        let shipped = InputProfile::parse(content, None).unwrap();
+        let builtin = InputProfile::default();
+        assert_eq!(shipped.mode, builtin.mode);
+        assert_eq!(shipped.buttons, builtin.buttons);
+        assert_eq!(shipped.axes, builtin.axes);
+        assert_eq!(shipped.keys, builtin.keys);
+        assert_eq!(shipped.chords, builtin.chords);
+        for (axis, curve) in &shipped.curves {
+            assert_eq!(*curve, builtin.curve(*axis), "{axis:?}");
+        }
+        assert!(InputProfile::parse(content, Some("swapped")).is_ok());
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert_eq!(
+            error("[profiles.default.buttons]\nz = \"hover\""),
+            "profile `default`: unknown button `z`"
+        );
+        assert_eq!(
+            error("[profiles.default.buttons]\na = \"fly_away\""),
+            "profile `default`: unknown action `fly_away` bound to `a`"
+        );
+        assert_eq!(
+            error("[profiles.default.axes]\nleftz = \"forward\""),
+            "profile `default`: unknown axis `leftz`"
+        );
+        // axes take motions only
+        assert_eq!(
+            error("[profiles.default.axes]\nleftx = \"hover\""),
+            "profile `default`: unknown action `hover` bound to `leftx`"
+        );
+        assert_eq!(
+            error("[profiles.default.keys]\nNoSuchKey = \"hover\""),
+            "profile `default`: unknown key `NoSuchKey`"
+        );
+        assert_eq!(
+            error("[profiles.default.keys]\nQ = \"-hover\""),
+            "profile `default`: unknown action `-hover` bound to `Q`"
+        );
+        assert_eq!(
+            error("[profiles.default]\nmode = \"mode5\""),
+            "profile `default`: unknown stick mode `mode5`"
+        );
+        assert!(matches!(
+            InputProfile::parse("[profiles.default.curves.leftx]\nexpo = 2.0", None),
+            Err(ProfileError::InvalidCurve { .. })
+        ));
+        assert!(matches!(
+            InputProfile::parse("[profiles.default]\nbutons = {}", None),
+            Err(ProfileError::Parse(..))
+        ));
+    }
+
+    #[test]
+    fn bad_chords() {
+        for chord in ["y", "y+", "+dpup", "y+dpup+a", "y+nope", "y + dpup", "y+y"] {
+            assert_eq!(
+                error(&format!(
+                    "[profiles.default.chords]\n\"{chord}\" = \"hover\""
+                )),
+                format!("profile `default`: unknown chord `{chord}`")
+            );
+        }
+        assert_eq!(
+            error("[profiles.default.chords]\n\"y+a\" = \"flip\""),
+            "profile `default`: unknown action `flip` bound to `y+a`"
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        // the same input under two spellings, either one is reported
+        let key = error("[profiles.default.keys]\nW = \"forward\"\nw = \"hover\"");
+        assert!(
+            key == "profile `default`: key `W` bound twice"
+                || key == "profile `default`: key `w` bound twice",
+            "{key}"
+        );
+        assert!(
+            error("[profiles.default.buttons]\na = \"hover\"\nA = \"land\"")
+                .ends_with("bound twice")
+        );
+        assert!(
+            error("[profiles.default.axes]\nleftx = \"up\"\nLEFTX = \"down\"")
+                .ends_with("bound twice")
+        );
+        assert!(
+            error("[profiles.default.chords]\n\"y+a\" = \"hover\"\n\"Y+A\" = \"land\"")
+                .ends_with("bound twice")
+        );
+        // and twice under the same one
+        assert!(matches!(
+            InputProfile::parse(
+                "[profiles.default.buttons]\na = \"hover\"\na = \"land\"",
+                None
+            ),
+            Err(ProfileError::Parse(..))
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+//! Hand launch and landing: throw-and-go takeoff and landing on the pilot's palm.
+//!
+//! Both are started with the take off button like a normal takeoff or landing, but only on the
+//! second press, so a stray press doesn't spin the motors up in someone's hand.
+
+use std::time::{Duration, Instant};
+
+use crate::drone::Drone;
+
+/// Second press of take off within this long confirms.
+const CONFIRM_TIME: Duration = Duration::from_secs(5);
+/// The drone stops its motors when it isn't thrown in about 5 s.
+const THROW_TIMEOUT: Duration = Duration::from_secs(8);
+const PALM_LANDING_TIMEOUT: Duration = Duration::from_secs(15);
+/// Outcomes stay on the HUD this long.
+const RESULT_TIME: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LaunchMode {
+    #[default]
+    Standard,
+    /// throw-and-go takeoff, normal landing
+    Throw,
+    /// normal takeoff, landing on the palm
+    Palm,
+    /// throw-and-go takeoff and palm landing
+    Hand,
+}
+
+impl LaunchMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Standard => Self::Throw,
+            Self::Throw => Self::Palm,
+            Self::Palm => Self::Hand,
+            Self::Hand => Self::Standard,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Standard => "STANDARD",
+            Self::Throw => "THROW & GO",
+            Self::Palm => "PALM LAND",
+            Self::Hand => "THROW & GO + PALM LAND",
+        }
+    }
+
+    fn throw(self) -> bool {
+        matches!(self, Self::Throw | Self::Hand)
+    }
+
+    fn palm(self) -> bool {
+        matches!(self, Self::Palm | Self::Hand)
+    }
+}
+
+/// What the take off button does now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Launch {
+    TakeOff,
+    Land,
+    Throw,
+    PalmLand,
+}
+
+impl Launch {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::TakeOff => "takeoff",
+            Self::Land => "land",
+            Self::Throw => "throw takeoff",
+            Self::PalmLand => "palm land",
+        }
+    }
+
+    pub fn landing(self) -> bool {
+        matches!(self, Self::Land | Self::PalmLand)
+    }
+
+    /// Sends the command to the drone.
+    pub fn send<D: Drone>(self, drone: &mut D) {
+        match self {
+            Self::TakeOff => drone.takeoff(),
+            Self::Land => drone.land(),
+            Self::Throw => drone.throw_takeoff(),
+            Self::PalmLand => drone.palm_land(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Idle,
+    /// waiting for the second press
+    Confirm(Launch, Instant),
+    Throwing(Instant),
+    PalmLanding(Instant),
+    Done(&'static str, Instant),
+}
+
+#[derive(Debug)]
+pub struct Launcher {
+    mode: LaunchMode,
+    state: State,
+}
+
+impl Default for Launcher {
+    fn default() -> Self {
+        Self {
+            mode: LaunchMode::default(),
+            state: State::Idle,
+        }
+    }
+}
+
+impl Launcher {
+    pub fn mode(&self) -> LaunchMode {
+        self.mode
+    }
+
+    /// Next mode, a pending confirmation is dropped.
+    pub fn cycle(&mut self) {
+        self.mode = self.mode.next();
+        self.state = State::Idle;
+        tracing::info!("launch mode: {}", self.mode.label());
+    }
+
+    /// The take off button was pressed at `now`, `None` while a hand launch waits for
+    /// confirmation.
+    pub fn request(&mut self, flying: bool, now: Instant) -> Option<Launch> {
+        let launch = match (flying, self.mode) {
+            (false, mode) if mode.throw() => Launch::Throw,
+            (false, _) => Launch::TakeOff,
+            (true, mode) if mode.palm() => Launch::PalmLand,
+            (true, _) => Launch::Land,
+        };
+        match (launch, &self.state) {
+            (Launch::TakeOff | Launch::Land, _) => {
+                self.state = State::Idle;
+                Some(launch)
+            }
+            (_, State::Confirm(pending, at))
+                if *pending == launch && now.saturating_duration_since(*at) < CONFIRM_TIME =>
+            {
+                self.state = match launch {
+                    Launch::Throw => State::Throwing(now),
+                    _ => State::PalmLanding(now),
+                };
+                Some(launch)
+            }
+            _ => {
+                self.state = State::Confirm(launch, now);
+                None
+            }
+        }
+    }
+
+    /// Follows a hand launch or landing.
+    pub fn update(&mut self, flying: bool, now: Instant) {
+        let since = |at: Instant| now.saturating_duration_since(at);
+        self.state = match self.state {
+            State::Confirm(_, at) if since(at) >= CONFIRM_TIME => State::Idle,
+            State::Throwing(_) if flying => State::Done("airborne", now),
+            State::Throwing(at) if since(at) >= THROW_TIMEOUT => {
+                tracing::warn!("throw takeoff: not thrown in time");
+                State::Done("not thrown in time", now)
+            }
+            State::PalmLanding(_) if !flying => State::Done("landed", now),
+            State::PalmLanding(at) if since(at) >= PALM_LANDING_TIMEOUT => {
+                State::Done("palm landing timed out", now)
+            }
+            State::Done(_, at) if since(at) >= RESULT_TIME => State::Idle,
+            _ => return,
+        };
+    }
+
+    /// HUD line: the mode and what's going on.
+    pub fn status(&self) -> String {
+        let state = match self.state {
+            State::Idle => return self.mode.label().to_owned(),
+            State::Confirm(Launch::Throw, _) => "take off again to arm the throw",
+            State::Confirm(_, _) => "take off again to land on the palm",
+            State::Throwing(_) => "motors on, throw the drone",
+            State::PalmLanding(_) => "hold your palm under the drone",
+            State::Done(result, _) => result,
+        };
+        format!("{}: {state}", self.mode.label())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn in_mode(mode: LaunchMode) -> Launcher {
+        Launcher {
+            mode,
+            state: State::Idle,
+        }
+    }
+
+    #[test]
+    fn standard_goes_on_the_first_press() {
+        let mut launcher = Launcher::default();
+        let now = Instant::now();
+        assert_eq!(launcher.request(false, now), Some(Launch::TakeOff));
+        assert_eq!(launcher.request(true, now), Some(Launch::Land));
+        assert_eq!(launcher.status(), "STANDARD");
+        // the other half of a hand mode too
+        let mut launcher = in_mode(LaunchMode::Throw);
+        assert_eq!(launcher.request(true, now), Some(Launch::Land));
+        let mut launcher = in_mode(LaunchMode::Palm);
+        assert_eq!(launcher.request(false, now), Some(Launch::TakeOff));
+    }
+
+    #[test]
+    fn second_press_in_time_arms() {
+        let mut launcher = in_mode(LaunchMode::Hand);
+        let now = Instant::now();
+        assert_eq!(launcher.request(false, now), None);
+        assert_eq!(
+            launcher.status(),
+            "THROW & GO + PALM LAND: take off again to arm the throw"
+        );
+        assert_eq!(launcher.request(false, now + secs(4)), Some(Launch::Throw));
+        assert_eq!(
+            launcher.status(),
+            "THROW & GO + PALM LAND: motors on, throw the drone"
+        );
+
+        assert_eq!(launcher.request(true, now + secs(10)), None);
+        assert_eq!(
+            launcher.status(),
+            "THROW & GO + PALM LAND: take off again to land on the palm"
+        );
+        assert_eq!(
+            launcher.request(true, now + secs(11)),
+            Some(Launch::PalmLand)
+        );
+    }
+
+    #[test]
+    fn late_press_asks_again() {
+        let mut launcher = in_mode(LaunchMode::Throw);
+        let now = Instant::now();
+        assert_eq!(launcher.request(false, now), None);
+        assert_eq!(launcher.request(false, now + CONFIRM_TIME), None);
+        assert_eq!(
+            launcher.status(),
+            "THROW & GO: take off again to arm the throw"
+        );
+        assert_eq!(
+            launcher.request(false, now + CONFIRM_TIME + secs(1)),
+            Some(Launch::Throw)
+        );
+
+        // the prompt goes away on its own
+        let mut launcher = in_mode(LaunchMode::Throw);
+        launcher.request(false, now);
+        launcher.update(false, now + secs(1));
+        assert!(matches!(launcher.state, State::Confirm(..)));
+        launcher.update(false, now + CONFIRM_TIME);
+        assert_eq!(launcher.status(), "THROW & GO");
+    }
+
+    #[test]
+    fn cycle_drops_the_confirmation() {
+        let mut launcher = in_mode(LaunchMode::Palm);
+        let now = Instant::now();
+        assert_eq!(launcher.request(true, now), None);
+        launcher.cycle();
+        assert_eq!(launcher.mode(), LaunchMode::Hand);
+        assert_eq!(launcher.status(), "THROW & GO + PALM LAND");
+        // starts over in the new mode
+        assert_eq!(launcher.request(true, now + secs(1)), None);
+        for _ in 0..4 {
+            launcher.cycle();
+        }
+        assert_eq!(launcher.mode(), LaunchMode::Hand);
+    }
+
+    #[test]
+    fn throw_outcomes() {
+        let now = Instant::now();
+        let mut launcher = in_mode(LaunchMode::Throw);
+        launcher.request(false, now);
+        launcher.request(false, now);
+        launcher.update(false, now + secs(1));
+        launcher.update(true, now + secs(2));
+        assert_eq!(launcher.status(), "THROW & GO: airborne");
+        launcher.update(true, now + secs(4));
+        assert_eq!(launcher.status(), "THROW & GO: airborne");
+        launcher.update(true, now + secs(2) + RESULT_TIME);
+        assert_eq!(launcher.status(), "THROW & GO");
+
+        launcher.request(false, now);
+        launcher.request(false, now);
+        launcher.update(false, now + THROW_TIMEOUT - secs(1));
+        assert_eq!(launcher.status(), "THROW & GO: motors on, throw the drone");
+        launcher.update(false, now + THROW_TIMEOUT);
+        assert_eq!(launcher.status(), "THROW & GO: not thrown in time");
+    }
+
+    #[test]
+    fn palm_outcomes() {
+        let now = Instant::now();
+        let mut launcher = in_mode(LaunchMode::Palm);
+        launcher.request(true, now);
+        launcher.request(true, now);
+        launcher.update(true, now + secs(3));
+        assert_eq!(
+            launcher.status(),
+            "PALM LAND: hold your palm under the drone"
+        );
+        launcher.update(false, now + secs(4));
+        assert_eq!(launcher.status(), "PALM LAND: landed");
+
+        let mut launcher = in_mode(LaunchMode::Palm);
+        launcher.request(true, now);
+        launcher.request(true, now);
+        launcher.update(true, now + PALM_LANDING_TIMEOUT);
+        assert_eq!(launcher.status(), "PALM LAND: palm landing timed out");
+    }
+
+    #[test]
+    fn landings() {
+        assert!(Launch::Land.landing());
+        assert!(Launch::PalmLand.landing());
+        assert!(!Launch::Throw.landing());
+        assert!(!Launch::TakeOff.landing());
+    }
+}
@@ -1,6 +1,32 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod axis;
+pub mod battery;
+pub mod config;
+pub mod drone;
+pub mod failsafe;
+#[cfg(any(test, feature = "fake-drone"))]
+pub mod fake_drone;
+pub mod flight;
+pub mod flip;
+pub mod geofence;
 pub mod help;
+pub mod hold;
+pub mod input;
+pub mod launch;
+pub mod maneuver;
+pub mod mission;
+pub mod nav;
+pub mod overlay;
+pub mod photo;
+pub mod recorder;
+pub mod replay;
+pub mod script;
+pub mod sdk;
+pub mod sim;
+pub mod sound;
+pub mod survey;
+pub mod telemetry;
 pub mod ui;
-pub mod utils;
+pub mod video_recorder;
@@ -1,16 +1,259 @@
-use std::{sync::mpsc, thread, time::Duration};
+use std::{
+    fs::File,
+    path::{Component, Path, PathBuf},
+    process,
+    sync::mpsc,
+    sync::Mutex,
+};
 
+use clap::Parser;
 // use rust_gamepad::gamepad::{self, Gamepad, GamepadState};
 use rust_tello::TelloController;
-use rust_tello_controller::{help::XBOX, ui::UI};
+use rust_tello_controller::{
+    config::Config,
+    drone::NoDrone,
+    help::XBOX,
+    input::{InputProfile, StickMode},
+    mission::Mission,
+    overlay,
+    recorder::{RecordFormat, Recorder},
+    replay,
+    script::Script,
+    sim::{SimConfig, Simulator},
+    ui::{UiSettings, UI},
+    video_recorder::VideoRecorder,
+};
+
+/// `TelloController` saves the pictures it takes here, it can't be told otherwise.
+const TELLO_PICTURES_DIR: &str = "./save_pics";
+
+/// Control DJI Ryze Tello with a gamepad.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// config file, see doc/config.toml
+    #[arg(long, default_value = "config.toml")]
+    config: PathBuf,
+    /// window width
+    #[arg(long)]
+    width: Option<u32>,
+    /// window height
+    #[arg(long)]
+    height: Option<u32>,
+    /// fullscreen on the current display, width and height are ignored
+    #[arg(long)]
+    fullscreen: bool,
+    /// trace, debug, info, warn or error
+    #[arg(long, default_value = "trace")]
+    log_level: tracing::Level,
+    /// log to this file instead of stdout
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+    /// pictures shown in the carousel and saved by the simulator, simulator and replay only
+    #[arg(long)]
+    pictures_dir: Option<PathBuf>,
+    /// input bindings file, see doc/input.toml
+    #[arg(long)]
+    input: Option<PathBuf>,
+    /// input profile from the bindings file
+    #[arg(long)]
+    profile: Option<String>,
+    /// startup stick mode: gamepad, mode1, mode2, mode3 or mode4
+    #[arg(long, value_parser = parse_stick_mode)]
+    stick_mode: Option<StickMode>,
+    /// fly the software simulator instead of the drone
+    #[arg(long, conflicts_with = "replay")]
+    simulator: bool,
+    /// H.264 elementary stream used as the simulator's video feed
+    #[arg(long, requires = "simulator")]
+    sim_video: Option<PathBuf>,
+    /// replay a recorded flight log instead of flying
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// record flights into this directory
+    #[arg(long, conflicts_with = "no_record")]
+    record: Option<PathBuf>,
+    /// jsonl or csv
+    #[arg(long)]
+    record_format: Option<String>,
+    /// don't record flights
+    #[arg(long)]
+    no_record: bool,
+    /// mission flown with the mission button, see doc/mission.toml
+    #[arg(long)]
+    mission: Option<PathBuf>,
+    /// Rhai flight script run with the script button, see doc/script.rhai
+    #[arg(long)]
+    script: Option<PathBuf>,
+    /// take Tello SDK text commands over TCP on this address, e.g. 127.0.0.1:8889
+    #[arg(long)]
+    command_port: Option<String>,
+    /// take Tello SDK text commands on stdin
+    #[arg(long)]
+    stdin_commands: bool,
+    /// burn the telemetry of `--flight-log` into a recorded video and exit
+    #[arg(long, requires = "flight_log")]
+    burn_overlay: Option<PathBuf>,
+    /// flight log for `--burn-overlay`
+    #[arg(long)]
+    flight_log: Option<PathBuf>,
+    /// output of `--burn-overlay`, `<video>-overlay.mp4` by default
+    #[arg(long)]
+    overlay_out: Option<PathBuf>,
+}
+
+fn parse_stick_mode(mode: &str) -> Result<StickMode, String> {
+    StickMode::parse(mode).ok_or_else(|| format!("unknown stick mode `{mode}`"))
+}
+
+fn parse_record_format(format: &str) -> Result<RecordFormat, String> {
+    match format {
+        "jsonl" => Ok(RecordFormat::JsonLines),
+        "csv" => Ok(RecordFormat::Csv),
+        _ => Err(format!(
+            "unknown record format `{format}`, use jsonl or csv"
+        )),
+    }
+}
+
+// `./save_pics` and `save_pics` are the same folder
+fn same_dir(a: &Path, b: &Path) -> bool {
+    let here = |c: &Component| *c != Component::CurDir;
+    a.components().filter(here).eq(b.components().filter(here))
+}
+
+fn exit_on_error<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+    match result {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("{e}");
+            process::exit(1);
+        }
+    }
+}
 
 fn main() {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::TRACE)
-        .init();
+    let args = Args::parse();
+
+    let logger = tracing_subscriber::fmt().with_max_level(args.log_level);
+    match args.log_file {
+        Some(ref path) => match File::create(path) {
+            Ok(file) => logger.with_ansi(false).with_writer(Mutex::new(file)).init(),
+            Err(e) => {
+                eprintln!("can't create log file {}: {e}", path.display());
+                process::exit(1);
+            }
+        },
+        None => logger.init(),
+    }
 
     tracing::info!("{}", XBOX);
 
+    let config = exit_on_error(Config::load(&args.config));
+
+    if let (Some(video), Some(flight_log)) = (args.burn_overlay, args.flight_log) {
+        let out = args.overlay_out.unwrap_or_else(|| {
+            let stem = video.file_stem().unwrap_or_default().to_string_lossy();
+            video.with_file_name(format!("{stem}-overlay.mp4"))
+        });
+        exit_on_error(overlay::burn(
+            &config.video.ffmpeg,
+            &video,
+            &flight_log,
+            &out,
+        ));
+        return;
+    }
+
+    let input_path = args.input.unwrap_or(config.input);
+    let profile = args.profile.or(config.profile);
+    let mut input = exit_on_error(InputProfile::load(&input_path, profile.as_deref()));
+    if let Some(mode) = args.stick_mode {
+        input.mode = mode;
+    }
+    let record_format = exit_on_error(parse_record_format(
+        &args.record_format.unwrap_or(config.record_format),
+    ));
+
+    let mission = args
+        .mission
+        .or(config.mission)
+        .map(|path| exit_on_error(Mission::load(&path)));
+    let script = args
+        .script
+        .or(config.script.file.clone())
+        .map(|path| exit_on_error(Script::load(&path)));
+
+    let mut commands = config.commands;
+    commands.port = args.command_port.or(commands.port);
+    commands.stdin |= args.stdin_commands;
+
+    let pictures_dir = args.pictures_dir.unwrap_or(config.pictures_dir);
+    // the carousel would watch a folder the drone never saves to
+    if !args.simulator
+        && args.replay.is_none()
+        && !same_dir(&pictures_dir, TELLO_PICTURES_DIR.as_ref())
+    {
+        tracing::error!(
+            "pictures dir {} needs --simulator or --replay, the Tello saves to {TELLO_PICTURES_DIR}",
+            pictures_dir.display()
+        );
+        process::exit(1);
+    }
+    let mut ui = UI::new(UiSettings {
+        width: args.width.unwrap_or(config.width),
+        height: args.height.unwrap_or(config.height),
+        fullscreen: args.fullscreen || config.fullscreen,
+        pictures_dir: pictures_dir.clone(),
+        input,
+        battery: config.battery,
+        failsafe: config.failsafe,
+        geofence: config.geofence,
+        altitude_hold: config.altitude_hold,
+        heading_hold: config.heading_hold,
+        mission,
+        survey: config.survey.map(|survey| survey.mission()),
+        script,
+        script_config: config.script,
+        flip: config.flip,
+        commands,
+    });
+    let record = args.record.is_some() || (config.record && !args.no_record);
+    // replays are recordings already
+    if record && args.replay.is_none() {
+        let dir = args.record.unwrap_or(config.record_dir);
+        ui.set_recorder(Recorder::spawn(dir, record_format));
+    }
+
+    // replays have no video
+    if args.replay.is_none() {
+        ui.set_video_recorder(VideoRecorder::spawn(config.video));
+    }
+
+    if let Some(path) = args.replay {
+        let (update_tx, update_rx) = mpsc::channel();
+        // no video in the flight logs, keep the sender so the video widget just idles
+        let (_video_tx, video_rx) = mpsc::channel();
+        ui.set_replay(exit_on_error(replay::start(&path, update_tx)));
+        // pilot input must not reach a drone that happens to be around
+        ui.mainloop(NoDrone, update_rx, video_rx);
+        return;
+    }
+
+    if args.simulator {
+        let config = SimConfig {
+            video_clip: args.sim_video,
+            picture: Some(PathBuf::from("doc/pic_0044.jpg")),
+            pictures_dir,
+        };
+        let (update_tx, update_rx) = mpsc::channel();
+        let (video_tx, video_rx) = mpsc::channel();
+        let sim = Simulator::spawn(config, update_tx, video_tx);
+        tracing::info!("flying the simulator");
+        ui.mainloop(sim, update_rx, video_rx);
+        return;
+    }
+
     let mut tello = TelloController::new();
 
     let (update_tx, update_rx) = rust_tello::comm_channel();
@@ -31,6 +274,5 @@ fn main() {
     // }
     tello.start_stick_update();
     tracing::info!("use gamepad to fly the drone");
-    let mut ui = UI::new(3440, 1440);
     ui.mainloop(tello, update_rx, video_rx);
 }
@@ -0,0 +1,158 @@
+//! Single maneuvers flown tick by tick from the UI loop, for scripts and the command console.
+//!
+//! Moves, turns and climbs are flown closed loop as one step missions, takeoff and landing
+//! wait for the drone to report the new state.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    drone::Flip,
+    mission::{Mission, MissionRunner, Step},
+    nav::Navigation,
+    telemetry::StickSample,
+};
+
+const TAKEOFF_TIMEOUT: Duration = Duration::from_secs(15);
+const LANDING_TIMEOUT: Duration = Duration::from_secs(15);
+/// m/s, takeoff is done once the climb has settled
+const SETTLED_CLIMB: f32 = 0.2;
+/// The drone takes about this long to flip and stabilize.
+const FLIP_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Maneuver {
+    TakeOff,
+    Land,
+    Step(Step),
+    Flip(Flip),
+}
+
+impl Maneuver {
+    pub fn label(&self) -> String {
+        match self {
+            Maneuver::TakeOff => "takeoff".to_owned(),
+            Maneuver::Land => "land".to_owned(),
+            Maneuver::Step(step) => step.label(),
+            Maneuver::Flip(flip) => format!("flip {}", flip.label()),
+        }
+    }
+}
+
+/// What the maneuver wants from the drone this tick.
+#[derive(Debug, Default)]
+pub struct ManeuverTick {
+    pub sticks: StickSample,
+    pub take_picture: bool,
+    pub take_off: bool,
+    pub land: bool,
+    pub flip: Option<Flip>,
+}
+
+#[derive(Debug)]
+enum State {
+    TakeOff,
+    Land,
+    Step(MissionRunner),
+    Flip,
+}
+
+#[derive(Debug)]
+pub struct ManeuverRunner {
+    maneuver: Maneuver,
+    state: State,
+    started: Instant,
+}
+
+impl ManeuverRunner {
+    /// Starts `maneuver`, `Ok(None)` when the drone is there already and `Err` when it can't be
+    /// flown now. Moves and turns use `speed` as max stick command.
+    pub fn start(
+        maneuver: Maneuver,
+        nav: &Navigation,
+        speed: f32,
+        step_timeout: f32,
+        tick: &mut ManeuverTick,
+    ) -> Result<Option<Self>, String> {
+        let state = match maneuver {
+            Maneuver::TakeOff if nav.flying => return Ok(None),
+            Maneuver::TakeOff => {
+                tick.take_off = true;
+                State::TakeOff
+            }
+            Maneuver::Land if !nav.flying => return Ok(None),
+            Maneuver::Land => {
+                tick.land = true;
+                State::Land
+            }
+            Maneuver::Step(ref step) if !nav.flying && *step != Step::Photo => {
+                return Err("not flying".to_owned())
+            }
+            Maneuver::Step(ref step) => {
+                let mission = Mission {
+                    name: maneuver.label(),
+                    speed,
+                    step_timeout,
+                    steps: vec![step.clone()],
+                };
+                State::Step(MissionRunner::start(mission, nav))
+            }
+            Maneuver::Flip(_) if !nav.flying => return Err("not flying".to_owned()),
+            Maneuver::Flip(flip) => {
+                tick.flip = Some(flip);
+                State::Flip
+            }
+        };
+        Ok(Some(Self {
+            maneuver,
+            state,
+            started: Instant::now(),
+        }))
+    }
+
+    pub fn maneuver(&self) -> &Maneuver {
+        &self.maneuver
+    }
+
+    /// Flies one tick further, `Some` with the result once done.
+    pub fn update(
+        &mut self,
+        nav: &Navigation,
+        tick: &mut ManeuverTick,
+    ) -> Option<Result<(), String>> {
+        let elapsed = self.started.elapsed();
+        let result = match self.state {
+            State::TakeOff => {
+                if nav.flying && nav.climb.abs() < SETTLED_CLIMB && elapsed.as_secs() >= 1 {
+                    Some(Ok(()))
+                } else if elapsed > TAKEOFF_TIMEOUT {
+                    Some(Err("takeoff timed out".to_owned()))
+                } else {
+                    None
+                }
+            }
+            State::Land => {
+                if !nav.flying {
+                    Some(Ok(()))
+                } else if elapsed > LANDING_TIMEOUT {
+                    Some(Err("landing timed out".to_owned()))
+                } else {
+                    None
+                }
+            }
+            State::Step(ref mut runner) => match runner.update(nav) {
+                Ok(command) => {
+                    tick.sticks = command.sticks;
+                    tick.take_picture = command.take_picture;
+                    runner.finished().then_some(Ok(()))
+                }
+                Err(e) => Some(Err(e)),
+            },
+            State::Flip => (elapsed >= FLIP_TIME).then_some(Ok(())),
+        };
+        if result.is_some() {
+            // the next maneuver starts from a stop, not with the last command
+            tick.sticks = StickSample::default();
+        }
+        result
+    }
+}
@@ -0,0 +1,582 @@
+//! Missions: relative moves, turns, altitude changes, hovers and photos flown one after another.
+//!
+//! Each step is flown closed loop on `nav::Navigation`, a PD controller on the position error
+//! and the measured velocity gives the stick commands. The pilot takes over by touching a stick.
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use sdl2::{pixels::Color, rect::Point, rect::Rect, render::Canvas, video::Window};
+use serde::Deserialize;
+
+use crate::{
+    nav::{self, Navigation},
+    telemetry::StickSample,
+};
+
+const POSITION_GAIN: f32 = 0.5; // stick per meter
+const VELOCITY_GAIN: f32 = 0.3; // stick per m/s
+const YAW_GAIN: f32 = 0.02; // stick per degree
+const POSITION_TOLERANCE: f32 = 0.2; // m
+const HEIGHT_TOLERANCE: f32 = 0.1; // m
+const YAW_TOLERANCE: f32 = 3.0; // degrees
+const SETTLED_SPEED: f32 = 0.15; // m/s
+const PHOTO_PAUSE: Duration = Duration::from_millis(500);
+/// Second press of the mission or survey button within this long starts it.
+const CONFIRM_TIME: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    /// meters relative to the heading at the start of the step
+    Move {
+        #[serde(default)]
+        forward: f32,
+        #[serde(default)]
+        right: f32,
+    },
+    /// degrees, positive clockwise
+    Turn {
+        degrees: f32,
+    },
+    /// meters, negative goes down
+    Climb {
+        meters: f32,
+    },
+    /// meters above the takeoff point
+    Altitude {
+        meters: f32,
+    },
+    Hover {
+        seconds: f32,
+    },
+    Photo,
+}
+
+impl Step {
+    pub fn label(&self) -> String {
+        match self {
+            Step::Move { forward, right } => {
+                format!("move {forward:+.1} m fwd {right:+.1} m right")
+            }
+            Step::Turn { degrees } => format!("turn {degrees:+.0} deg"),
+            Step::Climb { meters } => format!("climb {meters:+.1} m"),
+            Step::Altitude { meters } => format!("altitude {meters:.1} m"),
+            Step::Hover { seconds } => format!("hover {seconds:.0} s"),
+            Step::Photo => "photo".to_owned(),
+        }
+    }
+}
+
+fn default_speed() -> f32 {
+    0.3
+}
+
+fn default_step_timeout() -> f32 {
+    30.0
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mission {
+    pub name: String,
+    /// max stick command, 0.0..1.0
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// seconds, the mission is aborted when a step takes longer
+    #[serde(default = "default_step_timeout")]
+    pub step_timeout: f32,
+    #[serde(rename = "step")]
+    pub steps: Vec<Step>,
+}
+
+impl Mission {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mission: Self =
+            toml::from_str(&content).map_err(|e| format!("{}: {e}", path.display()))?;
+        mission
+            .validate()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(mission)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.speed > 0.0 && self.speed <= 1.0) {
+            return Err(format!("speed {} not in 0.0..=1.0", self.speed));
+        }
+        if !(self.step_timeout > 0.0 && self.step_timeout.is_finite()) {
+            return Err(format!(
+                "step_timeout {} must be positive",
+                self.step_timeout
+            ));
+        }
+        if self.steps.is_empty() {
+            return Err("no steps".to_owned());
+        }
+        for (n, step) in self.steps.iter().enumerate() {
+            let valid = match *step {
+                Step::Move { forward, right } => forward.is_finite() && right.is_finite(),
+                Step::Turn { degrees } => degrees.is_finite(),
+                Step::Climb { meters } => meters.is_finite(),
+                Step::Altitude { meters } => meters >= 0.0 && meters.is_finite(),
+                Step::Hover { seconds } => seconds >= 0.0 && seconds.is_finite(),
+                Step::Photo => true,
+            };
+            if !valid {
+                return Err(format!("step {}: invalid value", n + 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Positions (north, east) after each step when started at `position` facing `yaw`.
+    pub fn plan(&self, position: (f32, f32), yaw: f32) -> Vec<(f32, f32)> {
+        let (mut position, mut yaw) = (position, yaw);
+        let mut path = vec![position];
+        for step in &self.steps {
+            match *step {
+                Step::Move { forward, right } => {
+                    let (north, east) = nav::to_world(yaw, (forward, right));
+                    position = (position.0 + north, position.1 + east);
+                    path.push(position);
+                }
+                Step::Turn { degrees } => yaw += degrees,
+                _ => {}
+            }
+        }
+        path
+    }
+}
+
+/// Missions start on the second press, a slipped thumb doesn't fly the drone off on its own.
+#[derive(Debug, Default)]
+pub struct StartConfirm {
+    /// survey or not, first pressed at
+    pending: Option<(bool, Instant)>,
+}
+
+impl StartConfirm {
+    /// True when this press confirms the first one for the same mission.
+    pub fn press(&mut self, survey: bool, now: Instant) -> bool {
+        match self.pending.take() {
+            Some((pending, at))
+                if pending == survey && now.saturating_duration_since(at) < CONFIRM_TIME =>
+            {
+                true
+            }
+            _ => {
+                self.pending = Some((survey, now));
+                false
+            }
+        }
+    }
+}
+
+/// Where the current step is heading.
+#[derive(Debug, Clone, Copy)]
+struct Target {
+    position: (f32, f32),
+    height: f32,
+    yaw: f32,
+}
+
+/// What the executor wants from the drone this frame.
+#[derive(Debug, Default)]
+pub struct MissionCommand {
+    pub sticks: StickSample,
+    pub take_picture: bool,
+}
+
+#[derive(Debug)]
+pub struct MissionRunner {
+    mission: Mission,
+    /// position and yaw the mission was started at
+    origin: ((f32, f32), f32),
+    step: usize,
+    step_started: Instant,
+    target: Target,
+    /// degrees still to turn in a turn step, counted from the yaw changes so full turns and
+    /// the direction aren't lost to wrapping
+    turn_left: f32,
+    /// yaw the turn was last counted at
+    last_yaw: f32,
+    photo_taken: bool,
+    photos: usize,
+}
+
+fn wrap_degrees(degrees: f32) -> f32 {
+    (degrees + 180.0).rem_euclid(360.0) - 180.0
+}
+
+impl MissionRunner {
+    pub fn start(mission: Mission, nav: &Navigation) -> Self {
+        tracing::info!(
+            "mission `{}` started, {} steps",
+            mission.name,
+            mission.steps.len()
+        );
+        let target = Target {
+            position: nav.position,
+            height: nav.height,
+            yaw: nav.yaw,
+        };
+        let mut runner = Self {
+            mission,
+            origin: (nav.position, nav.yaw),
+            step: 0,
+            step_started: Instant::now(),
+            target,
+            turn_left: 0.0,
+            last_yaw: nav.yaw,
+            photo_taken: false,
+            photos: 0,
+        };
+        runner.enter_step(nav);
+        runner
+    }
+
+    // targets are chained from the previous target, not from where the drone ended up,
+    // so errors don't add up over the steps
+    fn enter_step(&mut self, nav: &Navigation) {
+        self.step_started = Instant::now();
+        self.photo_taken = false;
+        let Some(step) = self.mission.steps.get(self.step) else {
+            return;
+        };
+        tracing::info!(
+            "mission step {}/{}: {}",
+            self.step + 1,
+            self.mission.steps.len(),
+            step.label()
+        );
+        match *step {
+            Step::Move { forward, right } => {
+                let (north, east) = nav::to_world(self.target.yaw, (forward, right));
+                self.target.position.0 += north;
+                self.target.position.1 += east;
+            }
+            Step::Turn { degrees } => {
+                // the turn ends on the chained target, whichever way the drone is off it now
+                self.turn_left = degrees + wrap_degrees(self.target.yaw - nav.yaw);
+                self.last_yaw = nav.yaw;
+                self.target.yaw = wrap_degrees(self.target.yaw + degrees);
+            }
+            Step::Climb { meters } => self.target.height = (self.target.height + meters).max(0.0),
+            Step::Altitude { meters } => self.target.height = meters,
+            Step::Hover { .. } | Step::Photo => {}
+        }
+    }
+
+    pub fn mission(&self) -> &Mission {
+        &self.mission
+    }
+
+    pub fn finished(&self) -> bool {
+        self.step >= self.mission.steps.len()
+    }
+
+    /// Stick commands towards the current target, `Err` when the mission has to be aborted.
+    pub fn update(&mut self, nav: &Navigation) -> Result<MissionCommand, String> {
+        let mut command = MissionCommand::default();
+        let Some(step) = self.mission.steps.get(self.step).cloned() else {
+            return Ok(command);
+        };
+        let elapsed = self.step_started.elapsed();
+        if elapsed.as_secs_f32() > self.mission.step_timeout {
+            return Err(format!(
+                "step {} ({}) timed out",
+                self.step + 1,
+                step.label()
+            ));
+        }
+
+        // hold the target in all axes, whatever the step
+        let speed = self.mission.speed;
+        let error = (
+            self.target.position.0 - nav.position.0,
+            self.target.position.1 - nav.position.1,
+        );
+        let world = (
+            POSITION_GAIN * error.0 - VELOCITY_GAIN * nav.velocity.0,
+            POSITION_GAIN * error.1 - VELOCITY_GAIN * nav.velocity.1,
+        );
+        let (forward, right) = nav::to_body(nav.yaw, world);
+        let height_error = self.target.height - nav.height;
+        let yaw_error = if let Step::Turn { .. } = step {
+            self.turn_left -= wrap_degrees(nav.yaw - self.last_yaw);
+            self.last_yaw = nav.yaw;
+            self.turn_left
+        } else {
+            wrap_degrees(self.target.yaw - nav.yaw)
+        };
+        command.sticks = StickSample {
+            forward: forward.clamp(-speed, speed),
+            right: right.clamp(-speed, speed),
+            up: (POSITION_GAIN * height_error - VELOCITY_GAIN * nav.climb).clamp(-speed, speed),
+            turn_clockwise: (YAW_GAIN * yaw_error).clamp(-speed, speed),
+        };
+
+        let settled = nav.velocity.0.hypot(nav.velocity.1) < SETTLED_SPEED;
+        let done = match step {
+            Step::Move { .. } => error.0.hypot(error.1) < POSITION_TOLERANCE && settled,
+            Step::Turn { .. } => yaw_error.abs() < YAW_TOLERANCE,
+            Step::Climb { .. } | Step::Altitude { .. } => height_error.abs() < HEIGHT_TOLERANCE,
+            Step::Hover { seconds } => elapsed.as_secs_f32() >= seconds,
+            Step::Photo => {
+                if !self.photo_taken {
+                    command.take_picture = true;
+                    self.photo_taken = true;
+                    self.photos += 1;
+                }
+                elapsed >= PHOTO_PAUSE
+            }
+        };
+        if done {
+            self.step += 1;
+            if self.finished() {
+                tracing::info!("mission `{}` finished", self.mission.name);
+            } else {
+                self.enter_step(nav);
+            }
+        }
+        Ok(command)
+    }
+
+    /// HUD line with the current step and the photos taken.
+    pub fn status(&self) -> String {
+        let mut status = match self.mission.steps.get(self.step) {
+            Some(step) => format!(
+                "MISSION {} {}/{}: {}",
+                self.mission.name,
+                self.step + 1,
+                self.mission.steps.len(),
+                step.label()
+            ),
+            None => format!("MISSION {} done", self.mission.name),
+        };
+        let photos = self
+            .mission
+            .steps
+            .iter()
+            .filter(|s| matches!(s, Step::Photo))
+            .count();
+        if photos > 0 {
+            status.push_str(&format!(", {}/{photos} photos", self.photos));
+        }
+        status
+    }
+
+    /// Steps flown so far, for the map.
+    pub fn progress(&self) -> usize {
+        self.mission.steps[..self.step.min(self.mission.steps.len())]
+            .iter()
+            .filter(|s| matches!(s, Step::Move { .. }))
+            .count()
+    }
+
+    pub fn origin(&self) -> ((f32, f32), f32) {
+        self.origin
+    }
+}
+
+/// Top down preview of `path` around `center`, legs up to `done` drawn as flown.
+pub fn draw_path(
+    canvas: &mut Canvas<Window>,
+    nav: &Navigation,
+    path: &[(f32, f32)],
+    done: usize,
+    center: (i32, i32),
+    size: i32,
+) -> Result<(), String> {
+    let half = size / 2;
+    let (cx, cy) = center;
+    let extent = path
+        .iter()
+        .chain(std::iter::once(&nav.position))
+        .map(|p| p.0.abs().max(p.1.abs()))
+        .fold(2.0_f32, f32::max);
+    let scale = 0.9 * half as f32 / extent;
+    // north is up, the takeoff point in the middle
+    let to_screen = |(north, east): (f32, f32)| {
+        Point::new(cx + (east * scale) as i32, cy - (north * scale) as i32)
+    };
+
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 120));
+    canvas.fill_rect(Rect::new(cx - half, cy - half, size as u32, size as u32))?;
+    let points: Vec<Point> = path.iter().map(|p| to_screen(*p)).collect();
+    for (n, leg) in points.windows(2).enumerate() {
+        canvas.set_draw_color(if n < done {
+            Color::RGB(120, 120, 120)
+        } else {
+            Color::RGB(60, 200, 255)
+        });
+        canvas.draw_line(leg[0], leg[1])?;
+    }
+    canvas.set_draw_color(Color::RGB(60, 200, 255));
+    for point in &points {
+        canvas.fill_rect(Rect::new(point.x() - 2, point.y() - 2, 5, 5))?;
+    }
+    let drone = to_screen(nav.position);
+    let (sin, cos) = nav.yaw.to_radians().sin_cos();
+    canvas.set_draw_color(Color::RGB(60, 255, 60));
+    canvas.fill_rect(Rect::new(drone.x() - 3, drone.y() - 3, 6, 6))?;
+    canvas.draw_line(
+        drone,
+        (
+            drone.x() + (sin * 12.0) as i32,
+            drone.y() - (cos * 12.0) as i32,
+        ),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_needs_a_second_press() {
+        let mut confirm = StartConfirm::default();
+        let now = Instant::now();
+        assert!(!confirm.press(false, now));
+        assert!(confirm.press(false, now + Duration::from_secs(1)));
+        // used up, the next start asks again
+        assert!(!confirm.press(false, now + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn late_or_other_press_asks_again() {
+        let mut confirm = StartConfirm::default();
+        let now = Instant::now();
+        assert!(!confirm.press(false, now));
+        assert!(!confirm.press(false, now + CONFIRM_TIME));
+        assert!(confirm.press(false, now + CONFIRM_TIME + Duration::from_secs(1)));
+        // the survey doesn't confirm the mission
+        assert!(!confirm.press(false, now));
+        assert!(!confirm.press(true, now));
+        assert!(confirm.press(true, now));
+    }
+
+    /// Degrees a full turn command turns the simulated drone per tick.
+    const TURN_RATE: f32 = 10.0;
+
+    fn mission(steps: Vec<Step>) -> Mission {
+        Mission {
+            name: "test".to_owned(),
+            speed: 0.5,
+            step_timeout: 30.0,
+            steps,
+        }
+    }
+
+    fn hovering(yaw: f32) -> Navigation {
+        let mut nav = Navigation::default();
+        nav.flying = true;
+        nav.height = 1.0;
+        nav.yaw = yaw;
+        nav
+    }
+
+    // flies the mission on a drone that only turns, returns the degrees turned clockwise
+    // and the commands' directions seen
+    fn fly(mission: Mission, nav: &mut Navigation) -> (f32, Vec<f32>) {
+        let mut runner = MissionRunner::start(mission, nav);
+        let mut turned = 0.0;
+        let mut commands = Vec::new();
+        for _ in 0..10_000 {
+            let command = runner.update(nav).unwrap();
+            if runner.finished() {
+                return (turned, commands);
+            }
+            let turn = command.sticks.turn_clockwise * TURN_RATE;
+            commands.push(command.sticks.turn_clockwise);
+            turned += turn;
+            nav.yaw = wrap_degrees(nav.yaw + turn);
+        }
+        panic!("mission didn't finish");
+    }
+
+    fn assert_turns(degrees: f32, start_yaw: f32) {
+        let mut nav = hovering(start_yaw);
+        let (turned, commands) = fly(mission(vec![Step::Turn { degrees }]), &mut nav);
+        assert!(
+            (turned - degrees).abs() < YAW_TOLERANCE,
+            "turn {degrees} from {start_yaw}: turned {turned}"
+        );
+        assert!(
+            commands.iter().all(|c| c * degrees.signum() >= 0.0),
+            "turn {degrees} went the other way"
+        );
+        assert!(wrap_degrees(nav.yaw - (start_yaw + degrees)).abs() < YAW_TOLERANCE);
+    }
+
+    #[test]
+    fn quarter_turn() {
+        assert_turns(90.0, 0.0);
+        assert_turns(90.0, 170.0);
+        assert_turns(-90.0, -170.0);
+    }
+
+    #[test]
+    fn long_turn_keeps_its_direction() {
+        assert_turns(270.0, 0.0);
+        assert_turns(-270.0, 45.0);
+    }
+
+    #[test]
+    fn full_turn_turns() {
+        assert_turns(360.0, 0.0);
+        assert_turns(-360.0, 179.0);
+    }
+
+    #[test]
+    fn more_than_a_full_turn() {
+        assert_turns(-450.0, 0.0);
+        assert_turns(720.0, -90.0);
+    }
+
+    #[test]
+    fn turns_end_on_the_chained_heading() {
+        // started 10 degrees off the mission's heading, two turns end at 180 from it
+        let mut nav = hovering(0.0);
+        let mut runner = MissionRunner::start(
+            mission(vec![
+                Step::Turn { degrees: 90.0 },
+                Step::Turn { degrees: 90.0 },
+                Step::Hover { seconds: 0.0 },
+            ]),
+            &nav,
+        );
+        nav.yaw = 10.0;
+        let mut turned = 0.0;
+        while !runner.finished() {
+            let turn = runner.update(&nav).unwrap().sticks.turn_clockwise * TURN_RATE;
+            turned += turn;
+            nav.yaw = wrap_degrees(nav.yaw + turn);
+        }
+        assert!((turned - 170.0).abs() < 2.0 * YAW_TOLERANCE, "{turned}");
+        assert!(wrap_degrees(nav.yaw - 180.0).abs() < YAW_TOLERANCE);
+    }
+
+    #[test]
+    fn plan_follows_the_turns() {
+        let path = mission(vec![
+            Step::Move {
+                forward: 1.0,
+                right: 0.0,
+            },
+            Step::Turn { degrees: 450.0 },
+            Step::Move {
+                forward: 1.0,
+                right: 0.0,
+            },
+        ])
+        .plan((0.0, 0.0), 0.0);
+        assert_eq!(path.len(), 3);
+        assert!((path[2].0 - 1.0).abs() < 1e-5 && (path[2].1 - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,78 @@
+//! Where the drone is, as far as the telemetry tells.
+//!
+//! Height and yaw are reported by the drone, the horizontal position is dead reckoned from the
+//! MVO velocities and reset to the takeoff point while on the ground, so it drifts over a flight.
+
+use std::time::Instant;
+
+use crate::telemetry::TelemetrySample;
+
+/// Dead reckoning gaps longer than this (seconds) are not integrated.
+const MAX_GAP: f32 = 1.0;
+
+#[derive(Debug, Default)]
+pub struct Navigation {
+    /// north, east in meters from the takeoff point
+    pub position: (f32, f32),
+    /// north, east in m/s
+    pub velocity: (f32, f32),
+    /// m/s, positive up
+    pub climb: f32,
+    /// meters
+    pub height: f32,
+    /// degrees, clockwise from north
+    pub yaw: f32,
+    pub flying: bool,
+    last: Option<Instant>,
+}
+
+impl Navigation {
+    pub fn distance(&self) -> f32 {
+        self.position.0.hypot(self.position.1)
+    }
+
+    /// Feeds telemetry received `at`, the same update is only taken once.
+    pub fn update(&mut self, sample: &TelemetrySample, at: Instant, flying: bool) {
+        if self.last == Some(at) {
+            return;
+        }
+        if let Some(height) = sample.height_m() {
+            self.height = height;
+        }
+        if let Some(yaw) = sample.yaw {
+            self.yaw = yaw;
+        }
+        self.flying = flying;
+        if !flying {
+            self.position = (0.0, 0.0);
+            self.velocity = (0.0, 0.0);
+            self.climb = 0.0;
+            self.last = Some(at);
+            return;
+        }
+        if let Some(last) = self.last {
+            let dt = at.duration_since(last).as_secs_f32();
+            if dt < MAX_GAP {
+                self.position.0 += self.velocity.0 * dt;
+                self.position.1 += self.velocity.1 * dt;
+            }
+        }
+        if let Some((north, east, down)) = sample.velocity_mps() {
+            self.velocity = (north, east);
+            self.climb = -down;
+        }
+        self.last = Some(at);
+    }
+}
+
+/// Body frame (forward, right) to world frame (north, east) at `yaw` degrees.
+pub fn to_world(yaw: f32, (forward, right): (f32, f32)) -> (f32, f32) {
+    let (sin, cos) = yaw.to_radians().sin_cos();
+    (forward * cos - right * sin, forward * sin + right * cos)
+}
+
+/// World frame (north, east) to body frame (forward, right) at `yaw` degrees.
+pub fn to_body(yaw: f32, (north, east): (f32, f32)) -> (f32, f32) {
+    let (sin, cos) = yaw.to_radians().sin_cos();
+    (north * cos + east * sin, -north * sin + east * cos)
+}
@@ -0,0 +1,479 @@
+//! Telemetry burned into the video, the way action cameras do it.
+//!
+//! Live, while recording, ffmpeg re-encodes the stream with a `drawtext` filter that reloads
+//! the text file written here on every telemetry update. Offline, a recorded video and its
+//! flight log are turned into an ASS subtitle track, with the sticks drawn as boxes, and
+//! burned in by ffmpeg.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    recorder::{self, RecordEvent},
+    telemetry::{StickSample, TelemetrySample},
+};
+
+/// Tello streams at about this rate, raw H.264 has no timestamps of its own. Only used when
+/// the recording's end time is unknown.
+const RAW_FPS: f32 = 30.0;
+const LIVE_UPDATE_EVERY: Duration = Duration::from_millis(100);
+// the frames are 960x720, subtitles are laid out on that
+const WIDTH: i32 = 960;
+const HEIGHT: i32 = 720;
+const STICK_BOX: i32 = 80;
+
+/// Wall clock of the first and last frame of a recorded video part, stored next to it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VideoStart {
+    pub start_unix_ms: u64,
+    /// written when the part is closed, missing after a crash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_unix_ms: Option<u64>,
+}
+
+impl VideoStart {
+    pub fn sidecar(video: &Path) -> PathBuf {
+        video.with_extension("json")
+    }
+
+    pub fn save(&self, video: &Path) -> io::Result<()> {
+        fs::write(Self::sidecar(video), serde_json::to_string(self).unwrap())
+    }
+
+    pub fn load(video: &Path) -> Result<Self, String> {
+        let path = Self::sidecar(video);
+        let content = fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        serde_json::from_str(&content).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+/// Two lines of flight data, as shown on the video.
+pub fn overlay_text(sample: &TelemetrySample, sticks: &StickSample) -> String {
+    let opt = |v: Option<f32>, precision: usize| match v {
+        Some(v) => format!("{v:.precision$}"),
+        None => "-".to_owned(),
+    };
+    let speed = sample
+        .velocity_mps()
+        .map(|(north, east, _)| north.hypot(east));
+    // `0.0 -` keeps a level flight from showing as -0.0
+    let climb = sample.velocity_mps().map(|(_, _, down)| 0.0 - down);
+    let battery = sample
+        .battery_percentage
+        .map(|b| b.to_string())
+        .unwrap_or_else(|| "-".to_owned());
+    format!(
+        "ALT {} m  SPD {} m/s  VS {} m/s  BAT {battery}%  YAW {}\n\
+         FWD {:+.2}  RIGHT {:+.2}  UP {:+.2}  TURN {:+.2}",
+        opt(sample.height_m(), 1),
+        opt(speed, 1),
+        opt(climb, 1),
+        opt(sample.yaw, 0),
+        sticks.forward,
+        sticks.right,
+        sticks.up,
+        sticks.turn_clockwise,
+    )
+}
+
+// ffmpeg filter arguments are separated by `:` and quoted with `'`
+fn filter_escape(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace(':', "\\:")
+        .replace('\'', "\\'")
+}
+
+/// Re-encodes a recorded part with the current telemetry drawn on it.
+pub struct LiveOverlay {
+    text_file: PathBuf,
+    ffmpeg: Child,
+    last_update: Option<Instant>,
+}
+
+impl LiveOverlay {
+    /// `out` is the overlaid MP4, the text file goes next to it.
+    pub fn spawn(ffmpeg: &Path, font: Option<&Path>, out: &Path) -> io::Result<Self> {
+        let text_file = out.with_extension("txt");
+        fs::write(&text_file, "")?;
+        let mut filter = format!(
+            "drawtext=textfile={}:reload=1:fontcolor=white:fontsize=22:line_spacing=6:\
+             box=1:boxcolor=black@0.5:boxborderw=8:x=20:y=h-th-20",
+            filter_escape(&text_file)
+        );
+        if let Some(font) = font {
+            let _ = write!(filter, ":fontfile={}", filter_escape(font));
+        }
+        let ffmpeg = Command::new(ffmpeg)
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args([
+                "-use_wallclock_as_timestamps",
+                "1",
+                "-f",
+                "h264",
+                "-i",
+                "pipe:0",
+            ])
+            .args(["-vf", &filter])
+            .args(["-c:v", "libx264", "-preset", "veryfast"])
+            .args(["-movflags", "+frag_keyframe+empty_moov"])
+            .arg(out)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+        Ok(Self {
+            text_file,
+            ffmpeg,
+            last_update: None,
+        })
+    }
+
+    pub fn frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        match self.ffmpeg.stdin.as_mut() {
+            Some(stdin) => stdin.write_all(frame),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    /// Updates the text, at most every `LIVE_UPDATE_EVERY`.
+    pub fn update(&mut self, sample: &TelemetrySample, sticks: &StickSample) -> io::Result<()> {
+        if self
+            .last_update
+            .is_some_and(|t| t.elapsed() < LIVE_UPDATE_EVERY)
+        {
+            return Ok(());
+        }
+        self.last_update = Some(Instant::now());
+        // drawtext may read the file at any time, replace it in one go
+        let tmp = self.text_file.with_extension("txt.tmp");
+        fs::write(&tmp, overlay_text(sample, sticks))?;
+        fs::rename(&tmp, &self.text_file)
+    }
+
+    pub fn finish(mut self) {
+        drop(self.ffmpeg.stdin.take());
+        match self.ffmpeg.wait() {
+            Ok(status) if !status.success() => {
+                tracing::error!("ffmpeg overlay failed: {status}")
+            }
+            Err(e) => tracing::error!("ffmpeg overlay failed: {e}"),
+            Ok(_) => {}
+        }
+        let _ = fs::remove_file(&self.text_file);
+    }
+}
+
+fn ass_time(ms: i64) -> String {
+    let cs = ms.max(0) / 10;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        cs / 360_000,
+        cs / 6000 % 60,
+        cs / 100 % 60,
+        cs % 100
+    )
+}
+
+// box outline and the stick position in it, one event each as each can have one position
+fn ass_stick(x: i32, y: i32, (sx, sy): (f32, f32)) -> [String; 2] {
+    let b = STICK_BOX;
+    let dx = ((sx + 1.0) / 2.0 * (b - 10) as f32) as i32;
+    let dy = ((1.0 - sy) / 2.0 * (b - 10) as f32) as i32;
+    [
+        format!("{{\\an7\\pos({x},{y})\\1a&HFF&\\bord2\\p1}}m 0 0 l {b} 0 {b} {b} 0 {b}{{\\p0}}"),
+        format!(
+            "{{\\an7\\pos({},{})\\1c&H00FF00&\\bord0\\p1}}m 0 0 l 10 0 10 10 0 10{{\\p0}}",
+            x + dx,
+            y + dy
+        ),
+    ]
+}
+
+/// ASS subtitles for a video that started at `start_unix_ms`, one event per telemetry record.
+pub fn ass_subtitles(records: &[recorder::Record], start_unix_ms: u64) -> String {
+    let mut ass = format!(
+        "[Script Info]\n\
+         ScriptType: v4.00+\n\
+         PlayResX: {WIDTH}\n\
+         PlayResY: {HEIGHT}\n\
+         \n\
+         [V4+ Styles]\n\
+         Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+         BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+         BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+         Style: Telemetry,Sans,22,&H00FFFFFF,&H00FFFFFF,&H00000000,&H80000000,0,0,0,0,100,100,\
+         0,0,3,4,0,1,20,20,20,1\n\
+         Style: Sticks,Sans,22,&H00FFFFFF,&H00FFFFFF,&H00FFFFFF,&H00000000,0,0,0,0,100,100,\
+         0,0,1,2,0,7,0,0,0,1\n\
+         \n\
+         [Events]\n\
+         Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n"
+    );
+    let mut sticks = StickSample::default();
+    let telemetry: Vec<(u64, TelemetrySample, StickSample)> = records
+        .iter()
+        .filter_map(|record| match &record.event {
+            RecordEvent::Sticks(s) => {
+                sticks = *s;
+                None
+            }
+            RecordEvent::Telemetry(t) => Some((record.unix_ms, t.clone(), sticks)),
+        })
+        .collect();
+    for (n, (unix_ms, sample, sticks)) in telemetry.iter().enumerate() {
+        let start = *unix_ms as i64 - start_unix_ms as i64;
+        let end = telemetry
+            .get(n + 1)
+            .map(|next| next.0 as i64 - start_unix_ms as i64)
+            .unwrap_or(start + 1000);
+        if end <= 0 {
+            continue;
+        }
+        let (start, end) = (ass_time(start), ass_time(end));
+        let text = overlay_text(sample, sticks).replace('\n', "\\N");
+        let _ = writeln!(ass, "Dialogue: 0,{start},{end},Telemetry,,0,0,0,,{text}");
+        // throttle and yaw on the left box, pitch and roll on the right one
+        let y = HEIGHT - STICK_BOX - 20;
+        let left = ass_stick(
+            WIDTH - 2 * STICK_BOX - 40,
+            y,
+            (sticks.turn_clockwise, sticks.up),
+        );
+        let right = ass_stick(WIDTH - STICK_BOX - 20, y, (sticks.right, sticks.forward));
+        for drawing in left.iter().chain(right.iter()) {
+            let _ = writeln!(ass, "Dialogue: 1,{start},{end},Sticks,,0,0,0,,{drawing}");
+        }
+    }
+    ass
+}
+
+// pictures in an H.264 stream: slices with `first_mb_in_slice` 0, whose ue(v) is a single 1 bit
+fn count_pictures(data: &[u8]) -> u64 {
+    data.windows(5)
+        .filter(|w| w[..3] == [0, 0, 1] && matches!(w[3] & 0x1f, 1 | 5) && w[4] & 0x80 != 0)
+        .count() as u64
+}
+
+/// Frame rate of a raw part from its pictures and how long it was recorded, `None` without
+/// an end time or with too little to go on.
+pub fn raw_fps(data: &[u8], start: &VideoStart) -> Option<f32> {
+    let secs = start.end_unix_ms?.checked_sub(start.start_unix_ms)? as f32 / 1000.0;
+    let pictures = count_pictures(data);
+    if pictures < 2 || secs <= 0.0 {
+        return None;
+    }
+    // the times are those of the first and the last picture
+    Some((pictures - 1) as f32 / secs)
+}
+
+/// Burns the flight log into a recorded video (raw `.h264` or `.mp4`) and writes `out`.
+pub fn burn(ffmpeg: &Path, video: &Path, flight_log: &Path, out: &Path) -> Result<(), String> {
+    let start = VideoStart::load(video)?;
+    let records = recorder::load(flight_log)?;
+    let subtitles = out.with_extension("ass");
+    fs::write(&subtitles, ass_subtitles(&records, start.start_unix_ms))
+        .map_err(|e| format!("{}: {e}", subtitles.display()))?;
+    tracing::info!(
+        "burning {} into {}, writing {}",
+        flight_log.display(),
+        video.display(),
+        out.display()
+    );
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-hide_banner", "-loglevel", "error", "-y"]);
+    if video.extension().is_some_and(|ext| ext == "h264") {
+        let data = fs::read(video).map_err(|e| format!("{}: {e}", video.display()))?;
+        // the subtitles are timed by the wall clock, the frames have to be too
+        let fps = raw_fps(&data, &start).unwrap_or_else(|| {
+            tracing::warn!("no recording end time, assuming {RAW_FPS} fps");
+            RAW_FPS
+        });
+        tracing::info!("raw video at {fps:.2} fps");
+        cmd.args(["-f", "h264", "-framerate", &format!("{fps:.3}")]);
+    }
+    let status = cmd
+        .arg("-i")
+        .arg(video)
+        .args(["-vf", &format!("ass={}", filter_escape(&subtitles))])
+        .args(["-c:v", "libx264", "-preset", "medium"])
+        .arg(out)
+        .status()
+        .map_err(|e| format!("can't run {}: {e}", ffmpeg.display()))?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed: {status}"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::Record;
+
+    fn sample() -> TelemetrySample {
+        TelemetrySample {
+            height: Some(12),
+            battery_percentage: Some(56),
+            yaw: Some(-90.4),
+            vx: Some(3000.0),
+            vy: Some(4000.0),
+            vz: Some(-500.0),
+            ..Default::default()
+        }
+    }
+
+    fn sticks() -> StickSample {
+        StickSample {
+            forward: 0.5,
+            right: -0.25,
+            up: 0.0,
+            turn_clockwise: 1.0,
+        }
+    }
+
+    fn record(unix_ms: u64, event: RecordEvent) -> Record {
+        Record {
+            t: 0.0,
+            unix_ms,
+            event,
+        }
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            overlay_text(&sample(), &sticks()),
+            "ALT 1.2 m  SPD 5.0 m/s  VS 0.5 m/s  BAT 56%  YAW -90\n\
+             FWD +0.50  RIGHT -0.25  UP +0.00  TURN +1.00"
+        );
+        let level = TelemetrySample {
+            vx: Some(0.0),
+            vy: Some(0.0),
+            vz: Some(0.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            overlay_text(&level, &StickSample::default()),
+            "ALT - m  SPD 0.0 m/s  VS 0.0 m/s  BAT -%  YAW -\n\
+             FWD +0.00  RIGHT +0.00  UP +0.00  TURN +0.00"
+        );
+    }
+
+    #[test]
+    fn time_format() {
+        assert_eq!(ass_time(0), "0:00:00.00");
+        assert_eq!(ass_time(1234), "0:00:01.23");
+        assert_eq!(ass_time(59_999), "0:00:59.99");
+        assert_eq!(ass_time(61_000), "0:01:01.00");
+        assert_eq!(ass_time(3_600_000 + 2_500), "1:00:02.50");
+        // before the video started
+        assert_eq!(ass_time(-500), "0:00:00.00");
+    }
+
+    #[test]
+    fn subtitles() {
+        let records = vec![
+            // over before the video started, dropped
+            record(200, RecordEvent::Telemetry(sample())),
+            // running into it, shown from the start
+            record(500, RecordEvent::Telemetry(sample())),
+            record(900, RecordEvent::Sticks(sticks())),
+            record(1500, RecordEvent::Telemetry(sample())),
+            record(1600, RecordEvent::Sticks(StickSample::default())),
+            record(2000, RecordEvent::Telemetry(TelemetrySample::default())),
+        ];
+        let ass = ass_subtitles(&records, 1000);
+        assert!(ass.starts_with("[Script Info]\nScriptType: v4.00+\nPlayResX: 960\n"));
+        let events: Vec<&str> = ass
+            .lines()
+            .filter(|line| line.starts_with("Dialogue:"))
+            .collect();
+        // the text and two boxes of two drawings each per record
+        assert_eq!(events.len(), 3 * 5);
+        assert!(events[0].starts_with("Dialogue: 0,0:00:00.00,0:00:00.50,Telemetry"));
+        assert_eq!(
+            events[5],
+            "Dialogue: 0,0:00:00.50,0:00:01.00,Telemetry,,0,0,0,,\
+             ALT 1.2 m  SPD 5.0 m/s  VS 0.5 m/s  BAT 56%  YAW -90\\N\
+             FWD +0.50  RIGHT -0.25  UP +0.00  TURN +1.00"
+        );
+        assert!(events[6].starts_with("Dialogue: 1,0:00:00.50,0:00:01.00,Sticks,,0,0,0,,{\\an7"));
+        // the last one is shown for a second, with the sticks let go
+        assert!(events[10].starts_with("Dialogue: 0,0:00:01.00,0:00:02.00,Telemetry"));
+        assert!(events[10].ends_with("FWD +0.00  RIGHT +0.00  UP +0.00  TURN +0.00"));
+        // nothing breaks a line or starts an override block but the ones meant to
+        for event in &events {
+            assert!(!event.contains('\n'));
+        }
+        assert!(events[5]
+            .split(",,")
+            .nth(1)
+            .is_some_and(|text| !text.contains('{')));
+    }
+
+    #[test]
+    fn stick_positions() {
+        // full right and full up, the dot in the top right corner of the box
+        let [outline, dot] = ass_stick(100, 200, (1.0, 1.0));
+        assert!(outline.starts_with("{\\an7\\pos(100,200)"));
+        assert!(dot.starts_with("{\\an7\\pos(170,200)"));
+        let [_, dot] = ass_stick(100, 200, (-1.0, -1.0));
+        assert!(dot.starts_with("{\\an7\\pos(100,270)"));
+    }
+
+    #[test]
+    fn filter_paths() {
+        assert_eq!(
+            filter_escape(Path::new("C:\\videos\\it's.ass")),
+            "C\\:\\\\videos\\\\it\\'s.ass"
+        );
+    }
+
+    #[test]
+    fn pictures_and_rate() {
+        // SPS, PPS, an IDR picture in two slices, then two more pictures
+        let stream = [
+            &[0, 0, 0, 1, 0x67, 0x42][..],
+            &[0, 0, 0, 1, 0x68, 0xce],
+            &[0, 0, 0, 1, 0x65, 0x88, 0x84],
+            &[0, 0, 1, 0x65, 0x41, 0x9a],
+            &[0, 0, 0, 1, 0x41, 0x9a, 0x02],
+            &[0, 0, 0, 1, 0x41, 0x9b, 0x04],
+        ]
+        .concat();
+        assert_eq!(count_pictures(&stream), 3);
+        let start = VideoStart {
+            start_unix_ms: 10_000,
+            end_unix_ms: Some(10_080),
+        };
+        assert_eq!(raw_fps(&stream, &start), Some(25.0));
+        let crashed = VideoStart {
+            end_unix_ms: None,
+            ..start
+        };
+        assert_eq!(raw_fps(&stream, &crashed), None);
+        assert_eq!(raw_fps(&stream[..12], &start), None);
+    }
+
+    #[test]
+    fn start_sidecar_without_end_loads() {
+        let video = std::env::temp_dir().join(format!("tello-overlay-{}.h264", std::process::id()));
+        fs::write(VideoStart::sidecar(&video), r#"{"start_unix_ms":42}"#).unwrap();
+        assert_eq!(
+            VideoStart::load(&video).unwrap(),
+            VideoStart {
+                start_unix_ms: 42,
+                end_unix_ms: None
+            }
+        );
+        let _ = fs::remove_file(VideoStart::sidecar(&video));
+    }
+}
@@ -0,0 +1,440 @@
+//! Flight data saved next to every picture taken.
+//!
+//! The drone sends the pictures on its own schedule and names them itself, so the state at the
+//! moment of the shot is queued and written as `<picture>.json` once a new picture shows up in
+//! the pictures folder.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{nav::Navigation, telemetry::TelemetrySample};
+
+const SCAN_EVERY: Duration = Duration::from_millis(500);
+/// Shots without a picture after this long are given up on.
+const PICTURE_TIMEOUT: Duration = Duration::from_secs(15);
+const EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhotoMeta {
+    pub unix_ms: u64,
+    /// meters north and east of the takeoff point, dead reckoned
+    pub north: f32,
+    pub east: f32,
+    /// meters
+    pub height: f32,
+    /// degrees, clockwise from north
+    pub yaw: f32,
+    /// degrees, from the IMU
+    pub pitch: Option<f32>,
+    pub roll: Option<f32>,
+    /// north, east, up in m/s
+    pub velocity: Option<(f32, f32, f32)>,
+    /// percent
+    pub battery: Option<i32>,
+    /// seconds
+    pub fly_time: Option<i32>,
+}
+
+impl PhotoMeta {
+    pub fn new(nav: &Navigation, sample: &TelemetrySample) -> Self {
+        Self {
+            unix_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            north: nav.position.0,
+            east: nav.position.1,
+            height: nav.height,
+            yaw: nav.yaw,
+            pitch: sample.pitch,
+            roll: sample.roll,
+            velocity: sample
+                .velocity_mps()
+                .map(|(north, east, down)| (north, east, 0.0 - down)),
+            battery: sample.battery_percentage,
+            fly_time: sample.fly_time,
+        }
+    }
+
+    pub fn sidecar(picture: &Path) -> PathBuf {
+        picture.with_extension("json")
+    }
+
+    pub fn save(&self, picture: &Path) -> io::Result<()> {
+        fs::write(
+            Self::sidecar(picture),
+            serde_json::to_string_pretty(self).unwrap(),
+        )
+    }
+
+    pub fn load(picture: &Path) -> Result<Self, String> {
+        let path = Self::sidecar(picture);
+        let content = fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        serde_json::from_str(&content).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// One line for the HUD.
+    pub fn summary(&self) -> String {
+        let opt = |v: Option<f32>| match v {
+            Some(v) => format!("{v:.0}"),
+            None => "-".to_owned(),
+        };
+        let speed = match self.velocity {
+            Some((north, east, up)) => format!("{:.1}/{up:+.1}", north.hypot(east)),
+            None => "-".to_owned(),
+        };
+        format!(
+            "{}  ALT {:.1} m  N {:.1} E {:.1}  YAW {:.0} PITCH {} ROLL {}  SPD {speed} m/s  \
+             BAT {}%  FLY {} s",
+            utc_time(self.unix_ms),
+            self.height,
+            self.north,
+            self.east,
+            self.yaw,
+            opt(self.pitch),
+            opt(self.roll),
+            self.battery.map_or("-".to_owned(), |b| b.to_string()),
+            self.fly_time.map_or("-".to_owned(), |t| t.to_string()),
+        )
+    }
+}
+
+// `YYYY-MM-DD hh:mm:ss UTC`, civil from days after Howard Hinnant
+fn utc_time(unix_ms: u64) -> String {
+    let secs = unix_ms / 1000;
+    let days = (secs / 86_400) as i64;
+    let (hh, mm, ss) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year}-{month:02}-{day:02} {hh:02}:{mm:02}:{ss:02} UTC")
+}
+
+fn is_picture(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+fn pictures(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut pictures: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| is_picture(path))
+        .collect();
+    pictures.sort();
+    pictures
+}
+
+/// Pairs shots with the pictures appearing in `dir`, oldest first.
+pub struct PhotoTagger {
+    dir: PathBuf,
+    known: HashSet<PathBuf>,
+    pending: VecDeque<(Instant, PhotoMeta)>,
+    last_scan: Instant,
+}
+
+impl PhotoTagger {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_owned(),
+            known: pictures(dir).into_iter().collect(),
+            pending: VecDeque::new(),
+            last_scan: Instant::now(),
+        }
+    }
+
+    /// A picture was just asked for, `meta` is saved with it when it arrives.
+    pub fn shot(&mut self, meta: PhotoMeta) {
+        self.pending.push_back((Instant::now(), meta));
+    }
+
+    /// Looks for new pictures, at most every `SCAN_EVERY`, and returns them.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_scan.elapsed() < SCAN_EVERY {
+            return Vec::new();
+        }
+        self.last_scan = Instant::now();
+        let mut new = Vec::new();
+        for picture in pictures(&self.dir) {
+            if self.known.contains(&picture) {
+                continue;
+            }
+            self.known.insert(picture.clone());
+            new.push(picture.clone());
+            // pictures nobody asked for here stay untagged
+            let Some((_, meta)) = self.pending.pop_front() else {
+                continue;
+            };
+            match meta.save(&picture) {
+                Ok(()) => tracing::info!("picture {} tagged", picture.display()),
+                Err(e) => tracing::error!("can't tag picture {}: {e}", picture.display()),
+            }
+        }
+        while let Some((at, _)) = self.pending.front() {
+            if at.elapsed() < PICTURE_TIMEOUT {
+                break;
+            }
+            tracing::warn!("no picture arrived for a shot, not tagged");
+            self.pending.pop_front();
+        }
+        new
+    }
+}
+
+/// Pictures the carousel holds.
+pub const CAROUSEL_SIZE: usize = 10;
+const CAROUSEL_DIR: &str = ".carousel";
+
+/// Keeps the carousel's pictures and shows the flight data of the zoomed one.
+///
+/// The carousel can't tell which picture it shows, so the browser keeps the newest
+/// `CAROUSEL_SIZE` pictures and stages them, numbered in order, in the folder the carousel
+/// loads. Both turn over that one list, new pictures are added to it and staged again.
+pub struct PhotoBrowser {
+    dir: PathBuf,
+    shown: Vec<PathBuf>,
+    index: usize,
+    zoomed: bool,
+    info: String,
+}
+
+impl PhotoBrowser {
+    pub fn new(dir: &Path) -> Self {
+        let mut shown = pictures(dir);
+        shown.drain(..shown.len().saturating_sub(CAROUSEL_SIZE));
+        Self {
+            dir: dir.to_owned(),
+            shown,
+            index: 0,
+            zoomed: false,
+            info: String::new(),
+        }
+    }
+
+    /// Where the carousel loads its pictures from, filled by `stage`.
+    pub fn carousel_dir(&self) -> PathBuf {
+        self.dir.join(CAROUSEL_DIR)
+    }
+
+    /// A new picture at the end of the list, the oldest one drops out when it's full. Staged
+    /// again for the carousel to pick up.
+    pub fn add(&mut self, picture: PathBuf) -> io::Result<()> {
+        self.shown.push(picture);
+        self.shown
+            .drain(..self.shown.len().saturating_sub(CAROUSEL_SIZE));
+        self.index = self.index.min(self.shown.len() - 1);
+        self.refresh();
+        self.stage()
+    }
+
+    /// Links the shown pictures into `carousel_dir`, named so they sort in the browser's order.
+    pub fn stage(&self) -> io::Result<()> {
+        let carousel = self.carousel_dir();
+        if carousel.exists() {
+            fs::remove_dir_all(&carousel)?;
+        }
+        fs::create_dir_all(&carousel)?;
+        for (i, picture) in self.shown.iter().enumerate() {
+            let name = picture.file_name().unwrap().to_string_lossy();
+            let staged = carousel.join(format!("{i:03}-{name}"));
+            if fs::hard_link(picture, &staged).is_err() {
+                fs::copy(picture, &staged)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn toggle_zoom(&mut self) {
+        self.zoomed = !self.zoomed;
+        self.refresh();
+    }
+
+    pub fn turn_left(&mut self) {
+        let count = self.shown.len().max(1);
+        self.index = (self.index + count - 1) % count;
+        self.refresh();
+    }
+
+    pub fn turn_right(&mut self) {
+        let count = self.shown.len().max(1);
+        self.index = (self.index + 1) % count;
+        self.refresh();
+    }
+
+    /// The picture under the cursor.
+    pub fn current(&self) -> Option<&Path> {
+        self.shown.get(self.index).map(PathBuf::as_path)
+    }
+
+    /// Flight data of the zoomed picture, empty when not zoomed.
+    pub fn info(&self) -> &str {
+        &self.info
+    }
+
+    fn refresh(&mut self) {
+        if !self.zoomed {
+            self.info.clear();
+            return;
+        }
+        self.info = match self.current() {
+            Some(picture) => match PhotoMeta::load(picture) {
+                Ok(meta) => meta.summary(),
+                Err(_) => "no flight data for this picture".to_owned(),
+            },
+            None => String::new(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(name: &str, pictures: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tello-photo-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for picture in pictures {
+            fs::write(dir.join(picture), picture.as_bytes()).unwrap();
+        }
+        dir
+    }
+
+    fn staged(browser: &PhotoBrowser) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(browser.carousel_dir())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn current(browser: &PhotoBrowser) -> String {
+        let picture = browser.current().unwrap();
+        picture.file_name().unwrap().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn carousel_gets_the_browsers_list() {
+        let dir = folder("list", &["b.jpg", "a.png", "notes.txt", "c.JPG"]);
+        let browser = PhotoBrowser::new(&dir);
+        browser.stage().unwrap();
+        assert_eq!(staged(&browser), ["000-a.png", "001-b.jpg", "002-c.JPG"]);
+        // staged again from scratch
+        browser.stage().unwrap();
+        assert_eq!(staged(&browser).len(), 3);
+        assert_eq!(
+            fs::read(browser.carousel_dir().join("001-b.jpg")).unwrap(),
+            b"b.jpg"
+        );
+        // the staging folder isn't a picture
+        assert_eq!(pictures(&dir).len(), 3);
+    }
+
+    #[test]
+    fn newest_pictures_up_to_the_carousel_size() {
+        let names: Vec<String> = (0..CAROUSEL_SIZE + 3)
+            .map(|i| format!("{i:02}.jpg"))
+            .collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let dir = folder("cap", &names);
+        let browser = PhotoBrowser::new(&dir);
+        browser.stage().unwrap();
+        let staged = staged(&browser);
+        assert_eq!(staged.len(), CAROUSEL_SIZE);
+        assert_eq!(staged[0], "000-03.jpg");
+        assert_eq!(current(&browser), "03.jpg");
+    }
+
+    #[test]
+    fn new_pictures_dont_move_the_cursor() {
+        let dir = folder("new", &["b.jpg", "d.jpg"]);
+        let mut browser = PhotoBrowser::new(&dir);
+        browser.turn_right();
+        assert_eq!(current(&browser), "d.jpg");
+        // on disk, not added yet
+        fs::write(dir.join("a.jpg"), b"a").unwrap();
+        browser.turn_right();
+        assert_eq!(current(&browser), "b.jpg");
+        browser.turn_left();
+        browser.turn_left();
+        assert_eq!(current(&browser), "b.jpg");
+    }
+
+    #[test]
+    fn new_pictures_are_staged() {
+        let dir = folder("add", &["a.jpg"]);
+        let mut tagger = PhotoTagger::new(&dir);
+        let mut browser = PhotoBrowser::new(&dir);
+        browser.stage().unwrap();
+        fs::write(dir.join("b.jpg"), b"b").unwrap();
+        tagger.last_scan -= SCAN_EVERY;
+        let new = tagger.poll();
+        assert_eq!(new, [dir.join("b.jpg")]);
+        for picture in new {
+            browser.add(picture).unwrap();
+        }
+        assert_eq!(staged(&browser), ["000-a.jpg", "001-b.jpg"]);
+        // nothing new
+        tagger.last_scan -= SCAN_EVERY;
+        assert!(tagger.poll().is_empty());
+    }
+
+    #[test]
+    fn full_carousel_drops_the_oldest() {
+        let names: Vec<String> = (0..CAROUSEL_SIZE).map(|i| format!("{i:02}.jpg")).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let dir = folder("full", &names);
+        let mut browser = PhotoBrowser::new(&dir);
+        browser.turn_left();
+        assert_eq!(current(&browser), "09.jpg");
+        fs::write(dir.join("10.jpg"), b"10").unwrap();
+        browser.add(dir.join("10.jpg")).unwrap();
+        let staged = staged(&browser);
+        assert_eq!(staged.len(), CAROUSEL_SIZE);
+        assert_eq!(staged[0], "000-01.jpg");
+        assert_eq!(staged[CAROUSEL_SIZE - 1], "009-10.jpg");
+        // the same slot in both
+        assert_eq!(current(&browser), "10.jpg");
+    }
+
+    #[test]
+    fn zoom_shows_the_flight_data() {
+        let dir = folder("zoom", &["a.jpg", "b.jpg"]);
+        let meta = PhotoMeta::new(&Navigation::default(), &TelemetrySample::default());
+        meta.save(&dir.join("b.jpg")).unwrap();
+        let mut browser = PhotoBrowser::new(&dir);
+        browser.toggle_zoom();
+        assert_eq!(browser.info(), "no flight data for this picture");
+        browser.turn_right();
+        assert_eq!(browser.info(), meta.summary());
+        browser.toggle_zoom();
+        assert_eq!(browser.info(), "");
+    }
+
+    #[test]
+    fn empty_folder() {
+        let dir = folder("empty", &[]);
+        let mut browser = PhotoBrowser::new(&dir);
+        browser.stage().unwrap();
+        browser.turn_left();
+        browser.toggle_zoom();
+        assert!(browser.current().is_none());
+        assert_eq!(browser.info(), "");
+    }
+}
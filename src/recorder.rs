@@ -0,0 +1,500 @@
+//! Flight recorder.
+//!
+//! Every flight goes to its own file `flight-<unix secs>-<part>.<jsonl|csv>`, a new part is
+//! started when the current one grows over `MAX_PART_BYTES`. Each line is one record:
+//!
+//! * `t` - seconds since takeoff, `unix_ms` - wall clock in milliseconds
+//! * `type` - `telemetry` for every `UpdateData` received, `sticks` whenever the commands
+//!   sent to the drone change
+//! * telemetry fields: `wifi_strength`, `height`, `fly_time`, `battery_percentage`,
+//!   `battery_milli_volts`, `light`, `pitch`, `roll`, `yaw`, `temperature`, `vx`, `vy`, `vz`,
+//!   missing values are `null` in JSON and empty in CSV
+//! * sticks fields: `forward`, `right`, `up`, `turn_clockwise`, -1.0..1.0
+//!
+//! CSV files start with a header line and carry all columns on every line.
+
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::telemetry::{StickSample, TelemetrySample};
+
+const MAX_PART_BYTES: u64 = 32 * 1024 * 1024;
+
+pub const CSV_HEADER: &str = "t,unix_ms,type,wifi_strength,height,fly_time,battery_percentage,\
+battery_milli_volts,light,pitch,roll,yaw,temperature,vx,vy,vz,forward,right,up,turn_clockwise";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordFormat {
+    JsonLines,
+    Csv,
+}
+
+impl RecordFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::JsonLines => "jsonl",
+            Self::Csv => "csv",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "jsonl" => Some(Self::JsonLines),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub t: f64,
+    pub unix_ms: u64,
+    #[serde(flatten)]
+    pub event: RecordEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordEvent {
+    Telemetry(TelemetrySample),
+    Sticks(StickSample),
+}
+
+fn csv_opt<T: Display>(v: &Option<T>) -> String {
+    v.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn parse_opt<T: FromStr>(col: &str) -> Result<Option<T>, String> {
+    if col.is_empty() {
+        return Ok(None);
+    }
+    col.parse()
+        .map(Some)
+        .map_err(|_| format!("invalid value `{col}`"))
+}
+
+fn parse_req<T: FromStr>(col: &str) -> Result<T, String> {
+    parse_opt(col)?.ok_or_else(|| "missing value".to_owned())
+}
+
+impl Record {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut cols = vec![self.t.to_string(), self.unix_ms.to_string()];
+        match &self.event {
+            RecordEvent::Telemetry(s) => {
+                cols.push("telemetry".to_owned());
+                cols.extend([
+                    csv_opt(&s.wifi_strength),
+                    csv_opt(&s.height),
+                    csv_opt(&s.fly_time),
+                    csv_opt(&s.battery_percentage),
+                    csv_opt(&s.battery_milli_volts),
+                    csv_opt(&s.light),
+                    csv_opt(&s.pitch),
+                    csv_opt(&s.roll),
+                    csv_opt(&s.yaw),
+                    csv_opt(&s.temperature),
+                    csv_opt(&s.vx),
+                    csv_opt(&s.vy),
+                    csv_opt(&s.vz),
+                ]);
+                cols.extend(["", "", "", ""].map(str::to_owned));
+            }
+            RecordEvent::Sticks(s) => {
+                cols.push("sticks".to_owned());
+                cols.extend(std::iter::repeat_n(String::new(), 13));
+                cols.extend([
+                    s.forward.to_string(),
+                    s.right.to_string(),
+                    s.up.to_string(),
+                    s.turn_clockwise.to_string(),
+                ]);
+            }
+        }
+        cols.join(",")
+    }
+
+    pub fn from_json(line: &str) -> Result<Self, String> {
+        serde_json::from_str(line).map_err(|e| e.to_string())
+    }
+
+    pub fn from_csv(line: &str) -> Result<Self, String> {
+        let cols: Vec<&str> = line.split(',').map(str::trim).collect();
+        if cols.len() != CSV_HEADER.split(',').count() {
+            return Err(format!(
+                "expected {} columns",
+                CSV_HEADER.split(',').count()
+            ));
+        }
+        let event = match cols[2] {
+            "telemetry" => RecordEvent::Telemetry(TelemetrySample {
+                wifi_strength: parse_opt(cols[3])?,
+                height: parse_opt(cols[4])?,
+                fly_time: parse_opt(cols[5])?,
+                battery_percentage: parse_opt(cols[6])?,
+                battery_milli_volts: parse_opt(cols[7])?,
+                light: parse_opt(cols[8])?,
+                pitch: parse_opt(cols[9])?,
+                roll: parse_opt(cols[10])?,
+                yaw: parse_opt(cols[11])?,
+                temperature: parse_opt(cols[12])?,
+                vx: parse_opt(cols[13])?,
+                vy: parse_opt(cols[14])?,
+                vz: parse_opt(cols[15])?,
+            }),
+            "sticks" => RecordEvent::Sticks(StickSample {
+                forward: parse_req(cols[16])?,
+                right: parse_req(cols[17])?,
+                up: parse_req(cols[18])?,
+                turn_clockwise: parse_req(cols[19])?,
+            }),
+            other => return Err(format!("unknown record type `{other}`")),
+        };
+        Ok(Self {
+            t: parse_req(cols[0])?,
+            unix_ms: parse_req(cols[1])?,
+            event,
+        })
+    }
+}
+
+/// Reads all records of a flight log, in file order.
+pub fn load(path: &Path) -> Result<Vec<Record>, String> {
+    let format = RecordFormat::from_path(path)
+        .ok_or_else(|| format!("{}: expected a .jsonl or .csv file", path.display()))?;
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut records = Vec::new();
+    for (n, line) in content.lines().enumerate() {
+        if line.trim().is_empty() || line == CSV_HEADER {
+            continue;
+        }
+        let record = match format {
+            RecordFormat::JsonLines => Record::from_json(line),
+            RecordFormat::Csv => Record::from_csv(line),
+        }
+        .map_err(|e| format!("{}:{}: {e}", path.display(), n + 1))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+enum Msg {
+    Start,
+    Stop,
+    Event(Instant, RecordEvent),
+    Shutdown,
+}
+
+/// Cheap handle to feed the recorder from any thread.
+#[derive(Clone)]
+pub struct RecorderHandle {
+    tx: Sender<Msg>,
+}
+
+impl RecorderHandle {
+    pub fn start(&self) {
+        let _ = self.tx.send(Msg::Start);
+    }
+
+    pub fn stop(&self) {
+        let _ = self.tx.send(Msg::Stop);
+    }
+
+    pub fn telemetry(&self, sample: TelemetrySample) {
+        let _ = self
+            .tx
+            .send(Msg::Event(Instant::now(), RecordEvent::Telemetry(sample)));
+    }
+
+    pub fn sticks(&self, sample: StickSample) {
+        let _ = self
+            .tx
+            .send(Msg::Event(Instant::now(), RecordEvent::Sticks(sample)));
+    }
+}
+
+pub struct Recorder {
+    handle: RecorderHandle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    pub fn spawn(dir: impl Into<PathBuf>, format: RecordFormat) -> Self {
+        let dir = dir.into();
+        let (tx, rx) = mpsc::channel();
+        let thread = thread::spawn(move || recorder_thread(rx, dir, format));
+        Self {
+            handle: RecorderHandle { tx },
+            thread: Some(thread),
+        }
+    }
+
+    pub fn handle(&self) -> RecorderHandle {
+        self.handle.clone()
+    }
+
+    /// Closes the current flight file and waits for the recorder to finish.
+    pub fn close(mut self) {
+        let _ = self.handle.tx.send(Msg::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct FlightFile {
+    dir: PathBuf,
+    format: RecordFormat,
+    name: u64,
+    part: u32,
+    out: BufWriter<File>,
+    written: u64,
+    started: Instant,
+    started_unix_ms: u64,
+}
+
+impl FlightFile {
+    fn create(dir: &Path, format: RecordFormat) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let (out, path) = Self::open_part(dir, format, now.as_secs(), 0)?;
+        tracing::info!("recording flight to {}", path.display());
+        let mut file = Self {
+            dir: dir.to_owned(),
+            format,
+            name: now.as_secs(),
+            part: 0,
+            out,
+            written: 0,
+            started: Instant::now(),
+            started_unix_ms: now.as_millis() as u64,
+        };
+        file.write_header()?;
+        Ok(file)
+    }
+
+    fn open_part(
+        dir: &Path,
+        format: RecordFormat,
+        name: u64,
+        part: u32,
+    ) -> io::Result<(BufWriter<File>, PathBuf)> {
+        let path = dir.join(format!("flight-{name}-{part:03}.{}", format.extension()));
+        Ok((BufWriter::new(File::create(&path)?), path))
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.format == RecordFormat::Csv {
+            self.write_line(CSV_HEADER)?;
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.out.write_all(line.as_bytes())?;
+        self.out.write_all(b"\n")?;
+        self.written += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn write(&mut self, at: Instant, event: RecordEvent) -> io::Result<()> {
+        if self.written > MAX_PART_BYTES {
+            self.out.flush()?;
+            self.part += 1;
+            let (out, path) = Self::open_part(&self.dir, self.format, self.name, self.part)?;
+            tracing::info!("continuing flight record in {}", path.display());
+            self.out = out;
+            self.written = 0;
+            self.write_header()?;
+        }
+        let since_start = at.saturating_duration_since(self.started);
+        let flush = matches!(event, RecordEvent::Telemetry(_));
+        let record = Record {
+            t: since_start.as_secs_f64(),
+            unix_ms: self.started_unix_ms + since_start.as_millis() as u64,
+            event,
+        };
+        let line = match self.format {
+            RecordFormat::JsonLines => record.to_json(),
+            RecordFormat::Csv => record.to_csv(),
+        };
+        self.write_line(&line)?;
+        // keep what we have on disk in case we go down
+        if flush {
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn recorder_thread(rx: Receiver<Msg>, dir: PathBuf, format: RecordFormat) {
+    let mut file: Option<FlightFile> = None;
+    let close = |file: &mut Option<FlightFile>| {
+        if let Some(mut f) = file.take() {
+            if let Err(e) = f.out.flush() {
+                tracing::error!("can't flush flight record: {e}");
+            }
+            tracing::info!("flight record closed after {:?}", f.started.elapsed());
+        }
+    };
+    while let Ok(msg) = rx.recv() {
+        match msg {
+            Msg::Start => {
+                close(&mut file);
+                match FlightFile::create(&dir, format) {
+                    Ok(f) => file = Some(f),
+                    Err(e) => {
+                        tracing::error!("can't start flight record in {}: {e}", dir.display())
+                    }
+                }
+            }
+            Msg::Stop => close(&mut file),
+            Msg::Event(at, event) => {
+                if let Some(f) = file.as_mut() {
+                    if let Err(e) = f.write(at, event) {
+                        tracing::error!("can't write flight record, recording stopped: {e}");
+                        file = None;
+                    }
+                }
+            }
+            Msg::Shutdown => break,
+        }
+    }
+    close(&mut file);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn telemetry() -> TelemetrySample {
+        TelemetrySample {
+            wifi_strength: Some(90),
+            height: Some(12),
+            fly_time: Some(34),
+            battery_percentage: Some(56),
+            battery_milli_volts: Some(3800),
+            light: Some(7),
+            pitch: Some(-1.5),
+            roll: Some(2.0),
+            yaw: Some(-179.0),
+            temperature: Some(61.0),
+            vx: Some(120.0),
+            vy: None,
+            vz: Some(-30.0),
+        }
+    }
+
+    fn sticks() -> StickSample {
+        StickSample {
+            forward: 0.25,
+            right: -1.0,
+            up: 0.0,
+            turn_clockwise: 0.125,
+        }
+    }
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record {
+                t: 0.0,
+                unix_ms: 1_700_000_000_000,
+                event: RecordEvent::Telemetry(telemetry()),
+            },
+            Record {
+                t: 0.125,
+                unix_ms: 1_700_000_000_125,
+                event: RecordEvent::Sticks(sticks()),
+            },
+            // nothing received yet
+            Record {
+                t: 0.5,
+                unix_ms: 1_700_000_000_500,
+                event: RecordEvent::Telemetry(TelemetrySample::default()),
+            },
+        ]
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for record in records() {
+            assert_eq!(Record::from_json(&record.to_json()).unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let columns = CSV_HEADER.split(',').count();
+        for record in records() {
+            let line = record.to_csv();
+            assert_eq!(line.split(',').count(), columns, "{line}");
+            assert_eq!(Record::from_csv(&line).unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn bad_csv_lines() {
+        assert!(Record::from_csv("0,1,telemetry").is_err());
+        let line = records()[1].to_csv().replace("sticks", "wind");
+        assert!(Record::from_csv(&line).is_err());
+        let line = records()[0].to_csv().replacen("90", "loud", 1);
+        assert!(Record::from_csv(&line).is_err());
+    }
+
+    #[test]
+    fn recorded_flight_loads_back() {
+        for format in [RecordFormat::JsonLines, RecordFormat::Csv] {
+            let dir = std::env::temp_dir().join(format!(
+                "tello-recorder-{}-{}",
+                std::process::id(),
+                format.extension()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            let recorder = Recorder::spawn(&dir, format);
+            let handle = recorder.handle();
+            // nothing is written outside of a flight
+            handle.sticks(sticks());
+            handle.start();
+            handle.telemetry(telemetry());
+            handle.sticks(sticks());
+            handle.stop();
+            handle.telemetry(telemetry());
+            recorder.close();
+
+            let files: Vec<PathBuf> = fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            assert_eq!(files.len(), 1, "{files:?}");
+            assert_eq!(RecordFormat::from_path(&files[0]), Some(format));
+            let events: Vec<RecordEvent> = load(&files[0])
+                .unwrap()
+                .into_iter()
+                .map(|record| record.event)
+                .collect();
+            assert_eq!(
+                events,
+                vec![
+                    RecordEvent::Telemetry(telemetry()),
+                    RecordEvent::Sticks(sticks())
+                ]
+            );
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Plays a recorded flight back into the channel `UI::mainloop` reads telemetry from.
+
+use std::{
+    path::Path,
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rust_tello::UpdateData;
+
+use crate::{
+    recorder::{self, RecordEvent},
+    telemetry::TelemetrySample,
+};
+
+const TICK: Duration = Duration::from_millis(10);
+const MIN_SPEED: f64 = 0.125;
+const MAX_SPEED: f64 = 16.0;
+
+/// Reads the telemetry records of a flight log, ordered by time.
+pub fn load(path: &Path) -> Result<Vec<(f64, TelemetrySample)>, String> {
+    let mut samples: Vec<(f64, TelemetrySample)> = recorder::load(path)?
+        .into_iter()
+        .filter_map(|record| match record.event {
+            RecordEvent::Telemetry(sample) => Some((record.t, sample)),
+            RecordEvent::Sticks(_) => None,
+        })
+        .collect();
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(samples)
+}
+
+#[derive(Debug)]
+struct PlayState {
+    paused: bool,
+    speed: f64,
+    position: f64,
+    seek_to: Option<f64>,
+}
+
+/// Play/pause, speed and seek controls of a running replay.
+#[derive(Clone)]
+pub struct ReplayControl {
+    state: Arc<Mutex<PlayState>>,
+    duration: f64,
+}
+
+impl ReplayControl {
+    pub fn toggle_pause(&self) {
+        let mut st = self.state.lock().unwrap();
+        st.paused = !st.paused;
+    }
+
+    pub fn faster(&self) {
+        let mut st = self.state.lock().unwrap();
+        st.speed = (st.speed * 2.0).min(MAX_SPEED);
+    }
+
+    pub fn slower(&self) {
+        let mut st = self.state.lock().unwrap();
+        st.speed = (st.speed / 2.0).max(MIN_SPEED);
+    }
+
+    /// Jumps `delta` seconds forward (or back when negative).
+    pub fn seek(&self, delta: f64) {
+        let mut st = self.state.lock().unwrap();
+        let from = st.seek_to.unwrap_or(st.position);
+        st.seek_to = Some((from + delta).clamp(0.0, self.duration));
+    }
+
+    pub fn status(&self) -> String {
+        let st = self.state.lock().unwrap();
+        format!(
+            "REPLAY {:>6.1}/{:.1} s x{}{}",
+            st.position,
+            self.duration,
+            st.speed,
+            if st.paused { " PAUSED" } else { "" }
+        )
+    }
+}
+
+/// Starts playing `path` into `tx` in real time.
+pub fn start(path: &Path, tx: Sender<UpdateData>) -> Result<ReplayControl, String> {
+    let samples = load(path)?;
+    let duration = samples.last().map(|s| s.0).unwrap_or_default();
+    tracing::info!(
+        "replaying {} samples, {duration:.1} s from {}",
+        samples.len(),
+        path.display()
+    );
+    let control = ReplayControl {
+        state: Arc::new(Mutex::new(PlayState {
+            paused: false,
+            speed: 1.0,
+            position: 0.0,
+            seek_to: None,
+        })),
+        duration,
+    };
+    let state = control.state.clone();
+    thread::spawn(move || player(samples, tx, state, duration));
+    Ok(control)
+}
+
+fn player(
+    samples: Vec<(f64, TelemetrySample)>,
+    tx: Sender<UpdateData>,
+    state: Arc<Mutex<PlayState>>,
+    duration: f64,
+) {
+    let mut next = 0;
+    let mut last = Instant::now();
+    loop {
+        thread::sleep(TICK);
+        let now = Instant::now();
+        let dt = now.duration_since(last).as_secs_f64();
+        last = now;
+
+        let mut st = state.lock().unwrap();
+        if let Some(to) = st.seek_to.take() {
+            st.position = to;
+            next = samples.partition_point(|(t, _)| *t <= to);
+            // show the state at the new position right away, even when paused
+            if next > 0 && tx.send(samples[next - 1].1.to_update_data()).is_err() {
+                return;
+            }
+        }
+        if !st.paused {
+            st.position = (st.position + dt * st.speed).min(duration);
+        }
+        let position = st.position;
+        drop(st);
+
+        while next < samples.len() && samples[next].0 <= position {
+            if tx.send(samples[next].1.to_update_data()).is_err() {
+                return;
+            }
+            next += 1;
+        }
+    }
+}
@@ -0,0 +1,554 @@
+//! Flight scripts in Rhai.
+//!
+//! A script runs on its own thread with a sandboxed engine: no modules, no `eval`, limits on
+//! operations, call depth and data sizes, and a wall clock timeout. Flight functions block the
+//! script while the UI loop flies them tick by tick, moves and turns the same way as mission
+//! steps. Aborting the script makes the blocked call, or the next operation, fail.
+//!
+//! ```text
+//! altitude(1.5);
+//! for i in 0..8 { turn(45); photo(); }
+//! print(`battery ${telemetry().battery}%`);
+//! land();
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use rhai::{module_resolvers::DummyModuleResolver, Dynamic, Engine, EvalAltResult, Map};
+use serde::Deserialize;
+
+use crate::{
+    maneuver::{Maneuver, ManeuverRunner, ManeuverTick},
+    mission::Step,
+    nav::Navigation,
+    telemetry::TelemetrySample,
+};
+
+/// Lines kept for the HUD console.
+const CONSOLE_LINES: usize = 5;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptConfig {
+    /// script started with the `script` action
+    pub file: Option<PathBuf>,
+    /// seconds a script may run
+    pub timeout: f32,
+    /// max stick command for moves and turns, 0.0..1.0
+    pub speed: f32,
+    /// seconds a single move, turn or climb may take
+    pub step_timeout: f32,
+    /// Rhai operations a script may run, a runaway loop hits this
+    pub max_operations: u64,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            timeout: 300.0,
+            speed: 0.3,
+            step_timeout: 30.0,
+            max_operations: 10_000_000,
+        }
+    }
+}
+
+impl ScriptConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.timeout <= 0.0 || !self.timeout.is_finite() {
+            return Err(format!("script: timeout {} must be positive", self.timeout));
+        }
+        if !(self.speed > 0.0 && self.speed <= 1.0) {
+            return Err(format!("script: speed {} not in 0.0..=1.0", self.speed));
+        }
+        if self.step_timeout <= 0.0 || !self.step_timeout.is_finite() {
+            return Err(format!(
+                "script: step_timeout {} must be positive",
+                self.step_timeout
+            ));
+        }
+        if self.max_operations == 0 {
+            return Err("script: max_operations must be positive".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// Engine without access to anything outside the script.
+fn sandbox(max_operations: u64) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(max_operations);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(10_000);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(1_000);
+    engine
+}
+
+/// A script as loaded, checked for syntax errors.
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub name: String,
+    source: String,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        sandbox(1)
+            .compile(&source)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "script".to_owned());
+        Ok(Self { name, source })
+    }
+}
+
+/// Script output, the last lines are shown on the HUD.
+#[derive(Debug, Clone, Default)]
+pub struct Console(Arc<Mutex<VecDeque<String>>>);
+
+impl Console {
+    pub fn push(&self, line: String) {
+        tracing::info!("script: {line}");
+        let mut lines = self.0.lock().unwrap();
+        if lines.len() == CONSOLE_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// What the script may read, refreshed every tick.
+#[derive(Debug, Clone, Default)]
+struct View {
+    sample: TelemetrySample,
+    position: (f32, f32),
+    height: f32,
+    yaw: f32,
+    flying: bool,
+}
+
+impl View {
+    fn to_map(&self) -> Map {
+        let opt = |v: Option<f32>| v.map_or(Dynamic::UNIT, |v| Dynamic::from_float(v as f64));
+        let velocity = self.sample.velocity_mps();
+        let mut map = Map::new();
+        map.insert("height".into(), Dynamic::from_float(self.height as f64));
+        map.insert("yaw".into(), Dynamic::from_float(self.yaw as f64));
+        map.insert("pitch".into(), opt(self.sample.pitch));
+        map.insert("roll".into(), opt(self.sample.roll));
+        map.insert("north".into(), Dynamic::from_float(self.position.0 as f64));
+        map.insert("east".into(), Dynamic::from_float(self.position.1 as f64));
+        map.insert("vx".into(), opt(velocity.map(|v| v.0)));
+        map.insert("vy".into(), opt(velocity.map(|v| v.1)));
+        map.insert("vz".into(), opt(velocity.map(|v| 0.0 - v.2)));
+        map.insert(
+            "battery".into(),
+            self.sample
+                .battery_percentage
+                .map_or(Dynamic::UNIT, |b| Dynamic::from_int(b as i64)),
+        );
+        map.insert(
+            "fly_time".into(),
+            self.sample
+                .fly_time
+                .map_or(Dynamic::UNIT, |t| Dynamic::from_int(t as i64)),
+        );
+        map.insert("flying".into(), Dynamic::from_bool(self.flying));
+        map
+    }
+}
+
+type Reply = Sender<Result<(), String>>;
+
+/// Blocks the script until the UI has flown `request`.
+fn call(
+    requests: &Sender<(Maneuver, Reply)>,
+    abort: &AtomicBool,
+    deadline: Instant,
+    request: Maneuver,
+) -> Result<(), Box<EvalAltResult>> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    requests
+        .send((request, reply_tx))
+        .map_err(|_| "aborted".to_owned())?;
+    loop {
+        if abort.load(Ordering::Relaxed) {
+            return Err("aborted".to_owned().into());
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err("timed out".to_owned().into());
+        }
+        match reply_rx.recv_timeout(left.min(Duration::from_millis(100))) {
+            Ok(result) => return result.map_err(Into::into),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err("aborted".to_owned().into()),
+        }
+    }
+}
+
+/// Runs `script` with the flight functions registered.
+fn run(
+    script: Script,
+    config: ScriptConfig,
+    requests: Sender<(Maneuver, Reply)>,
+    view: Arc<Mutex<View>>,
+    abort: Arc<AtomicBool>,
+    console: Console,
+) -> Result<(), String> {
+    let deadline = Instant::now() + Duration::from_secs_f32(config.timeout);
+    let mut engine = sandbox(config.max_operations);
+    let printer = console.clone();
+    engine.on_print(move |line| printer.push(line.to_owned()));
+    let printer = console.clone();
+    engine.on_debug(move |line, _, _| printer.push(line.to_owned()));
+    let stop = abort.clone();
+    engine.on_progress(move |_| {
+        if stop.load(Ordering::Relaxed) {
+            Some("aborted".into())
+        } else if Instant::now() > deadline {
+            Some("timed out".into())
+        } else {
+            None
+        }
+    });
+
+    let register = |engine: &mut Engine, name: &str, request: fn() -> Maneuver| {
+        let (requests, abort) = (requests.clone(), abort.clone());
+        engine.register_fn(name, move || call(&requests, &abort, deadline, request()));
+    };
+    register(&mut engine, "takeoff", || Maneuver::TakeOff);
+    register(&mut engine, "land", || Maneuver::Land);
+    register(&mut engine, "photo", || Maneuver::Step(Step::Photo));
+
+    // every number argument may be given as an integer or a float
+    let register_number = |engine: &mut Engine, name: &str, step: fn(f32) -> Step| {
+        let (float_requests, float_abort) = (requests.clone(), abort.clone());
+        engine.register_fn(name, move |value: rhai::FLOAT| {
+            let request = Maneuver::Step(step(value as f32));
+            call(&float_requests, &float_abort, deadline, request)
+        });
+        let (int_requests, int_abort) = (requests.clone(), abort.clone());
+        engine.register_fn(name, move |value: rhai::INT| {
+            let request = Maneuver::Step(step(value as f32));
+            call(&int_requests, &int_abort, deadline, request)
+        });
+    };
+    register_number(&mut engine, "forward", |meters| Step::Move {
+        forward: meters,
+        right: 0.0,
+    });
+    register_number(&mut engine, "right", |meters| Step::Move {
+        forward: 0.0,
+        right: meters,
+    });
+    register_number(&mut engine, "turn", |degrees| Step::Turn { degrees });
+    register_number(&mut engine, "climb", |meters| Step::Climb { meters });
+    register_number(&mut engine, "altitude", |meters| Step::Altitude {
+        meters: meters.max(0.0),
+    });
+    register_number(&mut engine, "hover", |seconds| Step::Hover {
+        seconds: seconds.max(0.0),
+    });
+    engine.register_fn("telemetry", move || view.lock().unwrap().to_map());
+
+    engine.run(&script.source).map_err(|e| match *e {
+        // why `on_progress` stopped it
+        EvalAltResult::ErrorTerminated(ref reason, _) => reason.to_string(),
+        _ => e.to_string(),
+    })
+}
+
+/// A script running on its own thread, flown by the UI loop through `update`.
+pub struct ScriptRunner {
+    name: String,
+    config: ScriptConfig,
+    thread: Option<JoinHandle<Result<(), String>>>,
+    requests: Receiver<(Maneuver, Reply)>,
+    running: Option<(ManeuverRunner, Reply)>,
+    view: Arc<Mutex<View>>,
+    abort: Arc<AtomicBool>,
+    console: Console,
+}
+
+impl ScriptRunner {
+    pub fn start(script: Script, config: ScriptConfig, console: Console) -> Self {
+        let name = script.name.clone();
+        console.push(format!("{name} started"));
+        let (requests_tx, requests) = mpsc::channel();
+        let view = Arc::new(Mutex::new(View::default()));
+        let abort = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (config, view, abort, console) =
+                (config.clone(), view.clone(), abort.clone(), console.clone());
+            thread::spawn(move || run(script, config, requests_tx, view, abort, console))
+        };
+        Self {
+            name,
+            config,
+            thread: Some(thread),
+            requests,
+            running: None,
+            view,
+            abort,
+            console,
+        }
+    }
+
+    /// Stops the script, the running request fails.
+    pub fn abort(&mut self, reason: &str) {
+        self.console
+            .push(format!("{} aborted: {reason}", self.name));
+        self.abort.store(true, Ordering::Relaxed);
+        if let Some((_, reply)) = self.running.take() {
+            let _ = reply.send(Err(format!("aborted: {reason}")));
+        }
+    }
+
+    /// True once the script has ended, its result is on the console.
+    pub fn finished(&mut self) -> bool {
+        if !self.thread.as_ref().is_some_and(|t| t.is_finished()) {
+            return false;
+        }
+        let result = self.thread.take().unwrap().join();
+        if !self.abort.load(Ordering::Relaxed) {
+            match result {
+                Ok(Ok(())) => self.console.push(format!("{} done", self.name)),
+                Ok(Err(e)) => self.console.push(format!("{} failed: {e}", self.name)),
+                Err(_) => self.console.push(format!("{} crashed", self.name)),
+            }
+        }
+        true
+    }
+
+    /// Flies the current request one tick further.
+    pub fn update(&mut self, nav: &Navigation, sample: &TelemetrySample) -> ManeuverTick {
+        *self.view.lock().unwrap() = View {
+            sample: sample.clone(),
+            position: nav.position,
+            height: nav.height,
+            yaw: nav.yaw,
+            flying: nav.flying,
+        };
+        let mut tick = ManeuverTick::default();
+        if self.running.is_none() {
+            if let Ok((maneuver, reply)) = self.requests.try_recv() {
+                let (speed, step_timeout) = (self.config.speed, self.config.step_timeout);
+                match ManeuverRunner::start(maneuver, nav, speed, step_timeout, &mut tick) {
+                    Ok(Some(runner)) => self.running = Some((runner, reply)),
+                    Ok(None) => {
+                        let _ = reply.send(Ok(()));
+                    }
+                    Err(e) => {
+                        let _ = reply.send(Err(e));
+                    }
+                }
+                return tick;
+            }
+        }
+        if let Some((ref mut runner, _)) = self.running {
+            if let Some(result) = runner.update(nav, &mut tick) {
+                let (_, reply) = self.running.take().unwrap();
+                let _ = reply.send(result);
+            }
+        }
+        tick
+    }
+
+    /// HUD line.
+    pub fn status(&self) -> String {
+        match self.running {
+            Some((ref runner, _)) => {
+                format!("SCRIPT {}: {}", self.name, runner.maneuver().label())
+            }
+            None => format!("SCRIPT {}", self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(source: &str) -> Script {
+        Script {
+            name: "test".to_owned(),
+            source: source.to_owned(),
+        }
+    }
+
+    struct Flown {
+        console: Vec<String>,
+        /// degrees clockwise
+        turned: f32,
+        pictures: usize,
+    }
+
+    // runs the script against a drone hovering in place that turns 10 degrees per tick at full
+    // stick, until it ends
+    fn fly(source: &str, config: ScriptConfig) -> Flown {
+        let console = Console::default();
+        let mut runner = ScriptRunner::start(script(source), config, console.clone());
+        let mut nav = Navigation::default();
+        nav.flying = true;
+        nav.height = 1.0;
+        let sample = TelemetrySample::default();
+        let (mut turned, mut pictures) = (0.0, 0);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !runner.finished() {
+            assert!(Instant::now() < deadline, "script still running");
+            let tick = runner.update(&nav, &sample);
+            let turn = tick.sticks.turn_clockwise * 10.0;
+            turned += turn;
+            nav.yaw = (nav.yaw + turn + 180.0).rem_euclid(360.0) - 180.0;
+            pictures += tick.take_picture as usize;
+            thread::sleep(Duration::from_millis(1));
+        }
+        Flown {
+            console: console.lines(),
+            turned,
+            pictures,
+        }
+    }
+
+    fn last_line(flown: &Flown) -> &str {
+        flown.console.last().unwrap()
+    }
+
+    #[test]
+    fn full_turn() {
+        let flown = fly("turn(360);", ScriptConfig::default());
+        assert_eq!(last_line(&flown), "test done");
+        assert!((flown.turned - 360.0).abs() < 3.0, "{}", flown.turned);
+        let flown = fly("turn(-450.0);", ScriptConfig::default());
+        assert!((flown.turned + 450.0).abs() < 3.0, "{}", flown.turned);
+    }
+
+    #[test]
+    fn turns_and_photos_in_a_loop() {
+        let flown = fly(
+            "for i in 0..4 { turn(90); photo(); }",
+            ScriptConfig::default(),
+        );
+        assert_eq!(last_line(&flown), "test done");
+        // every turn starts where the last one ended, within its tolerance
+        assert!((flown.turned - 360.0).abs() < 4.0 * 3.0, "{}", flown.turned);
+        assert_eq!(flown.pictures, 4);
+    }
+
+    #[test]
+    fn runaway_loop_times_out() {
+        let config = ScriptConfig {
+            timeout: 0.2,
+            max_operations: u64::MAX,
+            ..Default::default()
+        };
+        let flown = fly("loop {}", config);
+        assert!(
+            last_line(&flown).contains("timed out"),
+            "{:?}",
+            flown.console
+        );
+    }
+
+    #[test]
+    fn blocked_call_times_out() {
+        let config = ScriptConfig {
+            timeout: 0.2,
+            ..Default::default()
+        };
+        let flown = fly("hover(60);", config);
+        assert!(
+            last_line(&flown).contains("timed out"),
+            "{:?}",
+            flown.console
+        );
+    }
+
+    #[test]
+    fn max_operations() {
+        let config = ScriptConfig {
+            max_operations: 1_000,
+            ..Default::default()
+        };
+        let flown = fly("let x = 0; loop { x += 1; }", config);
+        assert!(
+            last_line(&flown).starts_with("test failed"),
+            "{:?}",
+            flown.console
+        );
+        // well within the limit
+        let flown = fly(
+            "let x = 0; for i in 0..10 { x += i; }",
+            ScriptConfig::default(),
+        );
+        assert_eq!(last_line(&flown), "test done");
+    }
+
+    #[test]
+    fn abort_while_a_call_is_blocked() {
+        let console = Console::default();
+        let mut runner = ScriptRunner::start(
+            script("hover(60);"),
+            ScriptConfig::default(),
+            console.clone(),
+        );
+        let mut nav = Navigation::default();
+        nav.flying = true;
+        let sample = TelemetrySample::default();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while runner.running.is_none() {
+            assert!(Instant::now() < deadline, "hover never started");
+            runner.update(&nav, &sample);
+            thread::sleep(Duration::from_millis(1));
+        }
+        runner.abort("test");
+        while !runner.finished() {
+            assert!(Instant::now() < deadline, "script still blocked");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(console.lines().last().unwrap(), "test aborted: test");
+        assert!(runner.running.is_none());
+    }
+
+    #[test]
+    fn sandboxed() {
+        assert!(sandbox(1).compile(r#"eval("1 + 1")"#).is_err());
+        let flown = fly(r#"import "script" as s;"#, ScriptConfig::default());
+        assert!(
+            last_line(&flown).starts_with("test failed"),
+            "{:?}",
+            flown.console
+        );
+        let config = ScriptConfig::default();
+        let flown = fly(r#"let s = "x"; loop { s += s; }"#, config);
+        assert!(
+            last_line(&flown).starts_with("test failed"),
+            "{:?}",
+            flown.console
+        );
+    }
+}
@@ -0,0 +1,683 @@
+//! Tello SDK text commands from the HUD command console, stdin or a TCP port.
+//!
+//! Lines are parsed as Tello SDK commands (`takeoff`, `up 50`, `cw 90`, `flip l`, `speed 30`,
+//! `battery?`, ...) and flown with the same maneuvers as scripts. Every line is answered with
+//! `ok`, `error <reason>` or the value asked for. One command flies at a time, others are
+//! answered `error busy` until it is done. `stop`, `land` and `emergency` break a running
+//! command, mission or script off, read commands are answered right away.
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    drone::Flip,
+    maneuver::{Maneuver, ManeuverRunner, ManeuverTick},
+    mission::Step,
+    nav::Navigation,
+    telemetry::TelemetrySample,
+};
+
+/// Replies kept for the HUD.
+const FEEDBACK_LINES: usize = 3;
+const HISTORY_LINES: usize = 50;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandConfig {
+    /// address the command port listens on, e.g. "127.0.0.1:8889", none by default
+    pub port: Option<String>,
+    /// read commands from stdin, replies go to stdout
+    pub stdin: bool,
+    /// cm/s until changed with `speed`, 10..=100
+    pub speed: u32,
+    /// seconds a single move, turn or climb may take
+    pub step_timeout: f32,
+}
+
+impl Default for CommandConfig {
+    fn default() -> Self {
+        Self {
+            port: None,
+            stdin: false,
+            speed: 30,
+            step_timeout: 30.0,
+        }
+    }
+}
+
+impl CommandConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(10..=100).contains(&self.speed) {
+            return Err(format!("commands: speed {} not in 10..=100", self.speed));
+        }
+        if self.step_timeout <= 0.0 || !self.step_timeout.is_finite() {
+            return Err(format!(
+                "commands: step_timeout {} must be positive",
+                self.step_timeout
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Query {
+    Battery,
+    Height,
+    Speed,
+    Time,
+    Attitude,
+    Wifi,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// enters SDK mode on the drone, nothing to do here
+    Command,
+    Fly(Maneuver),
+    Stop,
+    Emergency,
+    Stream(bool),
+    /// cm/s
+    Speed(u32),
+    Read(Query),
+}
+
+// integer argument within `range`
+fn number(name: &str, args: &[&str], range: (i32, i32), unit: &str) -> Result<f32, String> {
+    let (min, max) = range;
+    let out_of_range = || format!("{name} takes {min}..{max} {unit}");
+    match args {
+        [arg] => match arg.parse::<i32>() {
+            Ok(n) if (min..=max).contains(&n) => Ok(n as f32),
+            _ => Err(out_of_range()),
+        },
+        _ => Err(out_of_range()),
+    }
+}
+
+/// Parses one SDK command line.
+pub fn parse(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((name, args)) = words.split_first() else {
+        return Err("empty command".to_owned());
+    };
+    let name = name.to_ascii_lowercase();
+    let cm = |args: &[&str]| number(&name, args, (20, 500), "cm").map(|cm| cm / 100.0);
+    let degrees = |args: &[&str]| number(&name, args, (1, 360), "degrees");
+    let command = match (name.as_str(), args) {
+        ("command", []) => Command::Command,
+        ("takeoff", []) => Command::Fly(Maneuver::TakeOff),
+        ("land", []) => Command::Fly(Maneuver::Land),
+        ("stop", []) => Command::Stop,
+        ("emergency", []) => Command::Emergency,
+        ("streamon", []) => Command::Stream(true),
+        ("streamoff", []) => Command::Stream(false),
+        ("up", _) => Command::Fly(Maneuver::Step(Step::Climb { meters: cm(args)? })),
+        ("down", _) => Command::Fly(Maneuver::Step(Step::Climb { meters: -cm(args)? })),
+        ("forward", _) => Command::Fly(Maneuver::Step(Step::Move {
+            forward: cm(args)?,
+            right: 0.0,
+        })),
+        ("back", _) => Command::Fly(Maneuver::Step(Step::Move {
+            forward: -cm(args)?,
+            right: 0.0,
+        })),
+        ("right", _) => Command::Fly(Maneuver::Step(Step::Move {
+            forward: 0.0,
+            right: cm(args)?,
+        })),
+        ("left", _) => Command::Fly(Maneuver::Step(Step::Move {
+            forward: 0.0,
+            right: -cm(args)?,
+        })),
+        ("cw", _) => Command::Fly(Maneuver::Step(Step::Turn {
+            degrees: degrees(args)?,
+        })),
+        ("ccw", _) => Command::Fly(Maneuver::Step(Step::Turn {
+            degrees: -degrees(args)?,
+        })),
+        ("flip", [direction]) => Command::Fly(Maneuver::Flip(match *direction {
+            "f" => Flip::Forward,
+            "b" => Flip::Back,
+            "l" => Flip::Left,
+            "r" => Flip::Right,
+            _ => return Err("flip takes l, r, f or b".to_owned()),
+        })),
+        ("speed", _) => Command::Speed(number(&name, args, (10, 100), "cm/s")? as u32),
+        ("battery?", []) => Command::Read(Query::Battery),
+        ("height?", []) => Command::Read(Query::Height),
+        ("speed?", []) => Command::Read(Query::Speed),
+        ("time?", []) => Command::Read(Query::Time),
+        ("attitude?", []) => Command::Read(Query::Attitude),
+        ("wifi?", []) => Command::Read(Query::Wifi),
+        _ => return Err(format!("unknown command `{}`", line.trim())),
+    };
+    Ok(command)
+}
+
+pub type Reply = Sender<String>;
+
+/// What the commands want from the drone this tick, besides the maneuver.
+#[derive(Debug, Default)]
+pub struct CommandTick {
+    pub maneuver: ManeuverTick,
+    pub hover: bool,
+    /// video on or off
+    pub stream: Option<bool>,
+    /// the running mission or script has to be broken off, with the command doing it
+    pub abort: Option<&'static str>,
+}
+
+/// Takes command lines from every source and flies them tick by tick.
+pub struct CommandRunner {
+    config: CommandConfig,
+    /// cm/s
+    speed: u32,
+    lines_tx: Sender<(String, Reply)>,
+    lines: Receiver<(String, Reply)>,
+    running: Option<(ManeuverRunner, Reply)>,
+}
+
+impl CommandRunner {
+    pub fn new(config: CommandConfig) -> Self {
+        let (lines_tx, lines) = mpsc::channel();
+        Self {
+            speed: config.speed,
+            config,
+            lines_tx,
+            lines,
+            running: None,
+        }
+    }
+
+    /// Where command sources send their lines, with the channel their reply goes to.
+    pub fn sender(&self) -> Sender<(String, Reply)> {
+        self.lines_tx.clone()
+    }
+
+    pub fn running(&self) -> Option<&Maneuver> {
+        self.running.as_ref().map(|(runner, _)| runner.maneuver())
+    }
+
+    /// Breaks the running command off.
+    pub fn abort(&mut self, reason: &str) {
+        if let Some((runner, reply)) = self.running.take() {
+            tracing::warn!("command {} aborted: {reason}", runner.maneuver().label());
+            let _ = reply.send(format!("error aborted: {reason}"));
+        }
+    }
+
+    /// Answers the waiting lines and flies the running command one tick further. While
+    /// `others`, a mission or script, fly the drone commands are refused as busy, but for
+    /// `stop`, `land` and `emergency` which ask for `CommandTick::abort`.
+    pub fn update(
+        &mut self,
+        nav: &Navigation,
+        sample: &TelemetrySample,
+        others: bool,
+    ) -> CommandTick {
+        let mut tick = CommandTick::default();
+        if let Some((ref mut runner, _)) = self.running {
+            if let Some(result) = runner.update(nav, &mut tick.maneuver) {
+                let (_, reply) = self.running.take().unwrap();
+                let _ = reply.send(match result {
+                    Ok(()) => "ok".to_owned(),
+                    Err(e) => format!("error {e}"),
+                });
+            }
+        }
+        while let Ok((line, reply)) = self.lines.try_recv() {
+            tracing::info!("command: {line}");
+            let answer = match parse(&line) {
+                Ok(command) => self.execute(command, nav, sample, others, reply.clone(), &mut tick),
+                Err(e) => Some(format!("error {e}")),
+            };
+            if let Some(answer) = answer {
+                let _ = reply.send(answer);
+            }
+        }
+        tick
+    }
+
+    // the answer, `None` when it comes once the maneuver is done
+    fn execute(
+        &mut self,
+        command: Command,
+        nav: &Navigation,
+        sample: &TelemetrySample,
+        others: bool,
+        reply: Reply,
+        tick: &mut CommandTick,
+    ) -> Option<String> {
+        let busy = self.running.is_some() || others;
+        match command {
+            Command::Command => Some("ok".to_owned()),
+            Command::Read(query) => Some(self.read(query, nav, sample)),
+            Command::Stop => {
+                self.abort("stop");
+                tick.abort = others.then_some("stop");
+                tick.hover = true;
+                Some("ok".to_owned())
+            }
+            // motors can't be cut from here, land as fast as the drone does
+            Command::Emergency => {
+                self.abort("emergency");
+                tick.abort = others.then_some("emergency");
+                tick.maneuver.land = nav.flying;
+                Some("ok".to_owned())
+            }
+            Command::Fly(Maneuver::Land) if busy => {
+                self.abort("land");
+                tick.abort = others.then_some("land");
+                self.fly(Maneuver::Land, nav, reply, tick)
+            }
+            _ if busy => Some("error busy".to_owned()),
+            Command::Stream(on) => {
+                tick.stream = Some(on);
+                Some("ok".to_owned())
+            }
+            Command::Speed(speed) => {
+                self.speed = speed;
+                Some("ok".to_owned())
+            }
+            Command::Fly(maneuver) => self.fly(maneuver, nav, reply, tick),
+        }
+    }
+
+    fn fly(
+        &mut self,
+        maneuver: Maneuver,
+        nav: &Navigation,
+        reply: Reply,
+        tick: &mut CommandTick,
+    ) -> Option<String> {
+        // the stick command giving about `speed`, full stick is about 1 m/s
+        let speed = self.speed as f32 / 100.0;
+        match ManeuverRunner::start(
+            maneuver,
+            nav,
+            speed,
+            self.config.step_timeout,
+            &mut tick.maneuver,
+        ) {
+            Ok(Some(runner)) => {
+                self.running = Some((runner, reply));
+                None
+            }
+            Ok(None) => Some("ok".to_owned()),
+            Err(e) => Some(format!("error {e}")),
+        }
+    }
+
+    fn read(&self, query: Query, nav: &Navigation, sample: &TelemetrySample) -> String {
+        let value = match query {
+            Query::Battery => sample.battery_percentage.map(|b| b.to_string()),
+            Query::Height => Some(format!("{:.0}dm", nav.height * 10.0)),
+            Query::Speed => Some(self.speed.to_string()),
+            Query::Time => sample.fly_time.map(|t| format!("{t}s")),
+            Query::Attitude => sample.pitch.zip(sample.roll).map(|(pitch, roll)| {
+                format!("pitch:{pitch:.0};roll:{roll:.0};yaw:{:.0};", nav.yaw)
+            }),
+            Query::Wifi => sample.wifi_strength.map(|w| w.to_string()),
+        };
+        value.unwrap_or_else(|| "error no data".to_owned())
+    }
+}
+
+// sends `line` and waits for its answer
+fn ask(lines: &Sender<(String, Reply)>, line: String) -> Option<String> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    lines.send((line, reply_tx)).ok()?;
+    reply_rx.recv().ok()
+}
+
+/// Answers the commands typed on stdin on stdout.
+pub fn read_stdin(lines: Sender<(String, Reply)>) {
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match ask(&lines, line) {
+                Some(answer) => println!("{answer}"),
+                None => break,
+            }
+        }
+    });
+}
+
+fn serve(stream: TcpStream, lines: Sender<(String, Reply)>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(answer) = ask(&lines, line) else {
+            break;
+        };
+        writeln!(writer, "{answer}")?;
+    }
+    Ok(())
+}
+
+/// Answers commands sent by line over TCP on `address`, a thread per connection.
+pub fn listen(address: &str, lines: Sender<(String, Reply)>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    tracing::info!("command port listening on {address}");
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::error!("command port: {e}");
+                    continue;
+                }
+            };
+            let lines = lines.clone();
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(e) = serve(stream, lines) {
+                    tracing::warn!("command connection {peer:?}: {e}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// The HUD command console: the line being typed, history and the last replies.
+#[derive(Debug)]
+pub struct CommandLine {
+    open: bool,
+    text: String,
+    history: VecDeque<String>,
+    /// history entry shown, counted from the newest
+    browsing: Option<usize>,
+    feedback: VecDeque<String>,
+    reply_tx: Reply,
+    replies: Receiver<String>,
+}
+
+impl Default for CommandLine {
+    fn default() -> Self {
+        let (reply_tx, replies) = mpsc::channel();
+        Self {
+            open: false,
+            text: String::new(),
+            history: VecDeque::new(),
+            browsing: None,
+            feedback: VecDeque::new(),
+            reply_tx,
+            replies,
+        }
+    }
+}
+
+impl CommandLine {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.browsing = None;
+    }
+
+    pub fn backspace(&mut self) {
+        self.text.pop();
+    }
+
+    /// Older history entry.
+    pub fn up(&mut self) {
+        let index = self.browsing.map_or(0, |i| i + 1);
+        if let Some(line) = self.history.get(self.history.len().wrapping_sub(index + 1)) {
+            self.text = line.clone();
+            self.browsing = Some(index);
+        }
+    }
+
+    /// Newer history entry, past the newest clears the line.
+    pub fn down(&mut self) {
+        match self.browsing {
+            Some(0) | None => {
+                self.browsing = None;
+                self.text.clear();
+            }
+            Some(index) => {
+                self.browsing = Some(index - 1);
+                self.text = self.history[self.history.len() - index].clone();
+            }
+        }
+    }
+
+    /// Sends the typed line to `lines`, its reply shows up in `feedback`.
+    pub fn submit(&mut self, lines: &Sender<(String, Reply)>) {
+        let line = self.text.trim().to_owned();
+        self.text.clear();
+        self.browsing = None;
+        if line.is_empty() {
+            return;
+        }
+        if self.history.back() != Some(&line) {
+            if self.history.len() == HISTORY_LINES {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.clone());
+        }
+        self.push(format!("> {line}"));
+        let _ = lines.send((line, self.reply_tx.clone()));
+    }
+
+    fn push(&mut self, line: String) {
+        if self.feedback.len() == FEEDBACK_LINES {
+            self.feedback.pop_front();
+        }
+        self.feedback.push_back(line);
+    }
+
+    /// The last replies, oldest first.
+    pub fn feedback(&mut self) -> Vec<String> {
+        while let Ok(reply) = self.replies.try_recv() {
+            self.push(reply);
+        }
+        self.feedback.iter().cloned().collect()
+    }
+
+    /// The line being typed, empty while closed.
+    pub fn prompt(&self) -> String {
+        if self.open {
+            format!("SDK> {}_", self.text)
+        } else {
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_moves_in_meters() {
+        assert_eq!(
+            parse("up 50"),
+            Ok(Command::Fly(Maneuver::Step(Step::Climb { meters: 0.5 })))
+        );
+        assert_eq!(
+            parse("left 120"),
+            Ok(Command::Fly(Maneuver::Step(Step::Move {
+                forward: 0.0,
+                right: -1.2
+            })))
+        );
+        assert_eq!(
+            parse("ccw 90"),
+            Ok(Command::Fly(Maneuver::Step(Step::Turn { degrees: -90.0 })))
+        );
+    }
+
+    #[test]
+    fn parses_flips_and_settings() {
+        assert_eq!(
+            parse("flip l"),
+            Ok(Command::Fly(Maneuver::Flip(Flip::Left)))
+        );
+        assert_eq!(parse("speed 30"), Ok(Command::Speed(30)));
+        assert_eq!(parse("TAKEOFF"), Ok(Command::Fly(Maneuver::TakeOff)));
+        assert_eq!(parse("battery?"), Ok(Command::Read(Query::Battery)));
+    }
+
+    #[test]
+    fn refuses_bad_commands() {
+        assert!(parse("up 10").is_err());
+        assert!(parse("cw 361").is_err());
+        assert!(parse("forward").is_err());
+        assert!(parse("flip x").is_err());
+        assert!(parse("takeoff now").is_err());
+        assert!(parse("barrel roll").is_err());
+        assert!(parse("").is_err());
+    }
+
+    fn flying(yaw: f32) -> Navigation {
+        let mut nav = Navigation::default();
+        nav.flying = true;
+        nav.height = 1.0;
+        nav.yaw = yaw;
+        nav
+    }
+
+    // sends `line`, returns where the reply goes
+    fn send(runner: &CommandRunner, line: &str) -> Receiver<String> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        runner.sender().send((line.to_owned(), reply_tx)).unwrap();
+        reply_rx
+    }
+
+    // flies the turn command on a drone turning 10 degrees per tick at full stick, returns
+    // the degrees turned clockwise
+    fn turn(line: &str, nav: &mut Navigation) -> f32 {
+        let mut runner = CommandRunner::new(CommandConfig::default());
+        let reply = send(&runner, line);
+        let sample = TelemetrySample::default();
+        let mut turned = 0.0;
+        for _ in 0..10_000 {
+            let tick = runner.update(nav, &sample, false);
+            if let Ok(answer) = reply.try_recv() {
+                assert_eq!(answer, "ok", "{line}");
+                return turned;
+            }
+            let turn = tick.maneuver.sticks.turn_clockwise * 10.0;
+            assert!(turn >= 0.0 || line.starts_with("ccw"), "{line} turned back");
+            turned += turn;
+            nav.yaw = (nav.yaw + turn + 180.0).rem_euclid(360.0) - 180.0;
+        }
+        panic!("{line} never finished");
+    }
+
+    #[test]
+    fn turns_the_whole_way() {
+        for (line, degrees) in [
+            ("cw 90", 90.0),
+            ("cw 270", 270.0),
+            ("cw 360", 360.0),
+            ("ccw 270", -270.0),
+        ] {
+            let mut nav = flying(170.0);
+            let turned = turn(line, &mut nav);
+            assert!((turned - degrees).abs() < 3.0, "{line} turned {turned}");
+        }
+    }
+
+    #[test]
+    fn stop_land_and_emergency_break_others_off() {
+        let mut runner = CommandRunner::new(CommandConfig::default());
+        let nav = flying(0.0);
+        let sample = TelemetrySample::default();
+
+        let busy = send(&runner, "up 50");
+        let tick = runner.update(&nav, &sample, true);
+        assert_eq!(busy.try_recv().unwrap(), "error busy");
+        assert_eq!(tick.abort, None);
+
+        let stop = send(&runner, "stop");
+        let tick = runner.update(&nav, &sample, true);
+        assert_eq!(stop.try_recv().unwrap(), "ok");
+        assert_eq!(tick.abort, Some("stop"));
+        assert!(tick.hover);
+
+        let emergency = send(&runner, "emergency");
+        let tick = runner.update(&nav, &sample, true);
+        assert_eq!(emergency.try_recv().unwrap(), "ok");
+        assert_eq!(tick.abort, Some("emergency"));
+        assert!(tick.maneuver.land);
+
+        // answered once the drone is down
+        let land = send(&runner, "land");
+        let tick = runner.update(&nav, &sample, true);
+        assert_eq!(tick.abort, Some("land"));
+        assert!(tick.maneuver.land);
+        assert!(land.try_recv().is_err());
+        assert_eq!(runner.running(), Some(&Maneuver::Land));
+        let tick = runner.update(&Navigation::default(), &sample, false);
+        assert_eq!(land.try_recv().unwrap(), "ok");
+        assert_eq!(tick.abort, None);
+    }
+
+    #[test]
+    fn land_breaks_a_running_command_off() {
+        let mut runner = CommandRunner::new(CommandConfig::default());
+        let nav = flying(0.0);
+        let sample = TelemetrySample::default();
+        let up = send(&runner, "up 50");
+        runner.update(&nav, &sample, false);
+        assert!(runner.running().is_some());
+        let land = send(&runner, "land");
+        let tick = runner.update(&nav, &sample, false);
+        assert_eq!(up.try_recv().unwrap(), "error aborted: land");
+        assert!(land.try_recv().is_err());
+        assert!(tick.maneuver.land);
+        // nothing else to break off
+        assert_eq!(tick.abort, None);
+    }
+
+    #[test]
+    fn history_goes_back_and_forth() {
+        let (lines, _rx) = mpsc::channel();
+        let mut line = CommandLine::default();
+        for command in ["takeoff", "up 50", "land"] {
+            line.insert(command);
+            line.submit(&lines);
+        }
+        line.up();
+        assert_eq!(line.text, "land");
+        line.up();
+        line.up();
+        line.up();
+        assert_eq!(line.text, "takeoff");
+        line.down();
+        assert_eq!(line.text, "up 50");
+        line.down();
+        line.down();
+        assert_eq!(line.text, "");
+    }
+}
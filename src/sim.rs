@@ -0,0 +1,539 @@
+//! Software drone for flying the UI without a Tello.
+//!
+//! Stick commands are integrated into position and attitude at `PHYSICS_HZ`, the battery drains
+//! while flying and `UpdateData` is emitted at `TELEMETRY_HZ` the same way the real controller
+//! does. Video comes from an optional H.264 elementary stream file played in a loop.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use rust_tello::UpdateData;
+
+use crate::{
+    drone::{Drone, Flip},
+    telemetry::{TelemetrySample, HEIGHT_UNIT_M, VELOCITY_UNIT_MPS},
+};
+
+const PHYSICS_HZ: u32 = 50;
+const TELEMETRY_HZ: u32 = 10;
+const VIDEO_FPS: u32 = 30;
+
+const MAX_SPEED: f32 = 3.0; // m/s at full stick
+const MAX_CLIMB: f32 = 1.5; // m/s
+const MAX_YAW_RATE: f32 = 120.0; // deg/s
+const VELOCITY_TAU: f32 = 0.4; // s, how fast the drone follows the sticks
+const TAKEOFF_HEIGHT: f32 = 0.8; // m
+const TAKEOFF_CLIMB: f32 = 0.6; // m/s
+const LANDING_DESCENT: f32 = 0.5; // m/s
+const DRAIN_FLYING: f32 = 100.0 / 780.0; // %/s, ~13 minutes of flight
+const DRAIN_IDLE: f32 = 0.01; // %/s
+const LOW_BATTERY_LANDING: f32 = 3.0; // %
+const FLIP_DRAIN: f32 = 0.5; // %
+const FLIP_OFFSET: f32 = 0.3; // m
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Landed,
+    TakingOff,
+    Flying,
+    Landing,
+}
+
+#[derive(Debug)]
+struct SimState {
+    phase: Phase,
+    /// north, east, up in meters from the start point
+    position: (f32, f32, f32),
+    /// north, east, up in m/s
+    velocity: (f32, f32, f32),
+    yaw: f32,
+    pitch: f32,
+    roll: f32,
+    forward: f32,
+    right: f32,
+    up: f32,
+    turn_clockwise: f32,
+    battery: f32,
+    fly_time: f32,
+    video: bool,
+    pictures: u32,
+}
+
+impl SimState {
+    fn new() -> Self {
+        Self {
+            phase: Phase::Landed,
+            position: (0.0, 0.0, 0.0),
+            velocity: (0.0, 0.0, 0.0),
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            forward: 0.0,
+            right: 0.0,
+            up: 0.0,
+            turn_clockwise: 0.0,
+            battery: 100.0,
+            fly_time: 0.0,
+            video: false,
+            pictures: 0,
+        }
+    }
+
+    fn step(&mut self, dt: f32) {
+        let airborne = self.phase != Phase::Landed;
+        self.battery =
+            (self.battery - dt * if airborne { DRAIN_FLYING } else { DRAIN_IDLE }).max(0.0);
+        if airborne {
+            self.fly_time += dt;
+        }
+        if self.phase == Phase::Flying && self.battery < LOW_BATTERY_LANDING {
+            tracing::warn!("sim: battery critical, landing");
+            self.phase = Phase::Landing;
+        }
+
+        // commanded velocities in the world frame
+        let (sin, cos) = self.yaw.to_radians().sin_cos();
+        let (mut north, mut east, mut climb, mut yaw_rate) = (0.0, 0.0, 0.0, 0.0);
+        match self.phase {
+            Phase::Landed => {}
+            Phase::TakingOff => {
+                climb = TAKEOFF_CLIMB;
+                if self.position.2 >= TAKEOFF_HEIGHT {
+                    self.phase = Phase::Flying;
+                }
+            }
+            Phase::Flying => {
+                let forward = self.forward * MAX_SPEED;
+                let right = self.right * MAX_SPEED;
+                north = forward * cos - right * sin;
+                east = forward * sin + right * cos;
+                climb = self.up * MAX_CLIMB;
+                yaw_rate = self.turn_clockwise * MAX_YAW_RATE;
+            }
+            Phase::Landing => climb = -LANDING_DESCENT,
+        }
+
+        let follow = (dt / VELOCITY_TAU).min(1.0);
+        let (vn, ve, vu) = self.velocity;
+        let accel_n = (north - vn) * follow;
+        let accel_e = (east - ve) * follow;
+        self.velocity = (vn + accel_n, ve + accel_e, vu + (climb - vu) * follow);
+
+        // tilt into the acceleration, in the body frame
+        let accel_fwd = accel_n * cos + accel_e * sin;
+        let accel_right = -accel_n * sin + accel_e * cos;
+        self.pitch = (-accel_fwd / dt * 4.0).clamp(-25.0, 25.0);
+        self.roll = (accel_right / dt * 4.0).clamp(-25.0, 25.0);
+
+        let (x, y, z) = self.position;
+        self.position = (
+            x + self.velocity.0 * dt,
+            y + self.velocity.1 * dt,
+            (z + self.velocity.2 * dt).max(0.0),
+        );
+        self.yaw = (self.yaw + yaw_rate * dt + 180.0).rem_euclid(360.0) - 180.0;
+
+        if self.phase == Phase::Landing && self.position.2 <= 0.0 {
+            tracing::info!("sim: landed");
+            self.phase = Phase::Landed;
+            self.velocity = (0.0, 0.0, 0.0);
+            self.pitch = 0.0;
+            self.roll = 0.0;
+        }
+    }
+
+    fn sample(&self) -> TelemetrySample {
+        let (vn, ve, vu) = self.velocity;
+        TelemetrySample {
+            wifi_strength: Some(90),
+            height: Some((self.position.2 / HEIGHT_UNIT_M).round() as i32),
+            fly_time: Some(self.fly_time as i32),
+            battery_percentage: Some(self.battery.ceil() as i32),
+            battery_milli_volts: Some(3400 + (self.battery * 8.0) as i32),
+            light: Some(100),
+            pitch: Some(self.pitch),
+            roll: Some(self.roll),
+            yaw: Some(self.yaw),
+            temperature: Some(40.0 + self.fly_time.min(600.0) / 20.0),
+            vx: Some(vn / VELOCITY_UNIT_MPS),
+            vy: Some(ve / VELOCITY_UNIT_MPS),
+            vz: Some(-vu / VELOCITY_UNIT_MPS),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimConfig {
+    /// H.264 elementary stream sent as the video feed while video is on
+    pub video_clip: Option<PathBuf>,
+    /// image copied into `pictures_dir` on every `take_picture`
+    pub picture: Option<PathBuf>,
+    pub pictures_dir: PathBuf,
+}
+
+pub struct Simulator {
+    state: Arc<Mutex<SimState>>,
+    config: SimConfig,
+    // without a clip the video widget still gets a live, silent channel
+    _video_tx: Option<Sender<Vec<u8>>>,
+}
+
+impl Simulator {
+    /// Starts the simulation, telemetry goes to `update_tx` and video to `video_tx`.
+    pub fn spawn(
+        config: SimConfig,
+        update_tx: Sender<UpdateData>,
+        video_tx: Sender<Vec<u8>>,
+    ) -> Self {
+        let state = Arc::new(Mutex::new(SimState::new()));
+        let physics = state.clone();
+        thread::spawn(move || physics_thread(physics, update_tx));
+        let units = match config.video_clip {
+            Some(ref clip) => load_clip(clip)
+                .map_err(|e| tracing::error!("sim: can't load video clip {}: {e}", clip.display()))
+                .ok(),
+            None => None,
+        };
+        let _video_tx = match units {
+            Some(units) => {
+                let video = state.clone();
+                thread::spawn(move || video_thread(video, units, video_tx));
+                None
+            }
+            None => Some(video_tx),
+        };
+        Self {
+            state,
+            config,
+            _video_tx,
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, SimState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Drone for Simulator {
+    fn takeoff(&mut self) {
+        let mut st = self.state();
+        if st.phase == Phase::Landed {
+            st.phase = Phase::TakingOff;
+        }
+    }
+
+    fn land(&mut self) {
+        let mut st = self.state();
+        if st.phase != Phase::Landed {
+            st.phase = Phase::Landing;
+        }
+    }
+
+    // thrown right away
+    fn throw_takeoff(&mut self) {
+        tracing::info!("sim: thrown");
+        self.takeoff();
+    }
+
+    // the palm is at the takeoff height
+    fn palm_land(&mut self) {
+        tracing::info!("sim: landing on the palm");
+        self.land();
+    }
+
+    fn hover(&mut self) {
+        let mut st = self.state();
+        st.forward = 0.0;
+        st.right = 0.0;
+        st.up = 0.0;
+        st.turn_clockwise = 0.0;
+        st.velocity = (0.0, 0.0, 0.0);
+    }
+
+    fn flying(&mut self) -> bool {
+        self.state().phase != Phase::Landed
+    }
+
+    fn take_picture(&mut self) {
+        let n = {
+            let mut st = self.state();
+            st.pictures += 1;
+            st.pictures
+        };
+        let Some(ref picture) = self.config.picture else {
+            tracing::info!("sim: picture {n} taken");
+            return;
+        };
+        let unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let target = self
+            .config
+            .pictures_dir
+            .join(format!("sim_{unix_ms}_{n:04}.jpg"));
+        if let Err(e) =
+            fs::create_dir_all(&self.config.pictures_dir).and_then(|_| fs::copy(picture, &target))
+        {
+            tracing::error!("sim: can't save picture {}: {e}", target.display());
+        }
+    }
+
+    fn toggle_video(&mut self) {
+        let mut st = self.state();
+        st.video = !st.video;
+    }
+
+    fn forward(&mut self, speed: f32) {
+        self.state().forward = speed.clamp(-1.0, 1.0);
+    }
+
+    fn right(&mut self, speed: f32) {
+        self.state().right = speed.clamp(-1.0, 1.0);
+    }
+
+    fn up(&mut self, speed: f32) {
+        self.state().up = speed.clamp(-1.0, 1.0);
+    }
+
+    fn turn_clockwise(&mut self, speed: f32) {
+        self.state().turn_clockwise = speed.clamp(-1.0, 1.0);
+    }
+
+    fn flip(&mut self, flip: Flip) {
+        let mut st = self.state();
+        if st.phase != Phase::Flying {
+            return;
+        }
+        tracing::info!("sim: flip {}", flip.label());
+        // a flip costs about a second of hover and leaves the drone a bit off
+        st.battery = (st.battery - FLIP_DRAIN).max(0.0);
+        let (forward, right) = match flip {
+            Flip::Forward => (FLIP_OFFSET, 0.0),
+            Flip::Back => (-FLIP_OFFSET, 0.0),
+            Flip::Left => (0.0, -FLIP_OFFSET),
+            Flip::Right => (0.0, FLIP_OFFSET),
+        };
+        let (sin, cos) = st.yaw.to_radians().sin_cos();
+        st.position.0 += forward * cos - right * sin;
+        st.position.1 += forward * sin + right * cos;
+    }
+}
+
+fn physics_thread(state: Arc<Mutex<SimState>>, update_tx: Sender<UpdateData>) {
+    let period = Duration::from_secs(1) / PHYSICS_HZ;
+    let telemetry_every = PHYSICS_HZ / TELEMETRY_HZ;
+    let mut last = Instant::now();
+    for tick in 0.. {
+        thread::sleep(period);
+        let now = Instant::now();
+        let dt = now.duration_since(last).as_secs_f32();
+        last = now;
+        let mut st = state.lock().unwrap();
+        st.step(dt);
+        if tick % telemetry_every == 0 {
+            let sample = st.sample();
+            drop(st);
+            if update_tx.send(sample.to_update_data()).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn load_clip(path: &Path) -> std::io::Result<Vec<Vec<u8>>> {
+    let units = split_units(&fs::read(path)?);
+    if units.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "no NAL units found",
+        ));
+    }
+    Ok(units)
+}
+
+/// Splits an H.264 elementary stream into NAL units, start codes included.
+fn split_units(data: &[u8]) -> Vec<Vec<u8>> {
+    let starts: Vec<usize> = (0..data.len().saturating_sub(3))
+        .filter(|&i| data[i..i + 4] == [0, 0, 0, 1])
+        .collect();
+    let mut units = Vec::with_capacity(starts.len());
+    for (n, &start) in starts.iter().enumerate() {
+        let end = starts.get(n + 1).copied().unwrap_or(data.len());
+        units.push(data[start..end].to_vec());
+    }
+    units
+}
+
+fn video_thread(state: Arc<Mutex<SimState>>, units: Vec<Vec<u8>>, video_tx: Sender<Vec<u8>>) {
+    let period = Duration::from_secs(1) / VIDEO_FPS;
+    for unit in units.iter().cycle() {
+        // parameter sets and slices go out one per tick, close enough to the frame rate
+        thread::sleep(period);
+        if !state.lock().unwrap().video {
+            continue;
+        }
+        if video_tx.send(unit.clone()).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / PHYSICS_HZ as f32;
+
+    // steps until `done` or `secs` are up, returns the seconds it took
+    fn run(st: &mut SimState, secs: f32, done: impl Fn(&SimState) -> bool) -> Option<f32> {
+        for tick in 0..(secs / DT) as u32 {
+            if done(st) {
+                return Some(tick as f32 * DT);
+            }
+            st.step(DT);
+        }
+        None
+    }
+
+    fn flying() -> SimState {
+        let mut st = SimState::new();
+        st.phase = Phase::TakingOff;
+        run(&mut st, 5.0, |st| st.phase == Phase::Flying).unwrap();
+        // settle into the hover
+        run(&mut st, 3.0, |_| false);
+        st
+    }
+
+    #[test]
+    fn takeoff_hover_land() {
+        let mut st = SimState::new();
+        run(&mut st, 1.0, |_| false);
+        assert_eq!(st.phase, Phase::Landed);
+        assert_eq!(st.position, (0.0, 0.0, 0.0));
+        assert_eq!(st.fly_time, 0.0);
+
+        st.phase = Phase::TakingOff;
+        let took = run(&mut st, 5.0, |st| st.phase == Phase::Flying).unwrap();
+        assert!(st.position.2 >= TAKEOFF_HEIGHT);
+        assert!(took > TAKEOFF_HEIGHT / TAKEOFF_CLIMB, "{took}");
+
+        // centered sticks hold the height
+        let height = st.position.2;
+        run(&mut st, 3.0, |_| false);
+        assert_eq!(st.phase, Phase::Flying);
+        assert!((st.position.2 - height).abs() < 0.3, "{}", st.position.2);
+        assert!(st.velocity.2.abs() < 0.01);
+
+        st.phase = Phase::Landing;
+        run(&mut st, 10.0, |st| st.phase == Phase::Landed).unwrap();
+        assert_eq!(st.position.2, 0.0);
+        assert_eq!(st.velocity, (0.0, 0.0, 0.0));
+        assert!(st.fly_time > 3.0);
+        assert_eq!(st.sample().height, Some(0));
+    }
+
+    #[test]
+    fn empty_battery_lands() {
+        let mut st = flying();
+        st.battery = LOW_BATTERY_LANDING + 0.01;
+        run(&mut st, 1.0, |st| st.phase == Phase::Landing).unwrap();
+        run(&mut st, 10.0, |st| st.phase == Phase::Landed).unwrap();
+    }
+
+    #[test]
+    fn sticks_in_the_body_frame() {
+        // facing east, forward flies east
+        let mut st = flying();
+        st.yaw = 90.0;
+        let start = st.position;
+        st.forward = 1.0;
+        run(&mut st, 3.0, |_| false);
+        assert!(
+            (st.velocity.1 - MAX_SPEED).abs() < 0.01,
+            "{:?}",
+            st.velocity
+        );
+        assert!(st.velocity.0.abs() < 0.01);
+        assert!(st.position.1 - start.1 > 6.0);
+        assert!((st.position.0 - start.0).abs() < 0.01);
+        // speeding up tilts forward, pitch down
+        let mut st = flying();
+        st.forward = 1.0;
+        st.step(DT);
+        assert!(st.pitch < 0.0);
+        assert_eq!(st.roll, 0.0);
+        // right slides right, facing north that's east
+        let mut st = flying();
+        st.right = -0.5;
+        run(&mut st, 3.0, |_| false);
+        assert!((st.velocity.1 + 0.5 * MAX_SPEED).abs() < 0.01);
+        // telemetry in the drone's units
+        let sample = st.sample();
+        assert!((sample.vy.unwrap() * VELOCITY_UNIT_MPS - st.velocity.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn climbing_and_turning() {
+        let mut st = flying();
+        let height = st.position.2;
+        st.up = 1.0;
+        run(&mut st, 2.0, |_| false);
+        assert!(st.position.2 - height > 2.0);
+        // climb reported as negative vz, like the drone
+        assert!(st.sample().vz.unwrap() < 0.0);
+
+        let mut st = flying();
+        st.turn_clockwise = 0.5;
+        run(&mut st, 1.0, |_| false);
+        assert!((st.yaw - 60.0).abs() < 1.0, "{}", st.yaw);
+        // wraps past south
+        st.turn_clockwise = 1.0;
+        run(&mut st, 1.5, |_| false);
+        assert!((st.yaw + 120.0).abs() < 1.0, "{}", st.yaw);
+        // no sticks on the ground
+        let mut st = SimState::new();
+        st.forward = 1.0;
+        st.turn_clockwise = 1.0;
+        run(&mut st, 1.0, |_| false);
+        assert_eq!(st.position, (0.0, 0.0, 0.0));
+        assert_eq!(st.yaw, 0.0);
+    }
+
+    #[test]
+    fn clip_splits_at_start_codes() {
+        let clip = [
+            0, 0, 0, 1, 0x67, 1, 2, 0, 0, 0, 1, 0x68, 3, 0, 0, 0, 1, 0x65, 4, 5, 6,
+        ];
+        assert_eq!(
+            split_units(&clip),
+            vec![
+                vec![0, 0, 0, 1, 0x67, 1, 2],
+                vec![0, 0, 0, 1, 0x68, 3],
+                vec![0, 0, 0, 1, 0x65, 4, 5, 6],
+            ]
+        );
+        // anything before the first start code is dropped
+        assert_eq!(
+            split_units(&[9, 9, 0, 0, 0, 1, 7]),
+            vec![vec![0, 0, 0, 1, 7]]
+        );
+        assert!(split_units(&[0, 0, 1, 0x67]).is_empty());
+        assert!(split_units(&[]).is_empty());
+    }
+
+    #[test]
+    fn clip_without_units() {
+        let path = std::env::temp_dir().join(format!("tello-sim-{}.h264", std::process::id()));
+        fs::write(&path, [1, 2, 3, 4, 5]).unwrap();
+        assert!(load_clip(&path).is_err());
+        fs::write(&path, [0, 0, 0, 1, 0x67]).unwrap();
+        assert_eq!(load_clip(&path).unwrap().len(), 1);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,53 @@
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+
+const SAMPLE_RATE: i32 = 22050;
+const VOLUME: i16 = 3000;
+
+/// Short warning tones, played through the default audio device.
+pub struct Beeper {
+    queue: AudioQueue<i16>,
+}
+
+impl Beeper {
+    /// `None` when there is no audio device, alarms are then only shown on the HUD.
+    pub fn open() -> Option<Self> {
+        let audio = sdl2::init()
+            .and_then(|sdl| sdl.audio())
+            .map_err(|e| tracing::warn!("no audio, alarms are silent: {e}"))
+            .ok()?;
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let queue = audio
+            .open_queue::<i16, _>(None, &spec)
+            .map_err(|e| tracing::warn!("can't open audio device, alarms are silent: {e}"))
+            .ok()?;
+        queue.resume();
+        Some(Self { queue })
+    }
+
+    /// Queues square wave tones, `(frequency in Hz, duration in ms)`, frequency 0 is a pause.
+    pub fn play(&self, tones: &[(u32, u32)]) {
+        let mut samples = Vec::new();
+        for &(freq, ms) in tones {
+            let len = (SAMPLE_RATE as u32 * ms / 1000) as usize;
+            if freq == 0 {
+                samples.extend(std::iter::repeat_n(0, len));
+                continue;
+            }
+            let half_period = (SAMPLE_RATE as u32 / freq / 2).max(1) as usize;
+            samples.extend((0..len).map(|i| {
+                if (i / half_period).is_multiple_of(2) {
+                    VOLUME
+                } else {
+                    -VOLUME
+                }
+            }));
+        }
+        if let Err(e) = self.queue.queue_audio(&samples) {
+            tracing::warn!("can't play alarm: {e}");
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Photo survey: a lawnmower grid over a rectangle, flown as a mission.
+//!
+//! The rectangle starts where the survey is started and extends `length` meters forward and
+//! `width` meters to the right. Lanes run forward and back, `lane_spacing` apart, with a
+//! picture every `photo_spacing` meters along them.
+
+use serde::Deserialize;
+
+use crate::mission::{Mission, Step};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SurveyConfig {
+    /// meters forward from the start point
+    pub length: f32,
+    /// meters to the right of the start point
+    pub width: f32,
+    /// meters above the takeoff point the grid is flown at
+    pub height: f32,
+    /// meters between lanes
+    pub lane_spacing: f32,
+    /// meters between pictures along a lane
+    pub photo_spacing: f32,
+    /// max stick command, 0.0..1.0
+    pub speed: f32,
+    /// seconds, the survey is aborted when a leg takes longer
+    pub step_timeout: f32,
+}
+
+impl Default for SurveyConfig {
+    fn default() -> Self {
+        Self {
+            length: 10.0,
+            width: 10.0,
+            height: 3.0,
+            lane_spacing: 2.0,
+            photo_spacing: 2.0,
+            speed: 0.3,
+            step_timeout: 30.0,
+        }
+    }
+}
+
+impl SurveyConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("length", self.length),
+            ("width", self.width),
+            ("height", self.height),
+        ] {
+            if value < 0.0 || !value.is_finite() {
+                return Err(format!("survey: {name} must not be negative"));
+            }
+        }
+        for (name, value) in [
+            ("lane_spacing", self.lane_spacing),
+            ("photo_spacing", self.photo_spacing),
+        ] {
+            if value <= 0.0 || !value.is_finite() {
+                return Err(format!("survey: {name} must be positive"));
+            }
+        }
+        self.mission()
+            .validate()
+            .map_err(|e| format!("survey: {e}"))
+    }
+
+    /// The grid as mission steps.
+    pub fn mission(&self) -> Mission {
+        // evenly spread, no lane or leg wider than asked for
+        let lanes = (self.width / self.lane_spacing).ceil().max(0.0) as usize + 1;
+        let lane_step = if lanes > 1 {
+            self.width / (lanes - 1) as f32
+        } else {
+            0.0
+        };
+        let legs = ((self.length / self.photo_spacing).ceil() as usize).max(1);
+        let leg = self.length / legs as f32;
+
+        let mut steps = vec![Step::Altitude {
+            meters: self.height,
+        }];
+        for lane in 0..lanes {
+            if lane > 0 {
+                steps.push(Step::Move {
+                    forward: 0.0,
+                    right: lane_step,
+                });
+            }
+            let forward = if lane % 2 == 0 { leg } else { -leg };
+            steps.push(Step::Photo);
+            for _ in 0..legs {
+                steps.push(Step::Move {
+                    forward,
+                    right: 0.0,
+                });
+                steps.push(Step::Photo);
+            }
+        }
+        Mission {
+            name: "survey".to_owned(),
+            speed: self.speed,
+            step_timeout: self.step_timeout,
+            steps,
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::time::SystemTime;
+
+use rust_tello::UpdateData;
+use serde::{Deserialize, Serialize};
+
+/// Tello reports height in decimeters.
+pub const HEIGHT_UNIT_M: f32 = 0.1;
+/// MVO velocities are in mm/s, x north, y east, z down.
+pub const VELOCITY_UNIT_MPS: f32 = 0.001;
+
+/// Flat copy of the interesting `UpdateData` fields, values as reported by the drone.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetrySample {
+    pub wifi_strength: Option<i32>,
+    pub height: Option<i32>,
+    pub fly_time: Option<i32>,
+    pub battery_percentage: Option<i32>,
+    pub battery_milli_volts: Option<i32>,
+    pub light: Option<i32>,
+    pub pitch: Option<f32>,
+    pub roll: Option<f32>,
+    pub yaw: Option<f32>,
+    pub temperature: Option<f32>,
+    pub vx: Option<f32>,
+    pub vy: Option<f32>,
+    pub vz: Option<f32>,
+}
+
+impl From<&UpdateData> for TelemetrySample {
+    fn from(update: &UpdateData) -> Self {
+        let mut sample = Self::default();
+        if let Some(ref wifi) = update.wifi {
+            sample.wifi_strength = Some(wifi.wifi_strength as i32);
+        }
+        if let Some(ref flight) = update.flight {
+            sample.height = Some(flight.height as i32);
+            sample.fly_time = Some(flight.fly_time as i32);
+            sample.battery_percentage = Some(flight.battery_percentage as i32);
+            sample.battery_milli_volts = Some(flight.battery_milli_volts as i32);
+        }
+        if let Some(ref light) = update.light {
+            sample.light = Some(light.light_strength as i32);
+        }
+        if let Some(ref log_record) = update.log {
+            if let Some(imu) = &log_record.imu {
+                sample.pitch = Some(imu.pitch as f32);
+                sample.roll = Some(imu.roll as f32);
+                sample.yaw = Some(imu.yaw as f32);
+                sample.temperature = Some(imu.temperature as f32);
+            }
+            if let Some(mvo) = &log_record.mvo {
+                sample.vx = mvo.vx.map(|v| v as f32);
+                sample.vy = mvo.vy.map(|v| v as f32);
+                sample.vz = mvo.vz.map(|v| v as f32);
+            }
+        }
+        sample
+    }
+}
+
+impl TelemetrySample {
+    pub fn height_m(&self) -> Option<f32> {
+        self.height.map(|h| h as f32 * HEIGHT_UNIT_M)
+    }
+
+    /// (north, east, down) velocity in m/s
+    pub fn velocity_mps(&self) -> Option<(f32, f32, f32)> {
+        Some((
+            self.vx? * VELOCITY_UNIT_MPS,
+            self.vy? * VELOCITY_UNIT_MPS,
+            self.vz? * VELOCITY_UNIT_MPS,
+        ))
+    }
+
+    /// Rebuilds the `UpdateData` this sample was taken from, as far as it was recorded.
+    pub fn to_update_data(&self) -> UpdateData {
+        let mut update = UpdateData::default();
+        if let Some(strength) = self.wifi_strength {
+            let wifi = update.wifi.get_or_insert_with(Default::default);
+            wifi.wifi_strength = strength as _;
+        }
+        if let Some(height) = self.height {
+            let flight = update.flight.get_or_insert_with(Default::default);
+            flight.height = height as _;
+            flight.fly_time = self.fly_time.unwrap_or_default() as _;
+            flight.battery_percentage = self.battery_percentage.unwrap_or_default() as _;
+            flight.battery_milli_volts = self.battery_milli_volts.unwrap_or_default() as _;
+        }
+        if let Some(strength) = self.light {
+            let light = update.light.get_or_insert_with(Default::default);
+            light.light_strength = strength as _;
+            // the light widget shows how fresh the reading is
+            light.light_strength_updated = SystemTime::now();
+        }
+        if let Some(yaw) = self.yaw {
+            let log_record = update.log.get_or_insert_with(Default::default);
+            let imu = log_record.imu.get_or_insert_with(Default::default);
+            imu.yaw = yaw as _;
+            imu.pitch = self.pitch.unwrap_or_default() as _;
+            imu.roll = self.roll.unwrap_or_default() as _;
+            imu.temperature = self.temperature.unwrap_or_default() as _;
+        }
+        if self.vx.is_some() || self.vy.is_some() || self.vz.is_some() {
+            let log_record = update.log.get_or_insert_with(Default::default);
+            let mvo = log_record.mvo.get_or_insert_with(Default::default);
+            mvo.vx = self.vx.map(|v| v as _);
+            mvo.vy = self.vy.map(|v| v as _);
+            mvo.vz = self.vz.map(|v| v as _);
+        }
+        update
+    }
+}
+
+/// Stick commands as sent via `forward/right/up/turn_clockwise`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct StickSample {
+    pub forward: f32,
+    pub right: f32,
+    pub up: f32,
+    pub turn_clockwise: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_data_round_trip() {
+        let sample = TelemetrySample {
+            wifi_strength: Some(90),
+            height: Some(12),
+            fly_time: Some(34),
+            battery_percentage: Some(56),
+            battery_milli_volts: Some(3800),
+            light: Some(7),
+            pitch: Some(-2.0),
+            roll: Some(3.0),
+            yaw: Some(-179.0),
+            temperature: Some(61.0),
+            vx: Some(120.0),
+            vy: None,
+            vz: Some(-30.0),
+        };
+        assert_eq!(TelemetrySample::from(&sample.to_update_data()), sample);
+        let empty = TelemetrySample::default();
+        assert_eq!(TelemetrySample::from(&empty.to_update_data()), empty);
+    }
+
+    #[test]
+    fn light_is_fresh() {
+        let before = SystemTime::now();
+        let sample = TelemetrySample {
+            light: Some(1),
+            ..Default::default()
+        };
+        let light = sample.to_update_data().light.unwrap();
+        assert!(light.light_strength_updated >= before);
+        assert!(TelemetrySample::default().to_update_data().light.is_none());
+    }
+}
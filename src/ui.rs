@@ -1,7 +1,9 @@
 use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
     sync::{mpsc::Receiver, RwLock},
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use rust_sdl_ui::{
@@ -9,18 +11,61 @@ use rust_sdl_ui::{
     desktop::{self, CommonWidgetProps},
     sdl,
 };
-use rust_tello::{TelloController, UpdateData};
-use sdl2::{controller::Axis, event::Event, keyboard::Keycode};
+use rust_tello::UpdateData;
+use sdl2::{
+    controller::{Axis, Button, GameController},
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    pixels::Color,
+    render::BlendMode,
+    video::FullscreenType,
+    GameControllerSubsystem,
+};
 
-use crate::utils;
+use crate::{
+    axis::RateLimiter,
+    battery::{BatteryConfig, BatteryGuard, BatteryLevel},
+    drone::{Drone, Flip},
+    failsafe::{ControllerFailsafe, FailsafeConfig, LinkWatch},
+    flight::{FlightState, Observation, Request, StateMachine},
+    flip::FlipConfig,
+    geofence::{Geofence, GeofenceConfig},
+    hold::{AltitudeHold, AltitudeHoldConfig, HeadingHold, HeadingHoldConfig, Headless},
+    input::{Action, InputProfile, KeyBinding, Motion},
+    launch::{Launch, Launcher},
+    maneuver::ManeuverTick,
+    mission::{self, Mission, MissionRunner, StartConfirm},
+    nav::Navigation,
+    photo::{PhotoBrowser, PhotoMeta, PhotoTagger, CAROUSEL_SIZE},
+    recorder::{Recorder, RecorderHandle},
+    replay::ReplayControl,
+    script::{Console, Script, ScriptConfig, ScriptRunner},
+    sdk::{self, CommandConfig, CommandLine, CommandRunner},
+    sound::Beeper,
+    telemetry::{StickSample, TelemetrySample, HEIGHT_UNIT_M},
+    video_recorder::{self, VideoRecorder},
+};
 
 #[derive(Debug)]
 struct DroneHandling {
     take_picture: bool,
     toggle_video: bool,
+    toggle_recording: bool,
     take_off: bool,
+    land: bool,
+    launch_mode: bool,
+    mission: bool,
+    survey: bool,
+    script: bool,
+    console: bool,
+    altitude_hold: bool,
+    heading_hold: bool,
+    headless: bool,
     hover: bool,
+    flip: Option<Flip>,
+    stick_mode: bool,
     sensitivity: f32,
+    throttle: f32,
     vert_accel: f32,
     vert_decel: f32,
     slide_right: f32,
@@ -34,9 +79,21 @@ struct DroneHandling {
 impl DroneHandling {
     pub fn zero_state(&mut self) {
         self.take_off = false;
+        self.land = false;
+        self.launch_mode = false;
+        self.mission = false;
+        self.survey = false;
+        self.script = false;
+        self.console = false;
+        self.altitude_hold = false;
+        self.heading_hold = false;
+        self.headless = false;
         self.hover = false;
+        self.flip = None;
+        self.stick_mode = false;
         self.take_picture = false;
         self.toggle_video = false;
+        self.toggle_recording = false;
         self.img_carousel_left = false;
         self.img_carousel_right = false;
         self.img_carousel_toggle_zoom = false;
@@ -55,19 +112,97 @@ impl DroneHandling {
             self.sensitivity = 1.0;
         }
     }
+
+    fn set_motion(&mut self, motion: Motion, value: f32) {
+        match motion {
+            Motion::Forward => self.forward = value,
+            Motion::SlideRight => self.slide_right = value,
+            Motion::TurnClockwise => self.turn_clockwise = value,
+            Motion::Throttle => self.throttle = value,
+            Motion::Up => self.vert_accel = value,
+            Motion::Down => self.vert_decel = value,
+        }
+    }
+
+    fn motion(&self, motion: Motion) -> f32 {
+        match motion {
+            Motion::Forward => self.forward,
+            Motion::SlideRight => self.slide_right,
+            Motion::TurnClockwise => self.turn_clockwise,
+            Motion::Throttle => self.throttle,
+            Motion::Up => self.vert_accel,
+            Motion::Down => self.vert_decel,
+        }
+    }
+
+    fn stop_motion(&mut self) {
+        self.forward = 0.0;
+        self.slide_right = 0.0;
+        self.turn_clockwise = 0.0;
+        self.throttle = 0.0;
+        self.vert_accel = 0.0;
+        self.vert_decel = 0.0;
+    }
+
+    fn vert_speed(&self) -> f32 {
+        self.throttle + self.vert_accel - self.vert_decel
+    }
+
+    // returns true when the action asks to quit
+    fn apply(&mut self, action: Action) -> bool {
+        match action {
+            Action::TakeOff => self.take_off = true,
+            Action::Land => self.land = true,
+            Action::LaunchMode => self.launch_mode = true,
+            Action::Mission => self.mission = true,
+            Action::Survey => self.survey = true,
+            Action::Script => self.script = true,
+            Action::Console => self.console = true,
+            Action::AltitudeHold => self.altitude_hold = true,
+            Action::HeadingHold => self.heading_hold = true,
+            Action::Headless => self.headless = true,
+            Action::Hover => self.hover = true,
+            Action::Flip(flip) => self.flip = Some(flip),
+            Action::TakePicture => self.take_picture = true,
+            Action::ToggleVideo => self.toggle_video = true,
+            Action::ToggleRecording => self.toggle_recording = true,
+            Action::SensitivityUp => self.sensitivity_inc(),
+            Action::SensitivityDown => self.sensitivity_dec(),
+            Action::Sensitivity(percent) => self.sensitivity = percent as f32 / 100.0,
+            Action::CarouselLeft => self.img_carousel_left = true,
+            Action::CarouselRight => self.img_carousel_right = true,
+            Action::CarouselZoom => self.img_carousel_toggle_zoom = true,
+            Action::StickMode => self.stick_mode = true,
+            Action::Quit => return true,
+        }
+        false
+    }
 }
 
 impl Default for DroneHandling {
     fn default() -> Self {
         Self {
             take_off: false,
+            land: false,
+            launch_mode: false,
+            mission: false,
+            survey: false,
+            script: false,
+            console: false,
+            altitude_hold: false,
+            heading_hold: false,
+            headless: false,
             hover: false,
+            flip: None,
+            stick_mode: false,
             take_picture: false,
             toggle_video: false,
+            toggle_recording: false,
             img_carousel_left: false,
             img_carousel_right: false,
             img_carousel_toggle_zoom: false,
             sensitivity: 0.2,
+            throttle: Default::default(),
             vert_accel: Default::default(),
             vert_decel: Default::default(),
             slide_right: Default::default(),
@@ -77,49 +212,210 @@ impl Default for DroneHandling {
     }
 }
 
+pub struct UiSettings {
+    pub width: u32,
+    pub height: u32,
+    /// fullscreen on the current display, width and height are ignored
+    pub fullscreen: bool,
+    /// pictures shown in the carousel
+    pub pictures_dir: PathBuf,
+    pub input: InputProfile,
+    pub battery: BatteryConfig,
+    pub failsafe: FailsafeConfig,
+    pub geofence: Option<GeofenceConfig>,
+    pub altitude_hold: AltitudeHoldConfig,
+    pub heading_hold: HeadingHoldConfig,
+    pub mission: Option<Mission>,
+    /// survey grid, flown the same way as a mission
+    pub survey: Option<Mission>,
+    pub script: Option<Script>,
+    pub script_config: ScriptConfig,
+    pub flip: FlipConfig,
+    /// Tello SDK command console and command port
+    pub commands: CommandConfig,
+}
+
 pub struct UI {
     width: u32,
     height: u32,
+    fullscreen: bool,
     fps: u32,
     drone: DroneHandling,
+    input: InputProfile,
+    axes: HashMap<Axis, RateLimiter>,
+    buttons: HashSet<Button>,
+    /// buttons whose release doesn't trigger their action
+    suppressed: HashSet<Button>,
+    keys: HashSet<Keycode>,
+    battery: BatteryGuard,
+    controller: ControllerFailsafe,
+    link: LinkWatch,
+    nav: Navigation,
+    /// a landing was commanded, the assists stay out of it
+    landing: bool,
+    flight: StateMachine,
+    launcher: Launcher,
+    flip: FlipConfig,
+    /// refusals shown for a while
+    notice: Option<(String, Instant)>,
+    altitude_hold: AltitudeHold,
+    heading_hold: HeadingHold,
+    headless: Headless,
+    fence: Option<Geofence>,
+    mission: Option<Mission>,
+    survey: Option<Mission>,
+    mission_runner: Option<MissionRunner>,
+    mission_confirm: StartConfirm,
+    script: Option<Script>,
+    script_config: ScriptConfig,
+    script_runner: Option<ScriptRunner>,
+    console: Console,
+    commands: CommandRunner,
+    command_line: CommandLine,
+    /// keys pressed while typing, their release isn't an action
+    typed: HashSet<Keycode>,
+    /// the video stream was toggled on
+    video: bool,
+    photos: PhotoTagger,
+    browser: PhotoBrowser,
+    game_controller: Option<GameControllerSubsystem>,
+    controllers: HashMap<u32, GameController>,
+    recorder: Option<Recorder>,
+    replay: Option<ReplayControl>,
+    video_recorder: Option<VideoRecorder>,
 }
 
+/// Height error in meters shown as a full vertical thrust gauge while holding.
+const HOLD_GAUGE_RANGE: f32 = 1.0;
+const NOTICE_TIME: Duration = Duration::from_secs(3);
+
 lazy_static! {
-    static ref VIDEO_FRAME: RwLock<Vec<u8>> = RwLock::new(utils::make(960 * 720 * 3));
     static ref UPDATE_DATA: RwLock<UpdateData> = RwLock::new(UpdateData::default());
+    static ref LAST_UPDATE: RwLock<Option<Instant>> = RwLock::new(None);
 }
 
-fn update_data(ctrl_rx: Receiver<UpdateData>) {
-    loop {
-        if let Ok(update) = ctrl_rx.recv() {
-            let mut g = UPDATE_DATA.write().unwrap();
-            *g = update;
-            drop(g);
+// flight data for a picture taken now
+fn photo_meta(nav: &Navigation) -> PhotoMeta {
+    PhotoMeta::new(nav, &TelemetrySample::from(&*UPDATE_DATA.read().unwrap()))
+}
+
+fn update_data(ctrl_rx: Receiver<UpdateData>, recorder: Option<RecorderHandle>) {
+    while let Ok(update) = ctrl_rx.recv() {
+        if let Some(ref recorder) = recorder {
+            recorder.telemetry(TelemetrySample::from(&update));
         }
+        let mut g = UPDATE_DATA.write().unwrap();
+        *g = update;
+        drop(g);
+        *LAST_UPDATE.write().unwrap() = Some(Instant::now());
     }
 }
 
 impl UI {
-    pub fn new(width: u32, height: u32) -> Self {
+    pub fn new(settings: UiSettings) -> Self {
+        tracing::info!("using input profile `{}`", settings.input.name);
+        let photos = PhotoTagger::new(&settings.pictures_dir);
+        let browser = PhotoBrowser::new(&settings.pictures_dir);
+        let commands = CommandRunner::new(settings.commands.clone());
+        if let Some(ref address) = settings.commands.port {
+            if let Err(e) = sdk::listen(address, commands.sender()) {
+                tracing::error!("can't open the command port {address}: {e}");
+            }
+        }
+        if settings.commands.stdin {
+            sdk::read_stdin(commands.sender());
+        }
         Self {
-            width,
-            height,
+            width: settings.width,
+            height: settings.height,
+            fullscreen: settings.fullscreen,
             fps: 60,
             drone: DroneHandling::default(),
+            input: settings.input,
+            axes: HashMap::new(),
+            buttons: HashSet::new(),
+            suppressed: HashSet::new(),
+            keys: HashSet::new(),
+            battery: BatteryGuard::new(settings.battery),
+            controller: ControllerFailsafe::new(&settings.failsafe),
+            link: LinkWatch::new(&settings.failsafe),
+            nav: Navigation::default(),
+            landing: false,
+            flight: StateMachine::default(),
+            launcher: Launcher::default(),
+            flip: settings.flip,
+            notice: None,
+            altitude_hold: AltitudeHold::new(settings.altitude_hold),
+            heading_hold: HeadingHold::new(settings.heading_hold),
+            headless: Headless::default(),
+            fence: settings.geofence.map(Geofence::new),
+            mission: settings.mission,
+            survey: settings.survey,
+            mission_runner: None,
+            mission_confirm: StartConfirm::default(),
+            script: settings.script,
+            script_config: settings.script_config,
+            script_runner: None,
+            console: Console::default(),
+            commands,
+            command_line: CommandLine::default(),
+            typed: HashSet::new(),
+            video: false,
+            photos,
+            browser,
+            game_controller: None,
+            controllers: HashMap::new(),
+            recorder: None,
+            replay: None,
+            video_recorder: None,
         }
     }
 
-    pub fn mainloop(
+    /// Shows replay status and takes replay controls from the keyboard:
+    /// F5 play/pause, F6 slower, F7 faster, F8 back 10 s, F9 forward 10 s.
+    pub fn set_replay(&mut self, replay: ReplayControl) {
+        self.replay = Some(replay);
+    }
+
+    /// Records every flight, from takeoff to landing, with the given recorder.
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Saves the video stream while recording is toggled on.
+    pub fn set_video_recorder(&mut self, recorder: VideoRecorder) {
+        self.video_recorder = Some(recorder);
+    }
+
+    pub fn mainloop<D: Drone>(
         &mut self,
-        mut tello: TelloController,
+        mut tello: D,
         ctrl_rx: Receiver<UpdateData>,
         video_rx: Receiver<Vec<u8>>,
     ) {
         let mut playing = true;
-        thread::spawn(move || update_data(ctrl_rx));
+        let recorder = self.recorder.as_ref().map(Recorder::handle);
+        let update_recorder = recorder.clone();
+        thread::spawn(move || update_data(ctrl_rx, update_recorder));
+        let mut last_sticks = StickSample::default();
 
         let (mut win, mut canvas) = desktop::Window::new(self.width, self.height, self.fps, true);
+        if self.fullscreen {
+            if let Err(e) = canvas.window_mut().set_fullscreen(FullscreenType::Desktop) {
+                tracing::error!("can't switch to fullscreen: {e}");
+            }
+            (self.width, self.height) = canvas.window().size();
+        }
+        // controllers plugged in later have to be opened to get their events
+        self.game_controller = sdl2::init()
+            .and_then(|sdl| sdl.game_controller())
+            .map_err(|e| tracing::error!("no game controller support: {e}"))
+            .ok();
 
+        let video_rx = match self.video_recorder {
+            Some(ref recorder) => video_recorder::tee(video_rx, recorder.handle()),
+            None => video_rx,
+        };
         let _video = desktop::VideoWidget::new(
             CommonWidgetProps::new(&canvas)
                 .place(0.5, 0.5)
@@ -184,12 +480,16 @@ impl UI {
         )
         .on_window(&mut win);
 
+        if let Err(e) = self.browser.stage() {
+            tracing::error!("can't stage the carousel pictures: {e}");
+        }
+        let carousel_dir = self.browser.carousel_dir();
         let image_carousel = desktop::ImageCarouselWidget::new(
             CommonWidgetProps::new(&canvas)
                 .place(0.5, 0.9)
                 .size(0.8, 0.1),
-            "./save_pics",
-            10,
+            carousel_dir.to_str().unwrap_or("./save_pics/.carousel"),
+            CAROUSEL_SIZE,
         )
         .on_window(&mut win);
 
@@ -198,6 +498,14 @@ impl UI {
         )
         .on_window(&mut win);
 
+        // under the yaw gauge
+        let heading_status = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.95, 0.67)
+                .size(0.08, 0.03),
+        )
+        .on_window(&mut win);
+
         let temperature = desktop::VertThrustWidget::new(
             CommonWidgetProps::new(&canvas).place(0.25, 0.1).rect(0.05),
         )
@@ -230,6 +538,13 @@ impl UI {
         )
         .on_window(&mut win);
 
+        let hold_status = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.1, 0.15)
+                .size(0.1, 0.03),
+        )
+        .on_window(&mut win);
+
         let height = desktop::TextWidget::new(
             CommonWidgetProps::new(&canvas)
                 .place(0.1, 0.2)
@@ -237,6 +552,102 @@ impl UI {
         )
         .on_window(&mut win);
 
+        let battery_alert = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.1, 0.6)
+                .size(0.15, 0.03),
+        )
+        .on_window(&mut win);
+
+        let alert = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.5, 0.2)
+                .size(0.3, 0.04),
+        )
+        .on_window(&mut win);
+
+        let stick_mode = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.1, 0.65)
+                .size(0.1, 0.03),
+        )
+        .on_window(&mut win);
+
+        let launch_status = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.1, 0.7)
+                .size(0.15, 0.03),
+        )
+        .on_window(&mut win);
+
+        let rec_status = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.85, 0.03)
+                .size(0.12, 0.03),
+        )
+        .on_window(&mut win);
+
+        // what the drone is doing, big and right above the video
+        let flight_state = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.5, 0.1)
+                .size(0.15, 0.05),
+        )
+        .on_window(&mut win);
+
+        let notice = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.5, 0.32)
+                .size(0.3, 0.03),
+        )
+        .on_window(&mut win);
+
+        let mission_status = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.5, 0.26)
+                .size(0.3, 0.03),
+        )
+        .on_window(&mut win);
+
+        let photo_info = desktop::TextWidget::new(
+            CommonWidgetProps::new(&canvas)
+                .place(0.5, 0.75)
+                .size(0.6, 0.03),
+        )
+        .on_window(&mut win);
+
+        let console: Vec<_> = (0..5)
+            .map(|n| {
+                desktop::TextWidget::new(
+                    CommonWidgetProps::new(&canvas)
+                        .place(0.3, 0.6 + n as f32 * 0.03)
+                        .size(0.3, 0.03),
+                )
+                .on_window(&mut win)
+            })
+            .collect();
+
+        // replies over the line being typed
+        let command_console: Vec<_> = (0..4)
+            .map(|n| {
+                desktop::TextWidget::new(
+                    CommonWidgetProps::new(&canvas)
+                        .place(0.7, 0.6 + n as f32 * 0.03)
+                        .size(0.3, 0.03),
+                )
+                .on_window(&mut win)
+            })
+            .collect();
+
+        let replay_status = self.replay.as_ref().map(|_| {
+            desktop::TextWidget::new(
+                CommonWidgetProps::new(&canvas)
+                    .place(0.5, 0.03)
+                    .size(0.2, 0.03),
+            )
+            .on_window(&mut win)
+        });
+
         // let _flight_log = desktop::FlightLogWidget::new(
         //     CommonWidgetProps::new(&canvas).place(0.65, 0.7).rect(0.12),
         // )
@@ -253,8 +664,11 @@ impl UI {
         vy.write().unwrap().set_scale(0.001);
         vz.write().unwrap().set_scale(0.001);
         let bg_texture = sdl::sdl_load_textures(&canvas, vec!["images/bg01.png".to_owned()]);
+        let beeper = Beeper::open();
+        let started = Instant::now();
 
         //sensitivity.write().unwrap().inc();
+        let mut last_frame = Instant::now();
         while playing {
             // main loop
             'running: loop {
@@ -264,6 +678,9 @@ impl UI {
                     playing = false;
                     break 'running;
                 }
+                self.update_axes(start.duration_since(last_frame).as_secs_f32());
+                self.update_keys();
+                last_frame = start;
                 tracing::debug!("drone-movement: {:?}", self.drone);
                 // clear before drawing
                 sdl::sdl_clear(&mut canvas, 10, 20, 30);
@@ -271,20 +688,70 @@ impl UI {
                 let h = self.height as i32;
                 sdl::sdl_scale_tex(&mut canvas, &bg_texture[0], w / 2, h / 2, w, h);
 
+                if self.drone.launch_mode {
+                    self.launcher.cycle();
+                }
                 if self.drone.take_off {
-                    let flying = tello.flying();
-                    if !flying {
-                        tracing::info!("takeoff");
-                        tello.takeoff();
+                    let flying = self.flight.state().airborne();
+                    let request = if flying {
+                        Request::Land
+                    } else {
+                        Request::TakeOff
+                    };
+                    if !flying && self.battery.forced_landing() {
+                        tracing::warn!("takeoff refused, battery critical");
+                    } else if !self.flight.allows(request) {
+                        let state = self.flight.state().label();
+                        tracing::warn!("takeoff refused while {state}");
+                        self.notice = Some((format!("TAKEOFF REFUSED: {state}"), Instant::now()));
                     } else {
-                        tracing::info!("land");
-                        tello.land();
+                        match self.launcher.request(flying, Instant::now()) {
+                            Some(launch) => {
+                                tracing::info!("{}", launch.label());
+                                self.launch(&mut tello, launch);
+                            }
+                            // waiting for the pilot to confirm
+                            None => tracing::info!("{}", self.launcher.status()),
+                        }
                     }
                 }
-                if self.drone.hover {
+                if self.controller.centering() && self.sticks_centered() {
+                    self.controller.centered();
+                }
+                // a paused replay sends nothing, that's no lost link
+                if self.replay.is_none() {
+                    if let Some(true) = self
+                        .link
+                        .update(*LAST_UPDATE.read().unwrap(), Instant::now())
+                    {
+                        self.drone.hover = true;
+                        if let Some(ref beeper) = beeper {
+                            beeper.play(&[(440, 600)]);
+                        }
+                    }
+                }
+                if self.controller.should_land(Instant::now()) && self.flight.allows(Request::Land)
+                {
+                    tracing::warn!("land");
+                    self.launch(&mut tello, Launch::Land);
+                }
+                if self.drone.land && self.flight.allows(Request::Land) {
+                    tracing::info!("land");
+                    self.launch(&mut tello, Launch::Land);
+                }
+                if self.drone.hover && self.flight.request(Request::Hover, Instant::now()) {
                     tracing::info!("hover");
                     tello.hover();
                 }
+                if self.drone.stick_mode {
+                    if self.flight.state().airborne() {
+                        tracing::warn!("stick mode can be changed on the ground only");
+                    } else {
+                        self.input.mode = self.input.mode.next();
+                        self.drone.stop_motion();
+                        tracing::info!("stick mode: {}", self.input.mode.label());
+                    }
+                }
 
                 let g_data = UPDATE_DATA.read().unwrap();
                 if let Some(ref wifi) = g_data.wifi {
@@ -300,10 +767,39 @@ impl UI {
                     fly_time.write().unwrap().set(flt);
                     height.write().unwrap().set(hgt);
                     battery_voltage.write().unwrap().set(batt);
-                    battery
-                        .write()
-                        .unwrap()
-                        .set(flight.battery_percentage as f32 / 100.0);
+                    if let Some(level) = self.battery.update(flight.battery_percentage as i32) {
+                        if let Some(ref beeper) = beeper {
+                            match level {
+                                BatteryLevel::Warning => {
+                                    beeper.play(&[(880, 150), (0, 100), (880, 150)])
+                                }
+                                BatteryLevel::Critical => beeper.play(&[
+                                    (1760, 400),
+                                    (0, 150),
+                                    (1760, 400),
+                                    (0, 150),
+                                    (1760, 400),
+                                ]),
+                                BatteryLevel::Normal => {}
+                            }
+                        }
+                    }
+                    // not only on the critical reading, the drone may not have been up then
+                    if self.battery.forced_landing()
+                        && self.flight.allows(Request::Land)
+                        && !self.landing
+                    {
+                        tracing::warn!("forced landing");
+                        self.launch(&mut tello, Launch::Land);
+                    }
+                    // flash the gauge while the battery is low
+                    let blink = started.elapsed().as_millis() / 400 % 2 == 1;
+                    let charge = if self.battery.level() > BatteryLevel::Normal && blink {
+                        0.0
+                    } else {
+                        flight.battery_percentage as f32 / 100.0
+                    };
+                    battery.write().unwrap().set(charge);
                 }
                 if let Some(ref light) = g_data.light {
                     light_signal
@@ -333,267 +829,793 @@ impl UI {
                         }
                     }
                 }
+                if let Some(at) = *LAST_UPDATE.read().unwrap() {
+                    self.nav
+                        .update(&TelemetrySample::from(&*g_data), at, tello.flying());
+                }
+                if !tello.flying() {
+                    self.landing = false;
+                }
+                self.launcher.update(tello.flying(), Instant::now());
+                launch_status.write().unwrap().set(self.launcher.status());
                 drop(g_data);
 
                 if self.drone.take_picture {
                     tracing::info!("take picture");
                     tello.take_picture();
+                    self.photos.shot(photo_meta(&self.nav));
+                }
+                for picture in self.photos.poll() {
+                    if let Err(e) = self.browser.add(picture) {
+                        tracing::error!("can't stage the carousel pictures: {e}");
+                    }
                 }
 
                 if self.drone.toggle_video {
                     tracing::info!("toggle video");
                     tello.toggle_video();
+                    self.video = !self.video;
+                }
+
+                if let Some(ref mut video_recorder) = self.video_recorder {
+                    if self.drone.toggle_recording {
+                        video_recorder.toggle();
+                    }
+                    rec_status.write().unwrap().set(video_recorder.status());
                 }
 
                 sensitivity.write().unwrap().set(self.drone.sensitivity);
 
+                let overridden =
+                    self.battery.forced_landing() || self.controller.active() || self.link.lost();
+                // the pilot still steers while the battery lands the drone
+                if self.controller.active() || self.link.lost() {
+                    self.drone.stop_motion();
+                }
+                battery_alert.write().unwrap().set(self.battery.status());
+                let alerts: Vec<String> = [self.link.status(), self.controller.status()]
+                    .into_iter()
+                    .filter(|s| !s.is_empty())
+                    .collect();
+                alert.write().unwrap().set(alerts.join(" / "));
+
                 // control tello
-                let vert_speed = self.drone.vert_accel - self.drone.vert_decel;
-                tello.forward(self.drone.forward);
-                tello.right(self.drone.slide_right);
-                tello.up(vert_speed);
-                tello.turn_clockwise(self.drone.turn_clockwise);
+                let mut sticks = StickSample {
+                    forward: self.drone.forward,
+                    right: self.drone.slide_right,
+                    up: self.drone.vert_speed(),
+                    turn_clockwise: self.drone.turn_clockwise,
+                };
+                if self.drone.mission {
+                    self.toggle_mission(tello.flying(), false);
+                }
+                if self.drone.survey {
+                    self.toggle_mission(tello.flying(), true);
+                }
+                if let Some(ref mut runner) = self.mission_runner {
+                    let abort = if overridden {
+                        Some("failsafe".to_owned())
+                    } else if sticks != StickSample::default() {
+                        Some("stick input".to_owned())
+                    } else {
+                        runner.update(&self.nav).map_or_else(Some, |command| {
+                            sticks = command.sticks;
+                            if command.take_picture {
+                                tracing::info!("take picture");
+                                tello.take_picture();
+                                self.photos.shot(photo_meta(&self.nav));
+                            }
+                            None
+                        })
+                    };
+                    if let Some(reason) = abort {
+                        tracing::warn!("mission aborted: {reason}");
+                        self.mission_runner = None;
+                        sticks = StickSample::default();
+                        tello.hover();
+                    } else if runner.finished() {
+                        self.mission_runner = None;
+                        tello.hover();
+                    }
+                }
+                if self.drone.script {
+                    self.toggle_script();
+                }
+                if let Some(ref mut runner) = self.script_runner {
+                    let abort = if overridden {
+                        Some("failsafe")
+                    } else if sticks != StickSample::default() {
+                        Some("stick input")
+                    } else if self.drone.hover {
+                        Some("hover")
+                    } else {
+                        None
+                    };
+                    if let Some(reason) = abort {
+                        runner.abort(reason);
+                        self.script_runner = None;
+                        sticks = StickSample::default();
+                        tello.hover();
+                    } else if runner.finished() {
+                        self.script_runner = None;
+                        tello.hover();
+                    } else {
+                        let sample = TelemetrySample::from(&*UPDATE_DATA.read().unwrap());
+                        let tick = runner.update(&self.nav, &sample);
+                        sticks = tick.sticks;
+                        if let Err(reason) = self.fly_tick(&mut tello, &tick) {
+                            if let Some(mut runner) = self.script_runner.take() {
+                                runner.abort(&reason);
+                            }
+                        }
+                    }
+                }
+                if self.drone.console {
+                    self.command_line.toggle();
+                    // typing doesn't fly
+                    let held: Vec<Keycode> = self.keys.iter().copied().collect();
+                    for key in held {
+                        self.release_key(key);
+                    }
+                }
+                if self.commands.running().is_some() {
+                    let abort = if overridden {
+                        Some("failsafe")
+                    } else if sticks != StickSample::default() {
+                        Some("stick input")
+                    } else if self.drone.hover {
+                        Some("hover")
+                    } else {
+                        None
+                    };
+                    if let Some(reason) = abort {
+                        self.commands.abort(reason);
+                        sticks = StickSample::default();
+                        tello.hover();
+                    }
+                }
+                {
+                    let sample = TelemetrySample::from(&*UPDATE_DATA.read().unwrap());
+                    let was_running = self.commands.running().is_some();
+                    // one thing at a time flies the drone
+                    let others = self.mission_runner.is_some() || self.script_runner.is_some();
+                    let mut tick = self.commands.update(&self.nav, &sample, others);
+                    if let Some(reason) = tick.abort {
+                        if self.mission_runner.take().is_some() {
+                            tracing::warn!("mission aborted: {reason}");
+                        }
+                        if let Some(mut runner) = self.script_runner.take() {
+                            runner.abort(reason);
+                        }
+                        sticks = StickSample::default();
+                    }
+                    if let Some(flip) = tick.maneuver.flip.take() {
+                        if let Err(reason) = self.flip(&mut tello, flip) {
+                            self.commands.abort(&reason);
+                        }
+                    }
+                    if was_running || self.commands.running().is_some() {
+                        sticks = tick.maneuver.sticks;
+                    }
+                    if tick.maneuver.take_off && self.battery.forced_landing() {
+                        tracing::warn!("takeoff refused, battery critical");
+                        self.commands.abort("battery critical");
+                    } else if let Err(reason) = self.fly_tick(&mut tello, &tick.maneuver) {
+                        self.commands.abort(&reason);
+                    }
+                    if tick.hover {
+                        tracing::info!("hover");
+                        tello.hover();
+                    }
+                    if tick.stream.is_some_and(|on| on != self.video) {
+                        tracing::info!("toggle video");
+                        tello.toggle_video();
+                        self.video = !self.video;
+                    }
+                }
+                let mut lines = self.command_line.feedback();
+                lines.push(self.command_line.prompt());
+                for (widget, line) in command_console.iter().zip(lines) {
+                    widget.write().unwrap().set(line);
+                }
+                let lines = self.console.lines();
+                for (n, line) in console.iter().enumerate() {
+                    line.write()
+                        .unwrap()
+                        .set(lines.get(n).cloned().unwrap_or_default());
+                }
+                if let Some(flip) = self.drone.flip {
+                    let busy = self.mission_runner.is_some()
+                        || self.script_runner.is_some()
+                        || self.commands.running().is_some();
+                    if busy || overridden {
+                        let reason = if busy { "autopilot flying" } else { "failsafe" };
+                        tracing::warn!("flip {} refused: {reason}", flip.label());
+                        self.notice = Some((format!("FLIP REFUSED: {reason}"), Instant::now()));
+                    } else {
+                        let _ = self.flip(&mut tello, flip);
+                    }
+                }
+                if let Some((ref text, at)) = self.notice {
+                    if at.elapsed() < NOTICE_TIME {
+                        notice.write().unwrap().set(text.clone());
+                    } else {
+                        notice.write().unwrap().set(String::new());
+                        self.notice = None;
+                    }
+                }
+                if self.drone.altitude_hold {
+                    self.altitude_hold.toggle();
+                }
+                // missions and scripts fly the height and heading themselves
+                let engaged = self.nav.flying
+                    && !self.landing
+                    && !overridden
+                    && self.mission_runner.is_none()
+                    && self.script_runner.is_none()
+                    && self.commands.running().is_none();
+                if let Some(up) = self.altitude_hold.update(
+                    self.nav.height,
+                    self.nav.climb,
+                    sticks.up,
+                    engaged,
+                    Instant::now(),
+                ) {
+                    sticks.up = up;
+                }
+                if self.drone.heading_hold {
+                    self.heading_hold.toggle();
+                }
+                if let Some(turn) =
+                    self.heading_hold
+                        .update(self.nav.yaw, sticks.turn_clockwise, engaged)
+                {
+                    sticks.turn_clockwise = turn;
+                }
+                if self.drone.headless {
+                    self.headless.toggle(self.nav.yaw);
+                }
+                if engaged {
+                    let (forward, right) = self
+                        .headless
+                        .apply(self.nav.yaw, (sticks.forward, sticks.right));
+                    sticks.forward = forward.clamp(-1.0, 1.0);
+                    sticks.right = right.clamp(-1.0, 1.0);
+                }
+                heading_status.write().unwrap().set(
+                    [
+                        self.heading_hold
+                            .locked()
+                            .map(|yaw| format!("HDG {yaw:.0}"))
+                            .or_else(|| self.heading_hold.on().then(|| "HDG".to_owned())),
+                        self.headless
+                            .reference()
+                            .map(|yaw| format!("HEADLESS {yaw:.0}")),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                );
+                mission_status.write().unwrap().set(
+                    match (&self.mission_runner, &self.script_runner) {
+                        (Some(runner), _) => runner.status(),
+                        (None, Some(runner)) => runner.status(),
+                        (None, None) => self
+                            .commands
+                            .running()
+                            .map(|maneuver| format!("SDK {}", maneuver.label()))
+                            .unwrap_or_default(),
+                    },
+                );
+                if let Some(ref mut fence) = self.fence {
+                    fence.limit(&self.nav, &mut sticks);
+                }
+                let observation = Observation {
+                    link: LAST_UPDATE.read().unwrap().is_some() && !self.link.lost(),
+                    ready: UPDATE_DATA.read().unwrap().flight.is_some()
+                        && !self.battery.forced_landing(),
+                    flying: tello.flying(),
+                    climb: self.nav.climb,
+                    sticks_idle: sticks == StickSample::default(),
+                    emergency: self.battery.forced_landing(),
+                };
+                let was_airborne = self.flight.state().airborne();
+                self.flight.update(&observation, Instant::now());
+                // the flight is recorded while airborne, however it took off or came down
+                if let Some(ref recorder) = recorder {
+                    match (was_airborne, self.flight.state().airborne()) {
+                        (false, true) => recorder.start(),
+                        (true, false) => recorder.stop(),
+                        _ => {}
+                    }
+                }
+                flight_state
+                    .write()
+                    .unwrap()
+                    .set(self.flight.state().label().to_owned());
+                // no sticks on the ground or on the way up and down
+                if !self.flight.allows(Request::Sticks) {
+                    sticks = StickSample::default();
+                }
+                // an emergency landing can be steered clear of obstacles, not climbed out of
+                if self.flight.state() == FlightState::Emergency {
+                    sticks.up = sticks.up.min(0.0);
+                }
+                tello.forward(sticks.forward);
+                tello.right(sticks.right);
+                tello.up(sticks.up);
+                tello.turn_clockwise(sticks.turn_clockwise);
+                if let Some(ref video_recorder) = self.video_recorder {
+                    if video_recorder.wants_overlay() {
+                        let sample = TelemetrySample::from(&*UPDATE_DATA.read().unwrap());
+                        video_recorder.overlay(sample, sticks);
+                    }
+                }
+                if sticks != last_sticks {
+                    if let Some(ref recorder) = recorder {
+                        recorder.sticks(sticks);
+                    }
+                    last_sticks = sticks;
+                }
 
                 if self.drone.img_carousel_toggle_zoom {
                     image_carousel.write().unwrap().toggle_show();
+                    self.browser.toggle_zoom();
                 }
                 if self.drone.img_carousel_left {
                     image_carousel.write().unwrap().turn_left();
+                    self.browser.turn_left();
                 }
                 if self.drone.img_carousel_right {
                     image_carousel.write().unwrap().turn_right();
+                    self.browser.turn_right();
                 }
+                photo_info
+                    .write()
+                    .unwrap()
+                    .set(self.browser.info().to_owned());
 
-                vert_thrust.write().unwrap().set(-vert_speed);
+                // holding, the gauge shows how far off the target the drone is
+                match self.altitude_hold.target() {
+                    Some(target) => {
+                        let error = (target - self.nav.height) / HOLD_GAUGE_RANGE;
+                        vert_thrust.write().unwrap().set(-error.clamp(-1.0, 1.0));
+                        hold_status
+                            .write()
+                            .unwrap()
+                            .set(format!("ALT HOLD {target:.1} m ({:.1})", self.nav.height));
+                    }
+                    None => {
+                        vert_thrust.write().unwrap().set(-sticks.up);
+                        hold_status
+                            .write()
+                            .unwrap()
+                            .set(if self.altitude_hold.on() {
+                                "ALT HOLD".to_owned()
+                            } else {
+                                String::new()
+                            });
+                    }
+                }
 
-                let ls = (self.drone.slide_right, -self.drone.forward);
-                let rs = (self.drone.turn_clockwise, 0.0);
+                let (ls, rs) = self.sticks();
                 left_stick.write().unwrap().set_stick(ls);
                 right_stick.write().unwrap().set_stick(rs);
+                stick_mode
+                    .write()
+                    .unwrap()
+                    .set(self.input.mode.label().to_owned());
+                if let (Some(replay), Some(replay_status)) = (&self.replay, &replay_status) {
+                    replay_status.write().unwrap().set(replay.status());
+                }
 
                 win.draw(&mut canvas);
+                // the running one, otherwise what would start
+                let preview = match self.mission_runner {
+                    Some(ref runner) => {
+                        let (position, yaw) = runner.origin();
+                        Some((runner.mission().plan(position, yaw), runner.progress()))
+                    }
+                    None => self
+                        .mission
+                        .as_ref()
+                        .or(self.survey.as_ref())
+                        .map(|mission| (mission.plan(self.nav.position, self.nav.yaw), 0)),
+                };
+                if let Some((path, done)) = preview {
+                    let size = self.height as i32 / 6;
+                    let center = (self.width as i32 * 3 / 4, self.height as i32 / 3);
+                    if let Err(e) =
+                        mission::draw_path(&mut canvas, &self.nav, &path, done, center, size)
+                    {
+                        tracing::error!("can't draw the mission: {e}");
+                    }
+                }
+                if let Some(ref fence) = self.fence {
+                    let size = self.height as i32 / 6;
+                    let center = (self.width as i32 * 3 / 4, self.height as i32 / 8);
+                    if let Err(e) = fence.draw(&self.nav, &mut canvas, center, size) {
+                        tracing::error!("can't draw the geofence map: {e}");
+                    }
+                }
+                if self.link.lost() {
+                    // gray out the stale gauges
+                    canvas.set_blend_mode(BlendMode::Blend);
+                    canvas.set_draw_color(Color::RGBA(60, 60, 60, 110));
+                    if let Err(e) = canvas.fill_rect(None) {
+                        tracing::error!("can't gray out the HUD: {e}");
+                    }
+                }
                 canvas.present();
                 sdl::sdl_maintain_fps(start, self.fps);
                 self.drone.zero_state();
             }
         }
+        if let Some(recorder) = self.recorder.take() {
+            recorder.close();
+        }
+        if let Some(video_recorder) = self.video_recorder.take() {
+            video_recorder.close();
+        }
         tracing::info!("exiting mainloop");
     }
 
+    // stick positions as the pilot holds them in the active stick mode
+    fn sticks(&self) -> ((f32, f32), (f32, f32)) {
+        match self.input.mode.layout() {
+            None => (
+                (self.drone.slide_right, -self.drone.forward),
+                (self.drone.turn_clockwise, 0.0),
+            ),
+            Some([lx, ly, rx, ry]) => (
+                (self.drone.motion(lx), -self.drone.motion(ly)),
+                (self.drone.motion(rx), -self.drone.motion(ry)),
+            ),
+        }
+    }
+
+    // the survey is flown as a mission, either one aborts whatever runs
+    fn toggle_mission(&mut self, flying: bool, survey: bool) {
+        if self.mission_runner.take().is_some() {
+            tracing::warn!("mission aborted by the pilot");
+            return;
+        }
+        if self.script_runner.is_some() {
+            tracing::warn!("a script is running");
+            return;
+        }
+        if self.commands.running().is_some() {
+            tracing::warn!("a command is running");
+            return;
+        }
+        let mission = if survey { &self.survey } else { &self.mission };
+        match mission {
+            Some(_) if flying && !self.mission_confirm.press(survey, Instant::now()) => {
+                let what = if survey { "SURVEY" } else { "MISSION" };
+                tracing::info!("press again to start the {}", what.to_lowercase());
+                self.notice = Some((format!("PRESS AGAIN TO START THE {what}"), Instant::now()));
+            }
+            Some(ref mission) if flying => {
+                self.mission_runner = Some(MissionRunner::start(mission.clone(), &self.nav))
+            }
+            Some(_) => tracing::warn!("missions can be started in the air only"),
+            None if survey => tracing::warn!("no survey configured"),
+            None => tracing::warn!("no mission loaded"),
+        }
+    }
+
+    fn toggle_script(&mut self) {
+        if let Some(mut runner) = self.script_runner.take() {
+            runner.abort("pilot");
+            return;
+        }
+        if self.mission_runner.is_some() {
+            tracing::warn!("a mission is running");
+            return;
+        }
+        if self.commands.running().is_some() {
+            tracing::warn!("a command is running");
+            return;
+        }
+        match self.script {
+            Some(ref script) => {
+                self.script_runner = Some(ScriptRunner::start(
+                    script.clone(),
+                    self.script_config.clone(),
+                    self.console.clone(),
+                ))
+            }
+            None => tracing::warn!("no script loaded"),
+        }
+    }
+
+    // takes off or lands
+    fn launch<D: Drone>(&mut self, tello: &mut D, launch: Launch) {
+        launch.send(tello);
+        let request = if launch.landing() {
+            self.landing = true;
+            Request::Land
+        } else {
+            Request::TakeOff
+        };
+        self.flight.request(request, Instant::now());
+    }
+
+    // does what a script or command maneuver asks for besides the sticks, an error when the
+    // flight state doesn't allow it
+    fn fly_tick<D: Drone>(&mut self, tello: &mut D, tick: &ManeuverTick) -> Result<(), String> {
+        if tick.take_picture {
+            tracing::info!("take picture");
+            tello.take_picture();
+            self.photos.shot(photo_meta(&self.nav));
+        }
+        if tick.take_off && !tello.flying() {
+            if !self.flight.allows(Request::TakeOff) {
+                return Err(format!(
+                    "takeoff refused while {}",
+                    self.flight.state().label()
+                ));
+            }
+            tracing::info!("takeoff");
+            self.launch(tello, Launch::TakeOff);
+        }
+        if tick.land {
+            tracing::info!("land");
+            self.launch(tello, Launch::Land);
+        }
+        Ok(())
+    }
+
+    // flips unless the drone is too low or the battery too weak, refusals go on the HUD
+    fn flip<D: Drone>(&mut self, tello: &mut D, flip: Flip) -> Result<(), String> {
+        let flight = UPDATE_DATA.read().unwrap().flight.as_ref().map(|flight| {
+            (
+                flight.battery_percentage as i32,
+                flight.height as f32 * HEIGHT_UNIT_M,
+            )
+        });
+        let flying = self.flight.allows(Request::Flip) && !self.landing;
+        if let Some(reason) = self.flip.refusal(flying, flight) {
+            tracing::warn!("flip {} refused: {reason}", flip.label());
+            self.notice = Some((format!("FLIP REFUSED: {reason}"), Instant::now()));
+            return Err(reason);
+        }
+        tracing::info!("flip {}", flip.label());
+        tello.flip(flip);
+        Ok(())
+    }
+
+    // a key typed into the command console
+    fn console_key(&mut self, key: Keycode) {
+        match key {
+            Keycode::Return | Keycode::KpEnter => self.command_line.submit(&self.commands.sender()),
+            Keycode::Backspace => self.command_line.backspace(),
+            Keycode::Up => self.command_line.up(),
+            Keycode::Down => self.command_line.down(),
+            Keycode::Escape => self.command_line.close(),
+            _ if matches!(
+                self.input.key(key),
+                Some(KeyBinding::Action(Action::Console))
+            ) =>
+            {
+                self.command_line.close()
+            }
+            _ => {}
+        }
+    }
+
+    // true when a connected controller has all bound axes inside their dead zones
+    fn sticks_centered(&self) -> bool {
+        const AXES: [Axis; 6] = [
+            Axis::LeftX,
+            Axis::LeftY,
+            Axis::RightX,
+            Axis::RightY,
+            Axis::TriggerLeft,
+            Axis::TriggerRight,
+        ];
+        self.controllers.values().any(|controller| {
+            AXES.iter()
+                .filter(|axis| self.input.axis(**axis).is_some())
+                .all(|axis| self.input.curve(*axis).shape(controller.axis(*axis)) == 0.0)
+        })
+    }
+
+    fn open_controller(&mut self, joystick_index: u32) {
+        let Some(ref subsystem) = self.game_controller else {
+            return;
+        };
+        match subsystem.open(joystick_index) {
+            Ok(controller) => {
+                tracing::info!("controller connected: {}", controller.name());
+                self.controllers
+                    .insert(controller.instance_id(), controller);
+                self.controller.reconnected();
+            }
+            Err(e) => tracing::error!("can't open controller {joystick_index}: {e}"),
+        }
+    }
+
+    // moves rate limited axes towards their targets and feeds them to the drone
+    fn update_axes(&mut self, dt: f32) {
+        for (axis, limiter) in self.axes.iter_mut() {
+            if limiter.settled() {
+                continue;
+            }
+            let Some(binding) = self.input.axis(*axis) else {
+                continue;
+            };
+            let value = self.drone.sensitivity * limiter.step(self.input.curve(*axis).rate, dt);
+            self.drone.set_motion(binding.motion, binding.apply(value));
+        }
+    }
+
+    // held motion keys drive their motions, opposite keys cancel out
+    fn update_keys(&mut self) {
+        let mut motions: Vec<(Motion, f32)> = Vec::new();
+        for key in &self.keys {
+            let Some(KeyBinding::Motion(binding)) = self.input.key(*key) else {
+                continue;
+            };
+            let value = binding.apply(self.drone.sensitivity);
+            match motions.iter_mut().find(|(m, _)| *m == binding.motion) {
+                Some((_, sum)) => *sum += value,
+                None => motions.push((binding.motion, value)),
+            }
+        }
+        for (motion, value) in motions {
+            self.drone.set_motion(motion, value.clamp(-1.0, 1.0));
+        }
+    }
+
+    fn release_key(&mut self, key: Keycode) {
+        if !self.keys.remove(&key) {
+            return;
+        }
+        let Some(KeyBinding::Motion(binding)) = self.input.key(key) else {
+            return;
+        };
+        let still_held = self.keys.iter().any(|k| {
+            matches!(self.input.key(*k), Some(KeyBinding::Motion(b)) if b.motion == binding.motion)
+        });
+        if !still_held {
+            self.drone.set_motion(binding.motion, 0.0);
+        }
+    }
+
+    // returns true when the key was a replay control
+    fn replay_handler(&self, key: Keycode) -> bool {
+        let Some(ref replay) = self.replay else {
+            return false;
+        };
+        match key {
+            Keycode::F5 => replay.toggle_pause(),
+            Keycode::F6 => replay.slower(),
+            Keycode::F7 => replay.faster(),
+            Keycode::F8 => replay.seek(-10.0),
+            Keycode::F9 => replay.seek(10.0),
+            _ => return false,
+        }
+        true
+    }
+
     fn drone_handler(&mut self, event_pump: &mut sdl2::EventPump) -> bool {
         for event in event_pump.poll_iter() {
             match event {
-                Event::ControllerButtonUp { button, .. } => {
-                    tracing::info!("Button {:?} up", button);
-                    match button {
-                        sdl2::controller::Button::A => self.drone.take_picture = true,
-                        sdl2::controller::Button::B => self.drone.toggle_video = true,
-                        sdl2::controller::Button::X => self.drone.img_carousel_toggle_zoom = true,
-
-                        sdl2::controller::Button::Guide => self.drone.hover = true,
-                        sdl2::controller::Button::Start => self.drone.take_off = true,
-
-                        sdl2::controller::Button::LeftShoulder => self.drone.sensitivity_dec(),
-                        sdl2::controller::Button::RightShoulder => self.drone.sensitivity_inc(),
-                        sdl2::controller::Button::DPadLeft => self.drone.img_carousel_left = true,
-                        sdl2::controller::Button::DPadRight => self.drone.img_carousel_right = true,
-                        _ => {}
-                    }
-                }
-
-                Event::ControllerAxisMotion {
-                    axis, value: val, ..
-                } => {
-                    tracing::info!("Axis {:?} moved to {}", axis, val);
-                    match axis {
-                        Axis::LeftX => {
-                            self.drone.slide_right = self.drone.sensitivity * val as f32 / 32767.0
-                        }
-                        Axis::LeftY => {
-                            self.drone.forward = -self.drone.sensitivity * val as f32 / 32767.0
-                        }
-                        Axis::RightX => {
-                            self.drone.turn_clockwise =
-                                self.drone.sensitivity * val as f32 / 32767.0
-                        }
-                        Axis::TriggerRight => {
-                            self.drone.vert_accel = self.drone.sensitivity * val as f32 / 32767.0
-                        }
-                        Axis::TriggerLeft => {
-                            self.drone.vert_decel = self.drone.sensitivity * val as f32 / 32767.0
-                        }
-                        _ => {}
-                    }
-                    // }
-                }
-
-                Event::Quit { .. } => {
-                    return true;
-                }
+                // the command console takes the keyboard while open
                 Event::KeyDown {
-                    keycode: Some(sdl2::keyboard::Keycode::Escape),
-                    ..
-                } => {
-                    return true;
+                    keycode: Some(key), ..
+                } if self.command_line.is_open() => {
+                    self.typed.insert(key);
+                    self.console_key(key);
                 }
 
-                Event::KeyDown {
-                    keycode: Some(Keycode::NUM_1),
-                    ..
-                } => {
-                    self.drone.sensitivity = 0.0;
-                    return false;
+                Event::KeyUp {
+                    keycode: Some(key), ..
+                } if self.command_line.is_open() || self.typed.contains(&key) => {
+                    self.typed.remove(&key);
+                    self.release_key(key);
                 }
 
-                Event::KeyDown {
-                    keycode: Some(Keycode::NUM_2),
-                    ..
-                } => {
-                    self.drone.sensitivity = 0.2;
-                    return false;
+                Event::TextInput { ref text, .. } if self.command_line.is_open() => {
+                    self.command_line.insert(text);
                 }
 
                 Event::KeyDown {
-                    keycode: Some(Keycode::NUM_3),
-                    ..
-                } => {
-                    self.drone.sensitivity = 0.4;
-                    return false;
-                }
+                    keycode: Some(key), ..
+                } if self.replay_handler(key) => {}
 
-                Event::KeyDown {
-                    keycode: Some(Keycode::NUM_4),
-                    ..
-                } => {
-                    self.drone.sensitivity = 0.6;
-                    return false;
-                }
+                Event::ControllerDeviceAdded { which, .. } => self.open_controller(which),
 
-                Event::KeyDown {
-                    keycode: Some(Keycode::NUM_5),
-                    ..
-                } => {
-                    self.drone.sensitivity = 0.8;
-                    return false;
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.controllers.remove(&which);
+                    // another controller is still in charge
+                    if !self.controllers.is_empty() {
+                        tracing::info!("a controller disconnected, another one is connected");
+                        continue;
+                    }
+                    // nothing may keep flying on the last stick values
+                    self.axes.clear();
+                    self.buttons.clear();
+                    self.suppressed.clear();
+                    self.drone.stop_motion();
+                    self.drone.hover = true;
+                    self.controller.lost(Instant::now());
                 }
 
-                Event::KeyDown {
-                    keycode: Some(Keycode::NUM_6),
-                    ..
-                } => {
-                    self.drone.sensitivity = 1.0;
-                    return false;
+                Event::ControllerButtonDown { button, .. } => {
+                    // a chord fires on the press, neither button's own action does
+                    if let Some(action) = self.input.chord(&self.buttons, button) {
+                        self.suppressed.extend(self.buttons.iter().copied());
+                        self.suppressed.insert(button);
+                        if self.drone.apply(action) {
+                            return true;
+                        }
+                    }
+                    self.buttons.insert(button);
+                    // the cancel combo buttons have actions of their own
+                    if self.battery.cancel(&self.buttons) {
+                        self.suppressed.extend(self.buttons.iter().copied());
+                    }
                 }
 
-                Event::KeyDown {
-                    keycode: Some(Keycode::Left),
-                    ..
-                } => {
-                    self.drone.slide_right = -self.drone.sensitivity;
-                    return false;
-                }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Right),
-                    ..
-                } => {
-                    self.drone.slide_right = self.drone.sensitivity;
-                    return false;
+                Event::ControllerButtonUp { button, .. } => {
+                    tracing::info!("Button {:?} up", button);
+                    self.buttons.remove(&button);
+                    if self.suppressed.remove(&button) {
+                        continue;
+                    }
+                    if let Some(action) = self.input.button(button) {
+                        if self.drone.apply(action) {
+                            return true;
+                        }
+                    }
                 }
 
-                Event::KeyDown {
-                    keycode: Some(Keycode::Up),
-                    ..
-                } => {
-                    self.drone.forward = self.drone.sensitivity;
-                    return false;
-                }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Down),
-                    ..
+                Event::ControllerAxisMotion {
+                    axis, value: val, ..
                 } => {
-                    self.drone.forward = -self.drone.sensitivity;
-                    return false;
+                    tracing::info!("Axis {:?} moved to {}", axis, val);
+                    if self.input.axis(axis).is_some() {
+                        self.axes.entry(axis).or_default().target =
+                            self.input.curve(axis).shape(val);
+                    }
                 }
 
-                Event::KeyDown {
-                    keycode: Some(Keycode::A),
-                    ..
-                } => {
-                    self.drone.vert_accel = self.drone.sensitivity;
-                    return false;
-                }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Q),
-                    ..
-                } => {
-                    self.drone.vert_decel = self.drone.sensitivity;
-                    return false;
+                Event::Quit { .. } => {
+                    return true;
                 }
 
-                Event::KeyUp {
-                    keycode: Some(Keycode::Left),
-                    ..
-                } => {
-                    self.drone.slide_right = 0.0;
-                    return false;
-                }
-                Event::KeyUp {
-                    keycode: Some(Keycode::Right),
+                Event::KeyDown {
+                    keycode: Some(key),
+                    repeat: false,
                     ..
-                } => {
-                    self.drone.slide_right = 0.0;
-                    return false;
-                }
+                } => match self.input.key(key) {
+                    Some(KeyBinding::Motion(_)) => {
+                        self.keys.insert(key);
+                    }
+                    Some(KeyBinding::Action(Action::Quit)) => return true,
+                    _ => {}
+                },
 
                 Event::KeyUp {
-                    keycode: Some(Keycode::Up),
-                    ..
-                } => {
-                    self.drone.forward = 0.0;
-                    return false;
-                }
-                Event::KeyUp {
-                    keycode: Some(Keycode::Down),
-                    ..
-                } => {
-                    self.drone.forward = 0.0;
-                    return false;
-                }
+                    keycode: Some(key), ..
+                } => match self.input.key(key) {
+                    Some(KeyBinding::Motion(_)) => self.release_key(key),
+                    Some(KeyBinding::Action(action)) if self.drone.apply(action) => return true,
+                    _ => {}
+                },
 
-                Event::KeyUp {
-                    keycode: Some(Keycode::A),
+                // key ups go to the focused window only, don't keep flying on stuck keys
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
                     ..
                 } => {
-                    self.drone.vert_accel = 0.0;
-                    return false;
-                }
-                Event::KeyUp {
-                    keycode: Some(Keycode::Q),
-                    ..
-                } => {
-                    self.drone.vert_decel = 0.0;
-                    return false;
-                }
-                Event::KeyUp {
-                    keycode: Some(Keycode::V),
-                    ..
-                } => {
-                    self.drone.toggle_video = true;
-                    return false;
-                }
-                Event::KeyUp {
-                    keycode: Some(Keycode::P),
-                    ..
-                } => {
-                    self.drone.take_picture = true;
-                    return false;
-                }
-                Event::KeyUp {
-                    keycode: Some(Keycode::SPACE),
-                    ..
-                } => {
-                    self.drone.take_off = true;
-                    return false;
+                    let held: Vec<Keycode> = self.keys.iter().copied().collect();
+                    for key in held {
+                        self.release_key(key);
+                    }
                 }
                 _ => {}
             }
@@ -1,7 +0,0 @@
-pub(crate) fn make<T>(len: usize) -> Vec<T> {
-    let mut v = Vec::with_capacity(len);
-    unsafe {
-        v.set_len(len);
-    }
-    v
-}
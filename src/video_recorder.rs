@@ -0,0 +1,586 @@
+//! Saves the H.264 stream from the drone.
+//!
+//! Frames are written as they come to `video-<unix secs>-<part>.h264` and, with `mp4` set,
+//! piped through ffmpeg into a fragmented `.mp4` next to it, so a crash leaves a playable file.
+//! A new part is started on the next keyframe once a part grows over `max_part_mb`, recording
+//! stops when the disk has less than `min_free_mb` left. The wall clock of the first and last
+//! frame of each part is saved to a `.json` next to it, for burning in the flight log later; with
+//! `overlay` set a copy with the live telemetry drawn on it is written as `-overlay.mp4`.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    overlay::{LiveOverlay, VideoStart},
+    telemetry::{StickSample, TelemetrySample},
+};
+
+const DISK_CHECK_EVERY: Duration = Duration::from_secs(5);
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VideoConfig {
+    pub dir: PathBuf,
+    /// also mux into MP4 with ffmpeg
+    pub mp4: bool,
+    pub ffmpeg: PathBuf,
+    /// also write a copy with the telemetry burned in, re-encoded by ffmpeg
+    pub overlay: bool,
+    /// font for the overlay, ffmpeg's default when unset
+    pub font: Option<PathBuf>,
+    pub max_part_mb: u64,
+    pub min_free_mb: u64,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("./videos"),
+            mp4: false,
+            ffmpeg: PathBuf::from("ffmpeg"),
+            overlay: false,
+            font: None,
+            max_part_mb: 1024,
+            min_free_mb: 1024,
+        }
+    }
+}
+
+enum Msg {
+    Start,
+    Stop,
+    Frame(Vec<u8>),
+    Overlay(TelemetrySample, StickSample),
+    Shutdown,
+}
+
+#[derive(Debug, Default)]
+struct Status {
+    started: Option<Instant>,
+    error: Option<String>,
+}
+
+/// Cheap handle to feed frames from the video thread.
+#[derive(Clone)]
+pub struct VideoRecorderHandle {
+    tx: Sender<Msg>,
+    recording: Arc<AtomicBool>,
+}
+
+impl VideoRecorderHandle {
+    pub fn frame(&self, frame: &[u8]) {
+        if self.recording.load(Ordering::Relaxed) {
+            let _ = self.tx.send(Msg::Frame(frame.to_vec()));
+        }
+    }
+}
+
+pub struct VideoRecorder {
+    handle: VideoRecorderHandle,
+    overlay: bool,
+    /// recording as asked for, the writer thread catches up later
+    on: bool,
+    status: Arc<Mutex<Status>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl VideoRecorder {
+    pub fn spawn(config: VideoConfig) -> Self {
+        let overlay = config.overlay;
+        let (tx, rx) = mpsc::channel();
+        let recording = Arc::new(AtomicBool::new(false));
+        let status = Arc::new(Mutex::new(Status::default()));
+        let thread = {
+            let recording = recording.clone();
+            let status = status.clone();
+            thread::spawn(move || recorder_thread(rx, config, recording, status))
+        };
+        Self {
+            handle: VideoRecorderHandle { tx, recording },
+            overlay,
+            on: false,
+            status,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn handle(&self) -> VideoRecorderHandle {
+        self.handle.clone()
+    }
+
+    pub fn recording(&self) -> bool {
+        self.handle.recording.load(Ordering::Relaxed)
+    }
+
+    /// True while `overlay` wants the telemetry.
+    pub fn wants_overlay(&self) -> bool {
+        self.overlay && self.recording()
+    }
+
+    pub fn overlay(&self, sample: TelemetrySample, sticks: StickSample) {
+        let _ = self.handle.tx.send(Msg::Overlay(sample, sticks));
+    }
+
+    /// Starts or stops recording. Decided from what was asked for last, the writer's state lags
+    /// behind and two quick toggles would both start.
+    pub fn toggle(&mut self) {
+        let mut status = self.status.lock().unwrap();
+        // a recording the writer gave up on is stopped already
+        if self.on && status.started.is_none() && status.error.is_some() {
+            self.on = false;
+        }
+        self.on = !self.on;
+        let msg = if self.on {
+            status.error = None;
+            Msg::Start
+        } else {
+            Msg::Stop
+        };
+        let _ = self.handle.tx.send(msg);
+    }
+
+    /// HUD line: elapsed time while recording, the reason when recording failed.
+    pub fn status(&self) -> String {
+        let status = self.status.lock().unwrap();
+        match (status.started, &status.error) {
+            (Some(started), _) => {
+                let secs = started.elapsed().as_secs();
+                format!("REC {:02}:{:02}", secs / 60, secs % 60)
+            }
+            (None, Some(error)) => format!("REC stopped: {error}"),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Finishes the current file and waits for the recorder to finish.
+    pub fn close(mut self) {
+        let _ = self.handle.tx.send(Msg::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Passes frames on to the returned receiver, copying them to `recorder` while it records.
+pub fn tee(video_rx: Receiver<Vec<u8>>, recorder: VideoRecorderHandle) -> Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        while let Ok(frame) = video_rx.recv() {
+            recorder.frame(&frame);
+            if tx.send(frame).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+// the decoder can only start on a sequence parameter set
+fn starts_with_sps(frame: &[u8]) -> bool {
+    let nal = match frame {
+        [0, 0, 0, 1, nal, ..] | [0, 0, 1, nal, ..] => nal,
+        _ => return false,
+    };
+    nal & 0x1f == 7
+}
+
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // the field types differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+// an error when `free` bytes are under `min_free_mb`, unknown space passes
+fn disk_check(free: Option<u64>, min_free_mb: u64) -> io::Result<()> {
+    match free {
+        Some(free) if free < min_free_mb * MB => Err(io::Error::other(format!(
+            "disk almost full, {} MB left",
+            free / MB
+        ))),
+        _ => Ok(()),
+    }
+}
+
+struct VideoFile {
+    config: VideoConfig,
+    name: u64,
+    part: u32,
+    raw: Option<BufWriter<File>>,
+    mp4: Option<Child>,
+    overlay: Option<LiveOverlay>,
+    /// latest telemetry for the overlay
+    telemetry: (TelemetrySample, StickSample),
+    written: u64,
+    /// the open part and its start, the end is filled in when it's closed
+    start: Option<(PathBuf, VideoStart)>,
+    last_frame_unix_ms: u64,
+    last_disk_check: Instant,
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+impl VideoFile {
+    fn new(config: &VideoConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let file = Self {
+            config: config.clone(),
+            name: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            part: 0,
+            raw: None,
+            mp4: None,
+            overlay: None,
+            telemetry: Default::default(),
+            written: 0,
+            start: None,
+            last_frame_unix_ms: 0,
+            last_disk_check: Instant::now(),
+        };
+        file.check_disk()?;
+        Ok(file)
+    }
+
+    fn check_disk(&self) -> io::Result<()> {
+        disk_check(free_space(&self.config.dir), self.config.min_free_mb)
+    }
+
+    fn open_part(&mut self) -> io::Result<()> {
+        let stem = self
+            .config
+            .dir
+            .join(format!("video-{}-{:03}", self.name, self.part));
+        let raw = stem.with_extension("h264");
+        tracing::info!("recording video to {}", raw.display());
+        self.raw = Some(BufWriter::new(File::create(&raw)?));
+        self.written = 0;
+        let start = VideoStart {
+            start_unix_ms: unix_ms(),
+            end_unix_ms: None,
+        };
+        start.save(&raw)?;
+        self.start = Some((raw, start));
+        if self.config.mp4 {
+            self.mp4 = spawn_ffmpeg(&self.config.ffmpeg, &stem.with_extension("mp4"))
+                .map_err(|e| tracing::error!("can't start ffmpeg, recording raw video only: {e}"))
+                .ok();
+        }
+        if self.config.overlay {
+            let out = self
+                .config
+                .dir
+                .join(format!("video-{}-{:03}-overlay.mp4", self.name, self.part));
+            self.overlay =
+                LiveOverlay::spawn(&self.config.ffmpeg, self.config.font.as_deref(), &out)
+                    .map_err(|e| tracing::error!("can't start the overlay: {e}"))
+                    .ok();
+            self.update_overlay();
+        }
+        Ok(())
+    }
+
+    fn update_overlay(&mut self) {
+        let Some(ref mut overlay) = self.overlay else {
+            return;
+        };
+        let (sample, sticks) = &self.telemetry;
+        if let Err(e) = overlay.update(sample, sticks) {
+            tracing::error!("can't update the overlay: {e}");
+        }
+    }
+
+    fn close_part(&mut self) {
+        if let Some(mut raw) = self.raw.take() {
+            if let Err(e) = raw.flush() {
+                tracing::error!("can't flush video: {e}");
+            }
+        }
+        if let Some((raw, mut start)) = self.start.take() {
+            start.end_unix_ms = Some(self.last_frame_unix_ms);
+            if let Err(e) = start.save(&raw) {
+                tracing::error!("can't save the video times: {e}");
+            }
+        }
+        self.close_mp4();
+        if let Some(overlay) = self.overlay.take() {
+            overlay.finish();
+        }
+    }
+
+    fn close_mp4(&mut self) {
+        if let Some(mut mp4) = self.mp4.take() {
+            // closing stdin lets ffmpeg finish the file
+            drop(mp4.stdin.take());
+            match mp4.wait() {
+                Ok(status) if !status.success() => tracing::error!("ffmpeg failed: {status}"),
+                Err(e) => tracing::error!("ffmpeg failed: {e}"),
+                Ok(_) => {}
+            }
+        }
+    }
+
+    fn write(&mut self, frame: &[u8]) -> io::Result<()> {
+        let keyframe = starts_with_sps(frame);
+        if self.raw.is_none() {
+            if !keyframe {
+                return Ok(());
+            }
+            self.open_part()?;
+        } else if keyframe && self.written > self.config.max_part_mb * MB {
+            self.close_part();
+            self.part += 1;
+            self.open_part()?;
+        }
+        if self.last_disk_check.elapsed() > DISK_CHECK_EVERY {
+            self.last_disk_check = Instant::now();
+            self.check_disk()?;
+        }
+        if let Some(ref mut raw) = self.raw {
+            raw.write_all(frame)?;
+            self.written += frame.len() as u64;
+            self.last_frame_unix_ms = unix_ms();
+        }
+        let mp4_failed = match self.mp4.as_mut().and_then(|c| c.stdin.as_mut()) {
+            Some(stdin) => stdin.write_all(frame).is_err(),
+            None => false,
+        };
+        if mp4_failed {
+            tracing::error!("ffmpeg went away, recording raw video only");
+            self.close_mp4();
+        }
+        let overlay_failed = self
+            .overlay
+            .as_mut()
+            .is_some_and(|overlay| overlay.frame(frame).is_err());
+        if overlay_failed {
+            tracing::error!("ffmpeg overlay went away");
+            if let Some(overlay) = self.overlay.take() {
+                overlay.finish();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn spawn_ffmpeg(ffmpeg: &Path, out: &Path) -> io::Result<Child> {
+    Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        // frames arrive in real time, their arrival is the timestamp
+        .args([
+            "-use_wallclock_as_timestamps",
+            "1",
+            "-f",
+            "h264",
+            "-i",
+            "pipe:0",
+        ])
+        .args(["-c", "copy", "-movflags", "+frag_keyframe+empty_moov"])
+        .arg(out)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+}
+
+fn recorder_thread(
+    rx: Receiver<Msg>,
+    config: VideoConfig,
+    recording: Arc<AtomicBool>,
+    status: Arc<Mutex<Status>>,
+) {
+    let mut file: Option<VideoFile> = None;
+    let stop = |file: &mut Option<VideoFile>, error: Option<String>| {
+        recording.store(false, Ordering::Relaxed);
+        if let Some(mut f) = file.take() {
+            f.close_part();
+            tracing::info!("video recording stopped");
+        }
+        let mut status = status.lock().unwrap();
+        status.started = None;
+        status.error = error;
+    };
+    while let Ok(msg) = rx.recv() {
+        match msg {
+            Msg::Start => {
+                stop(&mut file, None);
+                match VideoFile::new(&config) {
+                    Ok(f) => {
+                        file = Some(f);
+                        recording.store(true, Ordering::Relaxed);
+                        status.lock().unwrap().started = Some(Instant::now());
+                    }
+                    Err(e) => {
+                        tracing::error!("can't record video to {}: {e}", config.dir.display());
+                        stop(&mut file, Some(e.to_string()));
+                    }
+                }
+            }
+            Msg::Stop => stop(&mut file, None),
+            Msg::Frame(frame) => {
+                if let Some(f) = file.as_mut() {
+                    if let Err(e) = f.write(&frame) {
+                        tracing::error!("video recording stopped: {e}");
+                        stop(&mut file, Some(e.to_string()));
+                    }
+                }
+            }
+            Msg::Overlay(sample, sticks) => {
+                if let Some(f) = file.as_mut() {
+                    f.telemetry = (sample, sticks);
+                    f.update_overlay();
+                }
+            }
+            Msg::Shutdown => break,
+        }
+    }
+    stop(&mut file, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPS: [u8; 8] = [0, 0, 0, 1, 0x67, 1, 2, 3];
+    const SLICE: [u8; 8] = [0, 0, 0, 1, 0x41, 4, 5, 6];
+
+    fn config(name: &str) -> VideoConfig {
+        let dir = std::env::temp_dir().join(format!("tello-video-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        VideoConfig {
+            dir,
+            min_free_mb: 0,
+            ..Default::default()
+        }
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn keyframes() {
+        assert!(starts_with_sps(&SPS));
+        assert!(starts_with_sps(&[0, 0, 1, 0x27, 9]));
+        assert!(!starts_with_sps(&SLICE));
+        // the picture parameter set alone isn't one
+        assert!(!starts_with_sps(&[0, 0, 0, 1, 0x68]));
+        assert!(!starts_with_sps(&[0, 0, 0, 2, 0x67]));
+        assert!(!starts_with_sps(&[0, 0, 0]));
+    }
+
+    #[test]
+    fn free_space_threshold() {
+        assert!(disk_check(None, 1024).is_ok());
+        assert!(disk_check(Some(1024 * MB), 1024).is_ok());
+        let error = disk_check(Some(1024 * MB - 1), 1024).unwrap_err();
+        assert_eq!(error.to_string(), "disk almost full, 1023 MB left");
+        assert!(disk_check(Some(0), 0).is_ok());
+    }
+
+    #[test]
+    fn full_disk_refuses_to_start() {
+        let mut config = config("full");
+        // more than any disk
+        config.min_free_mb = 1 << 40;
+        assert!(VideoFile::new(&config).is_err());
+    }
+
+    #[test]
+    fn parts_start_on_keyframes() {
+        let config = VideoConfig {
+            max_part_mb: 0,
+            ..config("parts")
+        };
+        let mut file = VideoFile::new(&config).unwrap();
+        file.name = 42;
+        // nothing to decode before the first keyframe
+        file.write(&SLICE).unwrap();
+        assert!(files(&config.dir).is_empty());
+        file.write(&SPS).unwrap();
+        file.write(&SLICE).unwrap();
+        // over the size, but the next part waits for a keyframe
+        file.write(&SLICE).unwrap();
+        assert_eq!(
+            files(&config.dir),
+            ["video-42-000.h264", "video-42-000.json"]
+        );
+        file.write(&SPS).unwrap();
+        file.write(&SLICE).unwrap();
+        file.close_part();
+        assert_eq!(
+            files(&config.dir),
+            [
+                "video-42-000.h264",
+                "video-42-000.json",
+                "video-42-001.h264",
+                "video-42-001.json"
+            ]
+        );
+        let first = fs::read(config.dir.join("video-42-000.h264")).unwrap();
+        assert_eq!(first, [&SPS[..], &SLICE, &SLICE].concat());
+        let second = fs::read(config.dir.join("video-42-001.h264")).unwrap();
+        assert_eq!(second, [SPS, SLICE].concat());
+    }
+
+    #[test]
+    fn start_time_sidecar() {
+        let config = config("start");
+        let mut file = VideoFile::new(&config).unwrap();
+        let before = unix_ms();
+        file.write(&SPS).unwrap();
+        file.write(&SLICE).unwrap();
+        file.close_part();
+        let video = config.dir.join(format!("video-{}-000.h264", file.name));
+        let start = VideoStart::load(&video).unwrap();
+        assert!(start.start_unix_ms >= before);
+        assert!(start.start_unix_ms - before < 5000);
+        let end = start.end_unix_ms.unwrap();
+        assert!(end >= start.start_unix_ms);
+        let json = fs::read_to_string(VideoStart::sidecar(&video)).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                "{{\"start_unix_ms\":{},\"end_unix_ms\":{end}}}",
+                start.start_unix_ms
+            )
+        );
+        assert!(VideoStart::load(&config.dir.join("missing.h264")).is_err());
+    }
+}
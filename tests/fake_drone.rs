@@ -0,0 +1,301 @@
+use std::{
+    net::UdpSocket,
+    sync::mpsc::RecvTimeoutError,
+    time::{Duration, Instant},
+};
+
+use rust_tello_controller::fake_drone::{
+    self, FakeDrone, FakeDroneConfig, FakeStatus, Received, FLIGHT_MSG, LAND_CMD, STICK_CMD,
+    TAKEOFF_CMD, WIFI_MSG,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+fn client(drone: &FakeDrone) -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(TIMEOUT)).unwrap();
+    socket.connect(drone.local_addr()).unwrap();
+    socket
+}
+
+fn connect(socket: &UdpSocket, video_port: u16) {
+    let mut req = fake_drone::CONN_REQ.to_vec();
+    req.extend(video_port.to_le_bytes());
+    socket.send(&req).unwrap();
+    let mut buf = [0u8; 64];
+    let n = socket.recv(&mut buf).unwrap();
+    let mut ack = fake_drone::CONN_ACK.to_vec();
+    ack.extend(video_port.to_le_bytes());
+    assert_eq!(&buf[..n], &ack[..]);
+}
+
+fn wait_for_packet(socket: &UdpSocket, cmd: u16) -> fake_drone::Packet {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut buf = [0u8; 2048];
+    while Instant::now() < deadline {
+        let n = socket.recv(&mut buf).unwrap();
+        if let Some(p) = fake_drone::parse(&buf[..n]) {
+            if p.cmd == cmd {
+                return p;
+            }
+        }
+    }
+    panic!("no packet {cmd:#06x} from the fake drone");
+}
+
+#[test]
+fn handshake() {
+    let drone = FakeDrone::start("127.0.0.1:0", FakeDroneConfig::default()).unwrap();
+    let socket = client(&drone);
+    connect(&socket, 6038);
+    assert!(drone.connected());
+    assert_eq!(
+        drone.received(),
+        vec![Received::ConnReq { video_port: 6038 }]
+    );
+}
+
+#[test]
+fn takeoff_and_land_are_acknowledged_and_recorded() {
+    let drone = FakeDrone::start("127.0.0.1:0", FakeDroneConfig::default()).unwrap();
+    let socket = client(&drone);
+    connect(&socket, 6038);
+
+    socket
+        .send(&fake_drone::packet(TAKEOFF_CMD, 0x68, 1, &[]))
+        .unwrap();
+    let ack = wait_for_packet(&socket, TAKEOFF_CMD);
+    assert_eq!(ack.seq, 1);
+    assert!(drone.flying());
+
+    socket
+        .send(&fake_drone::packet(LAND_CMD, 0x68, 2, &[0]))
+        .unwrap();
+    wait_for_packet(&socket, LAND_CMD);
+    assert!(!drone.flying());
+
+    let cmds: Vec<u16> = drone.commands().iter().map(|p| p.cmd).collect();
+    assert_eq!(cmds, vec![TAKEOFF_CMD, LAND_CMD]);
+}
+
+#[test]
+fn status_packets() {
+    let status = FakeStatus {
+        height: 12,
+        battery_percentage: 55,
+        ..Default::default()
+    };
+    let drone = FakeDrone::start(
+        "127.0.0.1:0",
+        FakeDroneConfig {
+            status,
+            video: None,
+        },
+    )
+    .unwrap();
+    let socket = client(&drone);
+    connect(&socket, 6038);
+
+    let flight = wait_for_packet(&socket, FLIGHT_MSG);
+    assert_eq!(
+        i16::from_le_bytes([flight.payload[0], flight.payload[1]]),
+        12
+    );
+    assert_eq!(flight.payload[12], 55);
+
+    let wifi = wait_for_packet(&socket, WIFI_MSG);
+    assert_eq!(wifi.payload[0], status.wifi_strength);
+}
+
+#[test]
+fn video_goes_to_the_video_port() {
+    let video = UdpSocket::bind("127.0.0.1:0").unwrap();
+    video.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let video_port = video.local_addr().unwrap().port();
+    let clip = vec![0, 0, 0, 1, 0x67, 1, 2, 3, 0, 0, 0, 1, 0x68, 4, 5];
+    let drone = FakeDrone::start(
+        "127.0.0.1:0",
+        FakeDroneConfig {
+            status: FakeStatus::default(),
+            video: Some(clip.clone()),
+        },
+    )
+    .unwrap();
+    let socket = client(&drone);
+    connect(&socket, video_port);
+
+    let mut buf = [0u8; 2048];
+    let n = video.recv(&mut buf).unwrap();
+    assert_eq!(&buf[2..n], &clip[..]);
+}
+
+#[test]
+fn corrupted_packets_are_not_commands() {
+    let drone = FakeDrone::start("127.0.0.1:0", FakeDroneConfig::default()).unwrap();
+    let socket = client(&drone);
+    connect(&socket, 6038);
+    let mut pkt = fake_drone::packet(TAKEOFF_CMD, 0x68, 1, &[]);
+    let last = pkt.len() - 1;
+    pkt[last] ^= 0xff;
+    socket.send(&pkt).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(drone.commands().is_empty());
+    assert!(matches!(
+        drone.received().last(),
+        Some(Received::Garbage(_))
+    ));
+}
+
+/// Where `TelloController` sends to.
+const TELLO_ADDR: &str = "192.168.10.1:8889";
+/// Set when the test runs again in a network namespace of its own.
+const NETNS_ENV: &str = "FAKE_DRONE_NETNS";
+
+/// `TelloController` only talks to 192.168.10.1. When the address isn't local the test runs
+/// again in a user and network namespace with the address on its loopback, no root needed.
+/// Returns false when this run is done: the namespaced run has taken over, or the namespace
+/// can't be made here and the test is skipped.
+fn drone_address_is_local() -> bool {
+    if UdpSocket::bind("192.168.10.1:0").is_ok() {
+        return true;
+    }
+    assert!(
+        std::env::var_os(NETNS_ENV).is_none(),
+        "192.168.10.1 missing in the namespace"
+    );
+    let skip = |why: &str| {
+        eprintln!(
+            "skipped: {why}; add 192.168.10.1 to the loopback interface to run \
+             tello_controller_against_fake_drone"
+        );
+        false
+    };
+    if !cfg!(target_os = "linux") {
+        return skip("192.168.10.1 isn't local");
+    }
+    // the setup failing exits with 77, the test harness with 101 on a failed test
+    let status = std::process::Command::new("unshare")
+        .args(["--user", "--map-root-user", "--net", "sh", "-c"])
+        .arg(
+            r#"ip link set lo up && ip addr add 192.168.10.1/32 dev lo || exit 77; exec "$0" "$@""#,
+        )
+        .arg(std::env::current_exe().unwrap())
+        .args([
+            "--exact",
+            "tello_controller_against_fake_drone",
+            "--nocapture",
+        ])
+        .env(NETNS_ENV, "1")
+        .stderr(std::process::Stdio::inherit())
+        .status();
+    match status {
+        Ok(status) if status.success() => false,
+        Ok(status) if status.code() == Some(101) => panic!("namespaced run failed"),
+        Ok(status) => skip(&format!("no network namespace for 192.168.10.1 ({status})")),
+        Err(e) => skip(&format!("can't run unshare: {e}")),
+    }
+}
+
+/// `TelloController` set up like in src/main.rs against the fake drone: telemetry and video
+/// reach the channels, and the launch the default Start binding asks for is sent and received.
+/// The flight state and the launcher are driven by hand here, the gating in `UI::mainloop`
+/// isn't run.
+#[test]
+fn tello_controller_against_fake_drone() {
+    use rust_tello::TelloController;
+    use rust_tello_controller::{
+        drone::Drone,
+        flight::{Observation, Request, StateMachine},
+        input::{Action, InputProfile},
+        launch::{Launch, Launcher},
+    };
+    use sdl2::controller::Button;
+    use std::sync::mpsc;
+
+    if !drone_address_is_local() {
+        return;
+    }
+    let drone = FakeDrone::start(
+        TELLO_ADDR,
+        FakeDroneConfig {
+            status: FakeStatus {
+                height: 7,
+                ..Default::default()
+            },
+            video: Some(vec![0, 0, 0, 1, 0x67, 1, 2, 3]),
+        },
+    )
+    .unwrap();
+
+    let mut tello = TelloController::new();
+    let (update_tx, update_rx) = rust_tello::comm_channel();
+    let (video_tx, video_rx) = mpsc::channel();
+    let _h = tello.start_ctrl_receiver(update_tx);
+    tello.start_video_receiver(video_tx);
+    tello.start_video_contoller();
+    tello.connect();
+    tello.start_stick_update();
+
+    let deadline = Instant::now() + TIMEOUT;
+    while !drone.connected() {
+        assert!(Instant::now() < deadline, "controller never connected");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // telemetry from the fake drone reaches the UI channel
+    let deadline = Instant::now() + TIMEOUT;
+    let height = loop {
+        let update = match update_rx.recv_timeout(TIMEOUT) {
+            Ok(update) => update,
+            Err(RecvTimeoutError::Timeout) => panic!("no telemetry"),
+            Err(e) => panic!("{e}"),
+        };
+        if let Some(flight) = update.flight {
+            break flight.height as i32;
+        }
+        assert!(Instant::now() < deadline, "no flight data");
+    };
+    assert_eq!(height, 7);
+    assert!(video_rx.recv_timeout(TIMEOUT).is_ok(), "no video");
+
+    // Start goes through the flight state and the launcher like in the UI
+    let profile = InputProfile::default();
+    assert_eq!(profile.button(Button::Start), Some(Action::TakeOff));
+    let mut flight = StateMachine::default();
+    let ready = Observation {
+        link: true,
+        ready: true,
+        sticks_idle: true,
+        ..Default::default()
+    };
+    flight.update(&ready, Instant::now());
+    flight.update(&ready, Instant::now());
+    assert!(flight.request(Request::TakeOff, Instant::now()));
+    let mut launcher = Launcher::default();
+    let launch = launcher.request(false, Instant::now());
+    assert_eq!(launch, Some(Launch::TakeOff));
+    launch.unwrap().send(&mut tello);
+    assert!(drone.wait_for(TAKEOFF_CMD, TIMEOUT).is_some());
+
+    Drone::forward(&mut tello, 0.5);
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let forward = drone
+            .commands()
+            .iter()
+            .filter(|p| p.cmd == STICK_CMD)
+            .filter_map(|p| fake_drone::decode_sticks(&p.payload))
+            .any(|s| s.pitch > 0.4);
+        if forward {
+            break;
+        }
+        assert!(Instant::now() < deadline, "no forward stick command");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    assert!(flight.allows(Request::Land));
+    let launch = launcher.request(true, Instant::now());
+    assert_eq!(launch, Some(Launch::Land));
+    launch.unwrap().send(&mut tello);
+    assert!(drone.wait_for(LAND_CMD, TIMEOUT).is_some());
+}